@@ -0,0 +1,118 @@
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use rusterizer::camera::{Camera, PitchMode};
+use rusterizer::geometry::{EdgeSetup, point_in_triangle, subdivide_count, vertex_to_screen};
+use rusterizer::material::Material;
+use rusterizer::obj::Vertex;
+use rusterizer::point2d::Point2D;
+use rusterizer::point3d::Point3D;
+use rusterizer::render::{Model, Rasterizer};
+use rusterizer::shading::{Fog, shade_pixel};
+use rusterizer::texture::Texture;
+use rusterizer::transform::Transform;
+use rusterizer::triangle::Triangle3D;
+
+fn checkerboard_texture(size: u32) -> Texture {
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let c = if (x / 8 + y / 8) % 2 == 0 { 255 } else { 0 };
+            rgba.extend_from_slice(&[c, c, c, 255]);
+        }
+    }
+    Texture { width: size, height: size, rgba }
+}
+
+// Covered pixels of a 1000x700 triangle, once recomputing every edge per pixel and once stepping them like the raster loop
+fn bench_large_triangle(c: &mut Criterion) {
+    let a = Point2D { x: 10.0, y: 10.0 };
+    let b = Point2D { x: 200.0, y: 710.0 };
+    let t = Point2D { x: 1010.0, y: 40.0 };
+    let setup = EdgeSetup::new(a, b, t);
+    c.bench_function("large_triangle_recompute", |bench| {
+        bench.iter(|| {
+            let mut weights = Point3D { x: 0.0, y: 0.0, z: 0.0 };
+            let mut covered = 0u32;
+            for y in 10..710 {
+                for x in 10..1010 {
+                    let p = Point2D { x: x as f32 + 0.5, y: y as f32 + 0.5 };
+                    covered += point_in_triangle(a, b, t, black_box(p), setup.area, setup.inv_area, &mut weights) as u32;
+                }
+            }
+            covered
+        })
+    });
+    c.bench_function("large_triangle_stepped", |bench| {
+        bench.iter(|| {
+            let EdgeSetup { ab, bc, ca, .. } = black_box(setup);
+            let mut covered = 0u32;
+            for y in 10..710 {
+                let row_start = Point2D { x: 10.5, y: y as f32 + 0.5 };
+                let (mut row_ab, mut row_bc, mut row_ca) = (ab.eval(row_start), bc.eval(row_start), ca.eval(row_start));
+                for _ in 10..1010 {
+                    covered += (row_ab >= 0.0 && row_bc >= 0.0 && row_ca >= 0.0) as u32;
+                    row_ab += ab.step_x;
+                    row_bc += bc.step_x;
+                    row_ca += ca.step_x;
+                }
+            }
+            covered
+        })
+    });
+
+    // The whole pipeline on a triangle filling most of a 1280x720 screen
+    let rasterizer = Rasterizer::new(1280, 720, subdivide_count(1280, 720, 16));
+    let vertex = |x: f32, y: f32| Vertex {
+        position: Point3D { x, y, z: 10.0 },
+        texcoord: Point2D { x: 0.0, y: 0.0 },
+        normal: Point3D { x: 0.0, y: 0.0, z: -1.0 },
+        alpha: 1.0,
+    };
+    let model = Model::new(
+        vec![Triangle3D::from_vertices(vertex(-15.0, -9.0), vertex(-15.0, 9.0), vertex(15.0, -9.0), 0)],
+        vec![Material::default()],
+        vec![checkerboard_texture(256).into()],
+    );
+    let identity = Transform { yaw: 0.0, pitch: 0.0, posistion: Point3D { x: 0.0, y: 0.0, z: 0.0 } };
+    let cam = Camera { fov: 90.0_f32.to_radians(), camera_speed: 1.0, mouse_sensitivity: 0.002, transform: identity, pitch_mode: PitchMode::default() };
+    c.bench_function("draw_large_triangle", |bench| {
+        bench.iter(|| {
+            rasterizer.screen.clear(0, 0, 0, 255);
+            rasterizer.draw_instanced(&model, &[identity], &cam, Point3D { x: 0.0, y: 0.0, z: -1.0 }, None)
+        })
+    });
+}
+
+fn bench_sample(c: &mut Criterion) {
+    let texture = checkerboard_texture(1024);
+    c.bench_function("texture_sample", |bench| {
+        bench.iter(|| texture.sample(black_box(0.37), black_box(0.61)))
+    });
+}
+
+fn bench_shade_pixel(c: &mut Criterion) {
+    let normal = Point3D { x: 0.3, y: 0.8, z: -0.5 };
+    let light = Point3D { x: -1.0, y: 0.0, z: 0.0 };
+    let ambient = Point3D { x: 0.1, y: 0.1, z: 0.1 };
+    let fog = Fog { color: (140, 150, 165), start: 250.0, end: 500.0 };
+    c.bench_function("shade_pixel", |bench| {
+        bench.iter(|| shade_pixel(black_box((200, 150, 100, 255)), black_box(normal), light, ambient, 300.0, None))
+    });
+    c.bench_function("shade_pixel_fog", |bench| {
+        bench.iter(|| shade_pixel(black_box((200, 150, 100, 255)), black_box(normal), light, ambient, 300.0, Some(&fog)))
+    });
+}
+
+fn bench_vertex_to_screen(c: &mut Criterion) {
+    let transform = Transform { yaw: 90.0_f32.to_radians(), pitch: 180.0_f32.to_radians(), posistion: Point3D { x: 0.0, y: 55.0, z: 300.0 } };
+    let cam = Camera { fov: 30.0_f32.to_radians(), camera_speed: 1.0, mouse_sensitivity: 0.002, transform: Transform { yaw: 0.0, pitch: 0.0, posistion: Point3D { x: 0.0, y: 0.0, z: 0.0 } }, pitch_mode: PitchMode::default() };
+    let resolution = Point2D { x: 1920.0, y: 1080.0 };
+    let scaled_inv_world_height = cam.scaled_inv_world_height(resolution.y);
+    c.bench_function("vertex_to_screen", |bench| {
+        bench.iter(|| vertex_to_screen(black_box(Point3D { x: 12.0, y: -4.0, z: 7.5 }), &transform, &cam, resolution, scaled_inv_world_height))
+    });
+}
+
+criterion_group!(benches, bench_large_triangle, bench_sample, bench_shade_pixel, bench_vertex_to_screen);
+criterion_main!(benches);
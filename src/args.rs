@@ -0,0 +1,143 @@
+use anyhow::{Result, bail};
+
+use crate::obj::RecomputeNormals;
+
+/// Command line options
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Args {
+    // Load the model shifted to its centroid and move it back in the object transform, for large-coordinate meshes
+    pub recenter: bool,
+    // Use 1 - v for texcoords, for exporters whose V axis is the other way up
+    pub flip_v: bool,
+    // Flip model faces wound against their vertex normals
+    pub fix_winding: bool,
+    // The model was exported Z-up
+    pub z_up: bool,
+    // Rebuild the model's normals from its faces on load
+    pub recompute_normals: RecomputeNormals,
+    // Split triangles larger than this world-space area on load, for meshes with a few enormous triangles
+    pub split_triangles: Option<f32>,
+    // Write rectangles.png and performance_metrics.png
+    pub debug_output: bool,
+    // OBJ with the same topology as the model to blend toward over time
+    pub morph_target: Option<String>,
+    // Sidecar of per-vertex bone weights, skins the model onto the built-in two bone animation
+    pub bone_weights: Option<String>,
+    // Render a turntable to numbered PNGs in this directory instead of opening a window
+    pub render_sequence: Option<String>,
+    // Length and frame rate of the rendered sequence, None uses the defaults
+    pub sequence_seconds: Option<f32>,
+    pub sequence_fps: Option<f32>,
+    // Turn the model around its vertical axis at this many degrees per second, overrides the scene's spin
+    pub spin: Option<f32>,
+    // Adapt the tile count while running to hold this frame rate, None keeps the startup tiling
+    pub adaptive_tiles: Option<f32>,
+    // JSON scene file, None uses the built-in scene
+    pub scene: Option<String>,
+    // Screenshots make pixels near this color transparent, for renders over a solid backdrop
+    pub color_key: Option<(u8, u8, u8)>,
+}
+
+impl Args {
+    /// Parse the arguments after the program name
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--recenter" => parsed.recenter = true,
+                "--flip-v" => parsed.flip_v = true,
+                "--fix-winding" => parsed.fix_winding = true,
+                "--z-up" => parsed.z_up = true,
+                "--recompute-normals" => parsed.recompute_normals = match args.next().as_deref() {
+                    Some("smooth") => RecomputeNormals::Smooth,
+                    Some("groups") => RecomputeNormals::SmoothingGroups,
+                    _ => bail!("--recompute-normals needs smooth or groups"),
+                },
+                "--split-triangles" => parsed.split_triangles = Some(positive(&arg, args.next())?),
+                "--debug-output" => parsed.debug_output = true,
+                "--morph-target" => match args.next() {
+                    Some(path) => parsed.morph_target = Some(path),
+                    None => bail!("--morph-target needs an OBJ path"),
+                },
+                "--bone-weights" => match args.next() {
+                    Some(path) => parsed.bone_weights = Some(path),
+                    None => bail!("--bone-weights needs a weights file path"),
+                },
+                "--render-sequence" => match args.next() {
+                    Some(dir) => parsed.render_sequence = Some(dir),
+                    None => bail!("--render-sequence needs an output directory"),
+                },
+                "--scene" => match args.next() {
+                    Some(path) => parsed.scene = Some(path),
+                    None => bail!("--scene needs a scene file path"),
+                },
+                "--sequence-seconds" => parsed.sequence_seconds = Some(positive(&arg, args.next())?),
+                "--sequence-fps" => parsed.sequence_fps = Some(positive(&arg, args.next())?),
+                "--adaptive-tiles" => parsed.adaptive_tiles = Some(positive(&arg, args.next())?),
+                "--spin" => parsed.spin = Some(finite(&arg, args.next())?),
+                "--color-key" => parsed.color_key = Some(rgb(&arg, args.next())?),
+                _ => bail!("Unknown argument {arg}"),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+// Value of an option that takes a positive number
+fn positive(option: &str, value: Option<String>) -> Result<f32> {
+    let Some(value) = value else { bail!("{option} needs a number") };
+    match value.parse::<f32>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+        _ => bail!("{option} needs a positive number, got {value}"),
+    }
+}
+
+// Value of an option that takes any finite number
+fn finite(option: &str, value: Option<String>) -> Result<f32> {
+    let Some(value) = value else { bail!("{option} needs a number") };
+    match value.parse::<f32>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => bail!("{option} needs a number, got {value}"),
+    }
+}
+
+// Value of an option that takes an r,g,b color in 0-255
+fn rgb(option: &str, value: Option<String>) -> Result<(u8, u8, u8)> {
+    let Some(value) = value else { bail!("{option} needs a color") };
+    let channels: Result<Vec<u8>, _> = value.split(',').map(|c| c.trim().parse::<u8>()).collect();
+    match channels.as_deref() {
+        Ok(&[r, g, b]) => Ok((r, g, b)),
+        _ => bail!("{option} needs a color as r,g,b from 0 to 255, got {value}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn color_key_takes_three_byte_channels() {
+        assert_eq!(parse(&["--color-key", "0,255,0"]).unwrap().color_key, Some((0, 255, 0)));
+        assert_eq!(parse(&[]).unwrap().color_key, None);
+        for bad in ["0,256,0", "0,255", "0,255,0,0", "green"] {
+            assert!(parse(&["--color-key", bad]).is_err(), "{bad}");
+        }
+        assert!(parse(&["--color-key"]).is_err());
+    }
+
+    #[test]
+    fn spin_takes_signed_degrees_per_second() {
+        assert_eq!(parse(&["--spin", "-45.5"]).unwrap().spin, Some(-45.5));
+        // Unset falls back to the scene's spin instead of stopping it
+        assert_eq!(parse(&[]).unwrap().spin, None);
+        for bad in ["NaN", "inf", "fast"] {
+            assert!(parse(&["--spin", bad]).is_err(), "{bad}");
+        }
+        assert!(parse(&["--spin"]).is_err());
+    }
+}
@@ -1,23 +1,97 @@
 use raylib::prelude::*;
+use crate::geometry::reconstruct_view_pos;
+use crate::point2d::Point2D;
+use crate::point3d::{self, Point3D};
 use crate::transform::Transform;
 
+// FOV limits, tan(fov / 2) stays within roughly [0.0087, 11.4] so the projection scale is always finite and non-zero
+pub const MIN_FOV: f32 = std::f32::consts::PI / 180.0;
+pub const MAX_FOV: f32 = 170.0 * std::f32::consts::PI / 180.0;
+
+/// How far mouse look may pitch the camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PitchMode {
+    // Pitch limited to +-this many radians, below 90 degrees the camera can never flip upside-down
+    Clamped(f32),
+    // Unlimited pitch for flying and inspection, wrapped to [-pi, pi)
+    Free,
+}
+
+impl Default for PitchMode {
+    fn default() -> Self {
+        PitchMode::Clamped(85.0f32.to_radians())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub struct Camera {
     pub fov: f32,
     pub camera_speed: f32,
     pub mouse_sensitivity: f32,
     pub transform: Transform,
+    pub pitch_mode: PitchMode,
 }
 
 impl Camera {
-    pub fn camera_update(&mut self, r1: &RaylibHandle) {
+    // Mouse look and zoom, applied once per rendered frame since the mouse delta is per frame
+    pub fn update_look(&mut self, r1: &RaylibHandle) {
         let mouse_delta = r1.get_mouse_delta();
         // Update yaw & pitch if clicking
         if r1.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT) {
-            self.transform.yaw   -= mouse_delta.x * self.mouse_sensitivity;
-            self.transform.pitch += mouse_delta.y * self.mouse_sensitivity;
-            // Clamp pitch so camera can't flip upside-down
-            self.transform.pitch = self.transform.pitch.clamp(-85.0f32.to_radians(), 85.0f32.to_radians());
+            self.rotate(mouse_delta.x * self.mouse_sensitivity, mouse_delta.y * self.mouse_sensitivity);
+        }
+        self.fov -= r1.get_mouse_wheel_move()/100.0;
+        // Clamp fov so camera can't flip inside-out
+        self.fov = self.fov.clamp(MIN_FOV, MAX_FOV);
+    }
+
+    /// Apply a look rotation in radians, pitch is limited per `pitch_mode`
+    pub fn rotate(&mut self, yaw_delta: f32, pitch_delta: f32) {
+        match self.pitch_mode {
+            PitchMode::Clamped(limit) => {
+                self.transform.yaw -= yaw_delta;
+                self.transform.pitch = (self.transform.pitch + pitch_delta).clamp(-limit, limit);
+            }
+            PitchMode::Free => {
+                // Past vertical the view is upside-down, so yaw turns the other way on screen. Flipping it keeps
+                // horizontal mouse motion turning the view the way it looks like it should instead of the flip at the pole
+                let upside_down = self.transform.pitch.cos() < 0.0;
+                self.transform.yaw += if upside_down { yaw_delta } else { -yaw_delta };
+                self.transform.pitch = (self.transform.pitch + pitch_delta + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI;
+            }
+        }
+    }
+
+    /// Move the camera to `eye` and aim its forward axis at `target`, the transform has no roll so `up` only matters for spotting a degenerate direction
+    pub fn look_at(&mut self, eye: Point3D, target: Point3D, up: Point3D) {
+        self.transform.posistion = eye;
+        let to_target = target - eye;
+        if point3d::dot3(to_target, to_target) <= f32::EPSILON {
+            return;
+        }
+        // Forward basis vector is (-sin(yaw)cos(pitch), sin(pitch), cos(yaw)cos(pitch))
+        let forward = point3d::normalize(to_target);
+        self.transform.pitch = forward.y.clamp(-1.0, 1.0).asin();
+        // Looking straight along up leaves yaw undefined, keep the current heading
+        if point3d::dot3(forward, point3d::normalize(up)).abs() < 1.0 - 1e-6 {
+            self.transform.yaw = (-forward.x).atan2(forward.z);
         }
+    }
+
+    /// Pixels per world unit at depth 1, the fov is clamped again here since it's a pub field that can be set past the limits
+    pub fn scaled_inv_world_height(&self, resolution_y: f32) -> f32 {
+        let world_height = (self.fov.clamp(MIN_FOV, MAX_FOV) * 0.5).tan() * 2.0;
+        resolution_y / world_height
+    }
+
+    /// World-space ray from the camera through a screen position, returned as (origin, unit direction)
+    pub fn pixel_ray(&self, x: f32, y: f32, resolution: Point2D) -> (Point3D, Point3D) {
+        let view_dir = reconstruct_view_pos(x, y, 1.0, resolution, self.scaled_inv_world_height(resolution.y));
+        (self.transform.posistion, point3d::normalize(self.transform.transform_direction(view_dir)))
+    }
+
+    // Keyboard movement, applied once per fixed simulation step
+    pub fn update_movement(&mut self, r1: &RaylibHandle) {
         let (right, _up, forward) = self.transform.get_basis_vectors();
 
         if r1.is_key_down(KeyboardKey::KEY_W) {self.transform.posistion += forward}
@@ -26,8 +100,78 @@ impl Camera {
         if r1.is_key_down(KeyboardKey::KEY_D) {self.transform.posistion += right}
         if r1.is_key_down(KeyboardKey::KEY_SPACE) {self.transform.posistion.y -= self.camera_speed}
         if r1.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {self.transform.posistion.y += self.camera_speed}
-        self.fov -= r1.get_mouse_wheel_move()/100.0;
-        // Clamp fov so camera can't flip inside-out
-        self.fov = self.fov.clamp(1.0_f32.to_radians(), 170.0_f32.to_radians());
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(fov: f32) -> Camera {
+        Camera {
+            fov,
+            camera_speed: 1.0,
+            mouse_sensitivity: 0.0,
+            transform: Transform { yaw: 0.0, pitch: 0.0, posistion: Point3D { x: 0.0, y: 0.0, z: 0.0 } },
+            pitch_mode: PitchMode::default(),
+        }
+    }
+
+    #[test]
+    fn projection_scale_is_finite_and_bounded_at_the_fov_limits() {
+        let at_max = camera(MAX_FOV).scaled_inv_world_height(720.0);
+        let at_min = camera(MIN_FOV).scaled_inv_world_height(720.0);
+        // 720 / (2 tan(85 degrees)) and 720 / (2 tan(0.5 degrees))
+        assert!((at_max - 31.5).abs() < 0.1, "{}", at_max);
+        assert!((at_min - 41250.0).abs() < 50.0, "{}", at_min);
+        // Past the limits, including a straight angle where tan blows up, the scale stays at the clamped values
+        for fov in [std::f32::consts::PI, 10.0, 0.0, -1.0] {
+            let scale = camera(fov).scaled_inv_world_height(720.0);
+            assert!(scale.is_finite() && (at_max..=at_min).contains(&scale), "{} at fov {}", scale, fov);
+        }
+    }
+
+    #[test]
+    fn look_at_points_the_forward_axis_at_the_target() {
+        let up = Point3D { x: 0.0, y: 1.0, z: 0.0 };
+        let eye = Point3D { x: 1.0, y: 2.0, z: -3.0 };
+        let mut camera = camera(1.0);
+        for target in [Point3D { x: 4.0, y: -1.0, z: 5.0 }, Point3D { x: -6.0, y: 2.5, z: -8.0 }, Point3D { x: 1.0, y: 0.0, z: 7.0 }] {
+            camera.look_at(eye, target, up);
+            let (_, _, forward) = camera.transform.get_basis_vectors();
+            let expected = point3d::normalize(target - eye);
+            assert!(point3d::dot3(forward, expected) > 1.0 - 1e-5, "{:?} vs {:?}", forward, expected);
+            assert_eq!(camera.transform.posistion, eye);
+        }
+        // Straight up is parallel to `up`, the heading from the last call is kept and the camera still looks up
+        let yaw = camera.transform.yaw;
+        camera.look_at(eye, eye + Point3D { x: 0.0, y: 3.0, z: 0.0 }, up);
+        assert_eq!(camera.transform.yaw, yaw);
+        assert!(camera.transform.get_basis_vectors().2.y > 1.0 - 1e-5);
+    }
+
+    #[test]
+    fn clamped_pitch_stops_at_the_limit_and_free_pitch_goes_over_the_top() {
+        let limit = 60.0f32.to_radians();
+        let mut clamped = Camera { pitch_mode: PitchMode::Clamped(limit), ..camera(1.0) };
+        let mut free = Camera { pitch_mode: PitchMode::Free, ..camera(1.0) };
+        // Three quarter turns up in small steps, well past both the limit and vertical
+        for _ in 0..30 {
+            clamped.rotate(0.0, 0.05 * std::f32::consts::PI);
+            free.rotate(0.0, 0.05 * std::f32::consts::PI);
+        }
+        assert_eq!(clamped.transform.pitch, limit);
+        // 270 degrees wraps to -90
+        assert!((free.transform.pitch + std::f32::consts::FRAC_PI_2).abs() < 1e-4, "{}", free.transform.pitch);
+        clamped.rotate(0.0, -10.0);
+        assert_eq!(clamped.transform.pitch, -limit);
+
+        // Upside-down, the same mouse motion turns yaw the other way so the view still follows the mouse
+        let mut over = Camera { pitch_mode: PitchMode::Free, ..camera(1.0) };
+        over.transform.pitch = std::f32::consts::PI * 0.75;
+        over.rotate(0.1, 0.0);
+        clamped.rotate(0.1, 0.0);
+        assert!((over.transform.yaw - 0.1).abs() < 1e-6);
+        assert!((clamped.transform.yaw + 0.1).abs() < 1e-6);
+    }
+}
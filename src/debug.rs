@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+use anyhow::Result;
+
+use crate::geometry::{TileLayout, draw_rectangles};
+use crate::rectangle::Rect;
+
+/// Where --debug-output's files go. Every save does nothing while it's off, so normal runs leave the directory alone
+#[derive(Debug, Clone, Default)]
+pub struct DebugOutput {
+    // None while debug output is off
+    dir: Option<PathBuf>,
+}
+
+impl DebugOutput {
+    pub fn new(enabled: bool, dir: impl Into<PathBuf>) -> Self {
+        Self { dir: enabled.then(|| dir.into()) }
+    }
+
+    /// Where a debug file called `name` goes, None while debug output is off
+    pub fn path(&self, name: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(name))
+    }
+
+    /// rectangles.png and tiles.json for a tiling, returns the files written
+    pub fn save_tiles(&self, tiles: &[Rect], width: u32, height: u32) -> Result<Vec<PathBuf>> {
+        let (Some(picture), Some(json)) = (self.path("rectangles.png"), self.path("tiles.json")) else { return Ok(Vec::new()) };
+        draw_rectangles(tiles, width, height, &picture.to_string_lossy());
+        TileLayout::new(tiles, width, height).save_json(&json.to_string_lossy())?;
+        Ok(vec![picture, json])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::subdivide_count;
+
+    #[test]
+    fn nothing_is_written_while_debug_output_is_off() {
+        let dir = std::env::temp_dir().join(format!("rusterizer-debug-off-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let debug = DebugOutput::new(false, &dir);
+        let written = debug.save_tiles(&subdivide_count(64, 48, 4), 64, 48);
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(written.unwrap().is_empty());
+        assert_eq!(files, 0);
+        assert_eq!(debug.path("performance_metrics.png"), None);
+        assert_eq!(DebugOutput::new(true, &dir).path("tiles.json"), Some(dir.join("tiles.json")));
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+/// Failures loading a scene's assets or setting up the renderer, for callers that want to react to the kind of failure rather than just report it
+#[derive(Debug)]
+pub enum RusterizerError {
+    // The file couldn't be opened or read
+    Io { path: String, source: std::io::Error },
+    // The OBJ was read but isn't valid
+    Obj { path: String, message: String },
+    // The glTF was read but isn't valid or uses something unsupported
+    Gltf { path: String, message: String },
+    // The image was read but couldn't be decoded
+    Texture { path: String, source: image::ImageError },
+    // The scene file was read but isn't valid
+    Scene { path: String, message: String },
+    // The morph target can't be paired with the model
+    Morph { path: String, message: String },
+    // The bone weights file can't be read or doesn't fit the model
+    Skin { path: String, message: String },
+    // The worker threads couldn't be started
+    ThreadPool { source: rayon::ThreadPoolBuildError },
+    // The window's framebuffer texture couldn't be created
+    Window { message: String },
+    // The performance plot couldn't be drawn or written
+    Plot { path: String, message: String },
+}
+
+impl fmt::Display for RusterizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RusterizerError::Io { path, source } => write!(f, "can't read {path}: {source}"),
+            RusterizerError::Obj { path, message } => write!(f, "invalid OBJ file {path}: {message}"),
+            RusterizerError::Gltf { path, message } => write!(f, "invalid glTF file {path}: {message}"),
+            RusterizerError::Texture { path, source } => write!(f, "can't decode texture {path}: {source}"),
+            RusterizerError::Scene { path, message } => write!(f, "invalid scene file {path}: {message}"),
+            RusterizerError::Morph { path, message } => write!(f, "can't use morph target {path}: {message}"),
+            RusterizerError::Skin { path, message } => write!(f, "can't use bone weights {path}: {message}"),
+            RusterizerError::ThreadPool { source } => write!(f, "can't start the render threads: {source}"),
+            RusterizerError::Window { message } => write!(f, "can't create the window's framebuffer: {message}"),
+            RusterizerError::Plot { path, message } => write!(f, "can't plot performance metrics to {path}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for RusterizerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RusterizerError::Io { source, .. } => Some(source),
+            RusterizerError::Texture { source, .. } => Some(source),
+            RusterizerError::ThreadPool { source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl RusterizerError {
+    /// Classify an image load failure, a file that couldn't be read is Io rather than Texture
+    pub fn texture(path: &str, error: image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(source) => RusterizerError::Io { path: path.to_string(), source },
+            source => RusterizerError::Texture { path: path.to_string(), source },
+        }
+    }
+
+    /// Classify an OBJ parse failure, a file that couldn't be read is Io rather than Obj
+    pub fn obj(path: &str, error: anyhow::Error) -> Self {
+        match error.downcast::<std::io::Error>() {
+            Ok(source) => RusterizerError::Io { path: path.to_string(), source },
+            Err(error) => RusterizerError::Obj { path: path.to_string(), message: error.to_string() },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn failures_are_classified_and_keep_their_source() {
+        let missing = || std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        let unreadable = RusterizerError::texture("wood.png", image::ImageError::IoError(missing()));
+        assert!(matches!(&unreadable, RusterizerError::Io { path, .. } if path == "wood.png"));
+        assert!(unreadable.source().is_some());
+        assert_eq!(unreadable.to_string(), "can't read wood.png: no such file");
+
+        assert!(matches!(RusterizerError::obj("cube.obj", anyhow::Error::from(missing())), RusterizerError::Io { .. }));
+        let invalid = RusterizerError::obj("cube.obj", anyhow::anyhow!("face 3 has 2 vertices"));
+        assert!(matches!(&invalid, RusterizerError::Obj { message, .. } if message == "face 3 has 2 vertices"));
+        assert!(invalid.source().is_none());
+        assert_eq!(invalid.to_string(), "invalid OBJ file cube.obj: face 3 has 2 vertices");
+
+        let plot = RusterizerError::Plot { path: "metrics.png".to_string(), message: "no frames were rendered".to_string() };
+        assert_eq!(plot.to_string(), "can't plot performance metrics to metrics.png: no frames were rendered");
+    }
+}
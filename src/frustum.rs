@@ -0,0 +1,148 @@
+use crate::point2d::Point2D;
+use crate::point3d::{self, Point3D, dot3};
+
+pub const PLANE_COUNT: usize = 6;
+// Indices into Frustum::planes and TriangleDistances
+pub const NEAR: usize = 0;
+pub const FAR: usize = 1;
+pub const LEFT: usize = 2;
+pub const RIGHT: usize = 3;
+pub const BOTTOM: usize = 4;
+pub const TOP: usize = 5;
+
+/// Plane with a unit normal, points on the normal's side have positive distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Point3D,
+    pub offset: f32,
+}
+
+impl Plane {
+    #[inline(always)]
+    pub fn distance(&self, point: Point3D) -> f32 {
+        dot3(self.normal, point) + self.offset
+    }
+}
+
+/// The camera's view volume in view space (looking down +z), every plane faces inward
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; PLANE_COUNT],
+}
+
+impl Frustum {
+    /// Matches the projection in view_to_screen, `far` may be infinite
+    pub fn new(resolution: Point2D, scaled_inv_world_height: f32, near: f32, far: f32) -> Self {
+        // Half the screen's extent in world units at depth 1, the side planes pass through the eye and these edges
+        let half_x = resolution.x * 0.5 / scaled_inv_world_height;
+        let half_y = resolution.y * 0.5 / scaled_inv_world_height;
+        let side = |x: f32, y: f32, z: f32| Plane { normal: point3d::normalize(Point3D { x, y, z }), offset: 0.0 };
+        Self {
+            planes: [
+                Plane { normal: Point3D { x: 0.0, y: 0.0, z: 1.0 }, offset: -near },
+                Plane { normal: Point3D { x: 0.0, y: 0.0, z: -1.0 }, offset: far },
+                side(1.0, 0.0, half_x),
+                side(-1.0, 0.0, half_x),
+                side(0.0, 1.0, half_y),
+                side(0.0, -1.0, half_y),
+            ],
+        }
+    }
+
+    /// The sphere is entirely behind one of the planes, conservative near the frustum's corners where it may still say false
+    pub fn sphere_outside(&self, center: Point3D, radius: f32) -> bool {
+        self.planes.iter().any(|plane| plane.distance(center) < -radius)
+    }
+
+    /// Signed distances of a view-space triangle's vertices to every plane, computed once and shared by culling and clipping
+    pub fn triangle_distances(&self, a: Point3D, b: Point3D, c: Point3D) -> TriangleDistances {
+        TriangleDistances(self.planes.map(|plane| [plane.distance(a), plane.distance(b), plane.distance(c)]))
+    }
+}
+
+/// Per plane, the distances of vertices a, b and c, positive inside
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriangleDistances(pub [[f32; 3]; PLANE_COUNT]);
+
+impl TriangleDistances {
+    /// Every vertex is outside the same plane, so no part of the triangle can be visible
+    pub fn outside(&self) -> bool {
+        self.0.iter().any(|d| d.iter().all(|&v| v < 0.0))
+    }
+
+    /// Every vertex is inside every plane, nothing needs clipping
+    pub fn inside(&self) -> bool {
+        self.0.iter().all(|d| d.iter().all(|&v| v >= 0.0))
+    }
+
+    /// The triangle has vertices on both sides of `plane`
+    pub fn crosses(&self, plane: usize) -> bool {
+        let d = self.0[plane];
+        d.iter().any(|&v| v < 0.0) && d.iter().any(|&v| v >= 0.0)
+    }
+}
+
+/// Ritter's approximate bounding sphere, within about 5% of the minimal one. A zero radius sphere at the origin for no points
+pub fn bounding_sphere(points: &[Point3D]) -> (Point3D, f32) {
+    let Some(&first) = points.first() else {
+        return (Point3D { x: 0.0, y: 0.0, z: 0.0 }, 0.0);
+    };
+    let distance_sq = |a: Point3D, b: Point3D| dot3(a - b, a - b);
+    let furthest_from = |from: Point3D| points.iter().copied().fold(from, |best, p| if distance_sq(p, from) > distance_sq(best, from) { p } else { best });
+    // Start from a roughly diametric pair, the point furthest from an arbitrary one and the point furthest from that
+    let x = furthest_from(first);
+    let y = furthest_from(x);
+    let mut center = point3d::lerp(x, y, 0.5);
+    let mut radius = distance_sq(x, y).sqrt() * 0.5;
+    // Grow the sphere just enough to take in each point left outside it
+    for &p in points {
+        let distance = distance_sq(p, center).sqrt();
+        if distance > radius {
+            let grown = (radius + distance) * 0.5;
+            center = point3d::lerp(center, p, (grown - radius) / distance);
+            radius = grown;
+        }
+    }
+    (center, radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounding_sphere_contains_every_point() {
+        let point = |x, y, z| Point3D { x, y, z };
+        let points = [
+            point(1.0, 2.0, 3.0), point(-4.0, 0.5, 2.0), point(3.0, -3.0, -1.0), point(0.0, 6.0, 0.0),
+            point(-2.0, -5.0, 4.0), point(5.0, 1.0, -6.0), point(0.2, 0.3, 0.1), point(-6.0, 2.0, -2.0),
+        ];
+        let (center, radius) = bounding_sphere(&points);
+        for p in points {
+            let distance = dot3(p - center, p - center).sqrt();
+            assert!(distance <= radius * (1.0 + 1e-5), "{:?} is {} from the center, radius {}", p, distance, radius);
+        }
+        // The widest pair is about 13.6 apart, so the minimal sphere has a radius of at least 6.8
+        assert!(radius < 8.0, "radius {}", radius);
+    }
+
+    #[test]
+    fn triangle_spanning_the_near_plane_has_distances_of_both_signs() {
+        // 64x48 at a 90 degree vertical FOV: the side planes pass through x = +-4/3 z and y = +-z
+        let frustum = Frustum::new(Point2D { x: 64.0, y: 48.0 }, 24.0, 1.0, 100.0);
+        let point = |x, y, z| Point3D { x, y, z };
+        let spanning = frustum.triangle_distances(point(0.0, 0.0, 0.5), point(0.0, 0.0, 5.0), point(0.5, 0.0, 5.0));
+        assert_eq!(spanning.0[NEAR], [-0.5, 4.0, 4.0]);
+        assert_eq!(spanning.0[FAR], [99.5, 95.0, 95.0]);
+        for plane in [LEFT, RIGHT, BOTTOM, TOP] {
+            assert!(spanning.0[plane].iter().all(|&d| d > 0.0), "{plane}: {:?}", spanning.0[plane]);
+        }
+        assert!(spanning.crosses(NEAR) && !spanning.crosses(FAR));
+        assert!(!spanning.inside() && !spanning.outside());
+
+        // Wholly past the right plane, at x = 10 where it only reaches 20 / 3
+        let right = frustum.triangle_distances(point(10.0, 0.0, 5.0), point(12.0, 1.0, 5.0), point(11.0, -1.0, 6.0));
+        assert!(right.0[RIGHT].iter().all(|&d| d < 0.0) && right.0[LEFT].iter().all(|&d| d > 0.0));
+        assert!(right.outside() && !right.crosses(RIGHT));
+    }
+}
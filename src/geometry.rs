@@ -1,9 +1,11 @@
 use image::RgbImage;
 use rand::Rng;
 use image::Rgb;
+use anyhow::Result;
+use serde::Serialize;
 
 use crate::point2d::{Point2D, perp, dot2};
-use crate::point3d::Point3D;
+use crate::point3d::{Point3D, dot3};
 use crate::transform::Transform;
 use crate::rectangle::Rect;
 use crate::camera::Camera;
@@ -14,17 +16,135 @@ pub fn signed_triangle_area(t1: Point2D, t2: Point2D, p: Point2D) -> f32 {
     dot2(ap, t1t2perp) / 2.0
 }
 
+/// signed_triangle_area(t1, t2, p) rewritten as step_x * p.x + step_y * p.y + offset, so it can be stepped across pixels by adding constants
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct EdgeFunction {
+    pub step_x: f32,
+    pub step_y: f32,
+    pub offset: f32,
+}
+
+impl EdgeFunction {
+    #[inline(always)]
+    pub fn new(t1: Point2D, t2: Point2D) -> Self {
+        let step_x = (t2.y - t1.y) * 0.5;
+        let step_y = (t1.x - t2.x) * 0.5;
+        Self { step_x, step_y, offset: -(t1.x * step_x + t1.y * step_y) }
+    }
+
+    #[inline(always)]
+    pub fn eval(&self, p: Point2D) -> f32 {
+        self.step_x * p.x + self.step_y * p.y + self.offset
+    }
+
+    /// How far below zero the value at a pixel center can be while the edge still touches some part of that pixel's square,
+    /// the edge function is linear so its largest value over the square is at the corner furthest along (step_x, step_y)
+    #[inline(always)]
+    pub fn conservative_bias(&self) -> f32 {
+        0.5 * (self.step_x.abs() + self.step_y.abs())
+    }
+}
+
+/// Everything rasterization needs from a screen-space triangle's corners, computed once per triangle in the transform stage
+/// so the per-tile loops only step and evaluate
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct EdgeSetup {
+    pub ab: EdgeFunction,
+    pub bc: EdgeFunction,
+    pub ca: EdgeFunction,
+    // Signed, negative for back faces
+    pub area: f32,
+    pub inv_area: f32,
+}
+
+impl EdgeSetup {
+    pub fn new(a: Point2D, b: Point2D, c: Point2D) -> Self {
+        let (area, inv_area) = inv_triangle_area(a, b, c);
+        Self { ab: EdgeFunction::new(a, b), bc: EdgeFunction::new(b, c), ca: EdgeFunction::new(c, a), area, inv_area }
+    }
+
+    /// Fraction of the pattern's samples around `pixel_center` inside a front-facing triangle
+    pub fn coverage(&self, pixel_center: Point2D, pattern: &SamplePattern) -> f32 {
+        let offsets = pattern.offsets();
+        if self.area.is_nan() || self.area <= 0.0 || offsets.is_empty() {
+            return 0.0
+        }
+        let inside = offsets.iter()
+            .map(|&offset| pixel_center + offset)
+            .filter(|&p| self.ab.eval(p) >= 0.0 && self.bc.eval(p) >= 0.0 && self.ca.eval(p) >= 0.0)
+            .count();
+        inside as f32 / offsets.len() as f32
+    }
+}
+
+// 2x2 samples on the pixel's axes, a near-horizontal edge crosses both rows at almost the same x so coverage only has 3 levels
+const GRID_OFFSETS: [Point2D; 4] = [
+    Point2D { x: -0.25, y: -0.25 },
+    Point2D { x: 0.25, y: -0.25 },
+    Point2D { x: -0.25, y: 0.25 },
+    Point2D { x: 0.25, y: 0.25 },
+];
+
+// The grid rotated so every sample has its own row and column, giving near-axis edges 5 coverage levels
+const ROTATED_GRID_OFFSETS: [Point2D; 4] = [
+    Point2D { x: -0.125, y: -0.375 },
+    Point2D { x: 0.375, y: -0.125 },
+    Point2D { x: 0.125, y: 0.375 },
+    Point2D { x: -0.375, y: 0.125 },
+];
+
+/// Where in a pixel its single coverage/interpolation sample is taken
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PixelCenter {
+    // x + 0.5, the D3D10+/OpenGL convention
+    #[default]
+    Center,
+    // x + 0.0, for matching reference renderers that sample at the pixel's corner
+    TopLeft,
+}
+
+impl PixelCenter {
+    /// Added to integer pixel coordinates to get the sample position
+    #[inline(always)]
+    pub fn offset(self) -> f32 {
+        match self {
+            PixelCenter::Center => 0.5,
+            PixelCenter::TopLeft => 0.0,
+        }
+    }
+}
+
+/// Sample positions within a pixel for multisampled coverage, as offsets from the pixel center in [-0.5, 0.5]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum SamplePattern {
+    Grid,
+    #[default]
+    RotatedGrid,
+    Custom(Vec<Point2D>),
+}
+
+impl SamplePattern {
+    pub fn offsets(&self) -> &[Point2D] {
+        match self {
+            SamplePattern::Grid => &GRID_OFFSETS,
+            SamplePattern::RotatedGrid => &ROTATED_GRID_OFFSETS,
+            SamplePattern::Custom(offsets) => offsets,
+        }
+    }
+}
+
+/// Recomputes all three edges for one point, the raster loop steps EdgeSetup's functions instead and is tested against this
 #[inline(always)]
 pub fn point_in_triangle(a: Point2D, b: Point2D, c: Point2D, p: Point2D, area: f32, inv_area: f32, weights: &mut Point3D) -> bool {
     // Fail fast on any step
     let area_ab: f32 = signed_triangle_area(a, b, p);
-    if !(area_ab >= 0.0) {return false}
+    if area_ab.is_nan() || area_ab < 0.0 {return false}
     let area_bc: f32 = signed_triangle_area(b, c, p);
-    if !(area_bc >= 0.0) {return false}
+    if area_bc.is_nan() || area_bc < 0.0 {return false}
     let area_ca: f32 = signed_triangle_area(c, a, p);
-    if !(area_ca >= 0.0) {return false}
+    if area_ca.is_nan() || area_ca < 0.0 {return false}
     // Use pre-computed area/inverse once per triangle
-    if !(area > 0.0) {return false}
+    if area.is_nan() || area <= 0.0 {return false}
     // Only compute weights if all checks pass
     weights.x = area_bc * inv_area;
     weights.y = area_ca * inv_area;
@@ -38,24 +158,94 @@ pub fn inv_triangle_area(a: Point2D, b: Point2D, c: Point2D) -> (f32,f32) {
     (area, 1.0 / area)
 }
 
+/// Trivial reject for a front-facing triangle against a tile: if every tile corner is outside the same edge no pixel in the tile can be covered
+#[inline(always)]
+pub fn triangle_misses_rect(a: Point2D, b: Point2D, c: Point2D, rect: &Rect, pixel_center: PixelCenter) -> bool {
+    // The outermost sample positions inside the tile
+    let offset = pixel_center.offset();
+    let min_x = rect.min_x as f32 + offset;
+    let min_y = rect.min_y as f32 + offset;
+    let max_x = rect.max_x as f32 - 1.0 + offset;
+    let max_y = rect.max_y as f32 - 1.0 + offset;
+    let corners = [
+        Point2D { x: min_x, y: min_y },
+        Point2D { x: max_x, y: min_y },
+        Point2D { x: min_x, y: max_y },
+        Point2D { x: max_x, y: max_y },
+    ];
+    [(a, b), (b, c), (c, a)]
+        .iter()
+        .any(|&(t1, t2)| corners.iter().all(|&p| signed_triangle_area(t1, t2, p) < 0.0))
+}
+
 #[inline(always)]
 pub fn vertex_to_screen(vertex: Point3D, transform: &Transform, camera: &Camera, resolution: Point2D, scaled_inv_world_height: f32) -> Point3D {
     
     let vertex_world: Point3D = transform.to_world_point(vertex);
     let vertex_view: Point3D = camera.transform.to_local_point(vertex_world);
+    view_to_screen(vertex_view, resolution, scaled_inv_world_height)
+}
+
+/// Projection half of vertex_to_screen, for points already in view space
+#[inline(always)]
+pub fn view_to_screen(vertex_view: Point3D, resolution: Point2D, scaled_inv_world_height: f32) -> Point3D {
     let z_inverted = 1.0 / vertex_view.z;
     
     let pixels_per_world_unit: f32 = scaled_inv_world_height * z_inverted;
 
-    // Apply scaling and shift to center screen (mul add for perf)
-    let screen_x = (vertex_view.x * pixels_per_world_unit).mul_add(1.0, resolution.x * 0.5);
-    let screen_y = (vertex_view.y * pixels_per_world_unit).mul_add(1.0, resolution.y * 0.5);
+    // Scale and shift to center screen fused into one FMA
+    let screen_x = vertex_view.x.mul_add(pixels_per_world_unit, resolution.x * 0.5);
+    let screen_y = vertex_view.y.mul_add(pixels_per_world_unit, resolution.y * 0.5);
     
     // z-buffer is pre-inverted for performance
     Point3D { x: screen_x, y: screen_y, z: z_inverted }
 }
 
+/// Inverse of the projection in vertex_to_screen, takes a screen position and its view depth (z, not the inverted z) back to view space
+#[inline(always)]
+pub fn reconstruct_view_pos(x: f32, y: f32, depth: f32, resolution: Point2D, scaled_inv_world_height: f32) -> Point3D {
+    let world_units_per_pixel: f32 = depth / scaled_inv_world_height;
+    Point3D {
+        x: (x - resolution.x * 0.5) * world_units_per_pixel,
+        y: (y - resolution.y * 0.5) * world_units_per_pixel,
+        z: depth,
+    }
+}
+
+/// Perspective-correct interpolation of a per-vertex scalar, `inv_depths` are the vertices' 1/z and `depth` the fragment's view z
+#[inline(always)]
+pub fn perspective_interp(a: f32, b: f32, c: f32, inv_depths: Point3D, weights: Point3D, depth: f32) -> f32 {
+    dot3(Point3D { x: a * inv_depths.x, y: b * inv_depths.y, z: c * inv_depths.z }, weights) * depth
+}
+
+/// perspective_interp for each component of a per-vertex vector
+#[inline(always)]
+pub fn perspective_interp3(a: Point3D, b: Point3D, c: Point3D, inv_depths: Point3D, weights: Point3D, depth: f32) -> Point3D {
+    Point3D {
+        x: perspective_interp(a.x, b.x, c.x, inv_depths, weights, depth),
+        y: perspective_interp(a.y, b.y, c.y, inv_depths, weights, depth),
+        z: perspective_interp(a.z, b.z, c.z, inv_depths, weights, depth),
+    }
+}
+
+/// Line segments of a square grid on the plane y = height, centered on the origin with `cells` cells of `spacing` per side
+pub fn grid_lines(cells: u32, spacing: f32, height: f32) -> Vec<(Point3D, Point3D)> {
+    let half = cells as f32 * spacing * 0.5;
+    let mut lines = Vec::with_capacity(2 * (cells as usize + 1));
+    for i in 0..=cells {
+        let offset = i as f32 * spacing - half;
+        lines.push((Point3D { x: offset, y: height, z: -half }, Point3D { x: offset, y: height, z: half }));
+        lines.push((Point3D { x: -half, y: height, z: offset }, Point3D { x: half, y: height, z: offset }));
+    }
+    lines
+}
+
+/// Smallest tile edge allowed, tiles are rasterized per pixel so this only keeps high subdivision from producing slivers
+/// whose per-tile scheduling costs more than their pixels
+pub const MIN_TILE_SIZE: u32 = 2;
+
 /// Subdivide a rectangle evenly with given depth
+/// Splits that would leave a tile narrower or shorter than MIN_TILE_SIZE are skipped
 pub fn subdivide(width: u32, height: u32, depth: u32) -> Vec<Rect> {
     let mut rects = Vec::new();
 
@@ -76,6 +266,19 @@ pub fn subdivide(width: u32, height: u32, depth: u32) -> Vec<Rect> {
         let w = r.max_x - r.min_x;
         let h = r.max_y - r.min_y;
 
+        // Fall back to the other axis when this one is too small to split, stop if neither fits
+        let can_split_x = w / 2 >= MIN_TILE_SIZE;
+        let can_split_y = h / 2 >= MIN_TILE_SIZE;
+        let vertical = match (can_split_x, can_split_y) {
+            (false, false) => {
+                rects.push(r);
+                return;
+            }
+            (true, false) => true,
+            (false, true) => false,
+            (true, true) => vertical,
+        };
+
         if vertical {
             let mid = r.min_x + w / 2;
             let left = Rect { min_x: r.min_x, min_y: r.min_y, max_x: mid, max_y: r.max_y };
@@ -95,6 +298,40 @@ pub fn subdivide(width: u32, height: u32, depth: u32) -> Vec<Rect> {
     rects
 }
 
+/// Split the screen into exactly `count` tiles (fewer if they'd drop below MIN_TILE_SIZE) by repeatedly halving the largest tile across its longer side
+pub fn subdivide_count(width: u32, height: u32, count: usize) -> Vec<Rect> {
+    let mut rects = vec![Rect { min_x: 0, min_y: 0, max_x: width, max_y: height }];
+
+    while rects.len() < count {
+        // Largest tile that can still be split, ties go to the earliest
+        let candidate = rects
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.width() / 2 >= MIN_TILE_SIZE || r.height() / 2 >= MIN_TILE_SIZE)
+            .fold(None, |best: Option<(usize, u64)>, (i, r)| {
+                let area = r.width() as u64 * r.height() as u64;
+                match best {
+                    Some((_, best_area)) if best_area >= area => best,
+                    _ => Some((i, area)),
+                }
+            });
+        let Some((index, _)) = candidate else { break };
+
+        let r = rects[index];
+        let split_x = r.width() / 2 >= MIN_TILE_SIZE && (r.width() >= r.height() || r.height() / 2 < MIN_TILE_SIZE);
+        let (first, second) = if split_x {
+            let mid = r.min_x + r.width() / 2;
+            (Rect { max_x: mid, ..r }, Rect { min_x: mid, ..r })
+        } else {
+            let mid = r.min_y + r.height() / 2;
+            (Rect { max_y: mid, ..r }, Rect { min_y: mid, ..r })
+        };
+        rects[index] = first;
+        rects.push(second);
+    }
+    rects
+}
+
 /// Save rectangles to an image file to represent areas of screen rendered by individual threads later (NOT USED IN RENDERING PIPELINE)
 pub fn draw_rectangles(rects: &[Rect], width: u32, height: u32, filename: &str) {
     let mut img = RgbImage::new(width, height);
@@ -118,3 +355,246 @@ pub fn draw_rectangles(rects: &[Rect], width: u32, height: u32, filename: &str)
 
     img.save(filename).expect("Failed to save image");
 }
+
+/// Tiling of a screen as plain data, tiles are listed in the order the rasterizer uses them so `index` lines up with DrawStats::tile_times
+#[derive(Debug, Clone, Serialize)]
+pub struct TileLayout {
+    pub width: u32,
+    pub height: u32,
+    pub tiles: Vec<TileEntry>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TileEntry {
+    pub index: usize,
+    // Half-open pixel bounds, max_x and max_y are excluded
+    pub rect: Rect,
+}
+
+impl TileLayout {
+    pub fn new(rects: &[Rect], width: u32, height: u32) -> Self {
+        let tiles = rects.iter().enumerate().map(|(index, &rect)| TileEntry { index, rect }).collect();
+        Self { width, height, tiles }
+    }
+
+    /// Whether the tiles cover every pixel of the screen exactly once
+    pub fn partitions_screen(&self) -> bool {
+        let mut covered = vec![false; self.width as usize * self.height as usize];
+        for tile in &self.tiles {
+            let rect = tile.rect;
+            if rect.max_x > self.width || rect.max_y > self.height {
+                return false;
+            }
+            for y in rect.min_y..rect.max_y {
+                for x in rect.min_x..rect.max_x {
+                    let pixel = &mut covered[y as usize * self.width as usize + x as usize];
+                    if *pixel {
+                        return false;
+                    }
+                    *pixel = true;
+                }
+            }
+        }
+        covered.iter().all(|&c| c)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Write the layout as JSON for external load-balance analysis, the structured counterpart of draw_rectangles
+    pub fn save_json(&self, filename: &str) -> Result<()> {
+        std::fs::write(filename, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subdivided_tiles_partition_the_screen() {
+        for (width, height) in [(64, 48), (1280, 720), (1000, 600), (36, 12)] {
+            for count in [1, 2, 3, 7, 16, 48, 200] {
+                let layout = TileLayout::new(&subdivide_count(width, height, count), width, height);
+                assert!(layout.partitions_screen(), "{} tiles of {}x{}", count, width, height);
+                assert!(layout.tiles.iter().enumerate().all(|(i, tile)| tile.index == i));
+            }
+            for depth in 0..4 {
+                assert!(TileLayout::new(&subdivide(width, height, depth), width, height).partitions_screen(), "depth {} of {}x{}", depth, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn gaps_overlaps_and_overhangs_are_not_partitions() {
+        let rect = |min_x, min_y, max_x, max_y| Rect { min_x, min_y, max_x, max_y };
+        let halves = [rect(0, 0, 8, 8), rect(8, 0, 16, 8)];
+        assert!(TileLayout::new(&halves, 16, 8).partitions_screen());
+        assert!(!TileLayout::new(&halves[..1], 16, 8).partitions_screen());
+        assert!(!TileLayout::new(&[halves[0], rect(7, 0, 16, 8)], 16, 8).partitions_screen());
+        assert!(!TileLayout::new(&[halves[0], rect(8, 0, 17, 8)], 16, 8).partitions_screen());
+    }
+
+    #[test]
+    fn tiny_screens_never_get_tiles_below_the_minimum() {
+        for (width, height) in [(5, 3), (7, 7), (2, 9), (3, 3), (4, 2)] {
+            let layouts = [subdivide(width, height, 12), subdivide_count(width, height, 500)];
+            for rects in layouts {
+                assert!(rects.iter().all(|r| r.width() >= MIN_TILE_SIZE && r.height() >= MIN_TILE_SIZE), "{:?} on {}x{}", rects, width, height);
+                assert!(TileLayout::new(&rects, width, height).partitions_screen());
+            }
+        }
+    }
+
+    #[test]
+    fn reconstruct_view_pos_inverts_vertex_to_screen() {
+        let camera = Camera {
+            fov: 70.0f32.to_radians(),
+            camera_speed: 1.0,
+            mouse_sensitivity: 0.0,
+            transform: Transform { yaw: 0.7, pitch: -0.3, posistion: Point3D { x: 2.0, y: -1.0, z: -6.0 } },
+            pitch_mode: crate::camera::PitchMode::default(),
+        };
+        let model = Transform { yaw: 1.2, pitch: 0.0, posistion: Point3D { x: 0.5, y: 0.0, z: 3.0 } };
+        let resolution = Point2D { x: 320.0, y: 200.0 };
+        let scale = camera.scaled_inv_world_height(resolution.y);
+        for vertex in [Point3D { x: 0.0, y: 0.0, z: 0.0 }, Point3D { x: 1.5, y: -2.0, z: 0.7 }, Point3D { x: -3.0, y: 1.0, z: -1.0 }] {
+            let screen = vertex_to_screen(vertex, &model, &camera, resolution, scale);
+            let view = camera.transform.to_local_point(model.to_world_point(vertex));
+            assert!(view.z > 0.0);
+            let back = reconstruct_view_pos(screen.x, screen.y, 1.0 / screen.z, resolution, scale);
+            for (got, expected) in [(back.x, view.x), (back.y, view.y), (back.z, view.z)] {
+                assert!((got - expected).abs() < 1e-3, "{:?} vs {:?}", back, view);
+            }
+        }
+    }
+
+    #[test]
+    fn large_corner_triangle_rejects_the_far_tiles() {
+        // Covers x / 50 + y / 40 <= 1 in the top-left corner of a 64x48 screen split into 4x4 tiles of 16x12
+        let (a, b, c) = (Point2D { x: 0.0, y: 0.0 }, Point2D { x: 0.0, y: 40.0 }, Point2D { x: 50.0, y: 0.0 });
+        let tile = |i: u32, j: u32| Rect { min_x: i * 16, min_y: j * 12, max_x: (i + 1) * 16, max_y: (j + 1) * 12 };
+        for j in 0..4 {
+            for i in 0..4 {
+                let rect = tile(i, j);
+                let covered = (rect.min_y..rect.max_y).any(|y| (rect.min_x..rect.max_x).any(|x| (x as f32 + 0.5) / 50.0 + (y as f32 + 0.5) / 40.0 <= 1.0));
+                if triangle_misses_rect(a, b, c, &rect, PixelCenter::Center) {
+                    assert!(!covered, "tile ({}, {}) rejected but covered", i, j);
+                }
+            }
+        }
+        assert!(!triangle_misses_rect(a, b, c, &tile(0, 0), PixelCenter::Center));
+        for (i, j) in [(3, 3), (2, 3), (3, 2), (2, 2)] {
+            assert!(triangle_misses_rect(a, b, c, &tile(i, j), PixelCenter::Center), "tile ({}, {})", i, j);
+        }
+    }
+
+    #[test]
+    fn stepped_edge_functions_match_point_in_triangle() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(391);
+        for _ in 0..50 {
+            let mut corner = || Point2D { x: rng.gen_range(0.0..200.0f32), y: rng.gen_range(0.0..200.0f32) };
+            let (a, b, c) = (corner(), corner(), corner());
+            let setup = EdgeSetup::new(a, b, c);
+            // Reference and stepped loop disagree only for centers within rounding of an edge
+            let near_edge = |p: Point2D| [(a, b), (b, c), (c, a)].iter().any(|&(t1, t2)| signed_triangle_area(t1, t2, p).abs() < 1e-2);
+            for y in 0..200 {
+                let row_start = Point2D { x: 0.5, y: y as f32 + 0.5 };
+                let (mut row_ab, mut row_bc, mut row_ca) = (setup.ab.eval(row_start), setup.bc.eval(row_start), setup.ca.eval(row_start));
+                for x in 0..200 {
+                    let p = Point2D { x: x as f32 + 0.5, y: y as f32 + 0.5 };
+                    let mut expected = Point3D { x: 0.0, y: 0.0, z: 0.0 };
+                    let inside = point_in_triangle(a, b, c, p, setup.area, setup.inv_area, &mut expected);
+                    let stepped = setup.area > 0.0 && row_ab >= 0.0 && row_bc >= 0.0 && row_ca >= 0.0;
+                    if !near_edge(p) {
+                        assert_eq!(inside, stepped, "{:?} in {:?} {:?} {:?}", p, a, b, c);
+                    }
+                    if inside && stepped {
+                        let weights = Point3D { x: row_bc * setup.inv_area, y: row_ca * setup.inv_area, z: row_ab * setup.inv_area };
+                        for (got, want) in [(weights.x, expected.x), (weights.y, expected.y), (weights.z, expected.z)] {
+                            assert!((got - want).abs() < 1e-3, "{:?} vs {:?}", weights, expected);
+                        }
+                    }
+                    row_ab += setup.ab.step_x;
+                    row_bc += setup.bc.step_x;
+                    row_ca += setup.ca.step_x;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fused_projection_matches_a_plain_multiply_add() {
+        let resolution = Point2D { x: 1920.0, y: 1080.0 };
+        let scale = 935.3;
+        for view in [Point3D { x: 0.0, y: 0.0, z: 1.0 }, Point3D { x: 12.0, y: -4.0, z: 7.5 }, Point3D { x: -300.0, y: 250.0, z: 900.0 }] {
+            let screen = view_to_screen(view, resolution, scale);
+            let pixels_per_world_unit = scale / view.z;
+            let (x, y) = (view.x * pixels_per_world_unit + resolution.x * 0.5, view.y * pixels_per_world_unit + resolution.y * 0.5);
+            assert!((screen.x - x).abs() < 1e-3 && (screen.y - y).abs() < 1e-3, "{:?} vs ({}, {})", screen, x, y);
+            assert_eq!(screen.z, 1.0 / view.z);
+        }
+        // The center of view lands on the center of the screen, not scaled by anything
+        let center = view_to_screen(Point3D { x: 0.0, y: 0.0, z: 5.0 }, resolution, scale);
+        assert_eq!((center.x, center.y), (960.0, 540.0));
+    }
+
+    #[test]
+    fn ten_by_ten_grid_has_eleven_lines_each_way() {
+        let lines = grid_lines(10, 1.0, 0.0);
+        assert_eq!(lines.len(), 22);
+        let along_z = lines.iter().filter(|(start, end)| start.x == end.x).count();
+        assert_eq!(along_z, 11);
+        for (start, end) in &lines {
+            assert!(start.y == 0.0 && end.y == 0.0);
+            // Every segment spans the whole grid and stays inside it
+            assert_eq!((*end - *start).x.abs() + (*end - *start).z.abs(), 10.0);
+            assert!([start.x, start.z, end.x, end.z].iter().all(|v| v.abs() <= 5.0));
+        }
+    }
+
+    #[test]
+    fn six_tiles_are_exactly_six_that_partition_the_screen() {
+        // Widths that aren't multiples of 4 are fine, tiles are rasterized per pixel
+        for (width, height) in [(1920, 1080), (1366, 768), (101, 37)] {
+            let tiles = subdivide_count(width, height, 6);
+            assert_eq!(tiles.len(), 6, "{}x{}", width, height);
+            assert!(TileLayout::new(&tiles, width, height).partitions_screen(), "{}x{}", width, height);
+            let area: u64 = tiles.iter().map(|tile| tile.width() as u64 * tile.height() as u64).sum();
+            assert_eq!(area, width as u64 * height as u64);
+        }
+    }
+
+    #[test]
+    fn perspective_interp_at_the_screen_centroid_weights_by_inverse_depth() {
+        // Vertices at view z 1, 2 and 4, the fragment at the screen-space centroid sees the harmonic mean depth
+        let inv_depths = Point3D { x: 1.0, y: 0.5, z: 0.25 };
+        let weights = Point3D { x: 1.0 / 3.0, y: 1.0 / 3.0, z: 1.0 / 3.0 };
+        let depth = 1.0 / dot3(inv_depths, weights);
+        assert!((depth - 12.0 / 7.0).abs() < 1e-6);
+        // An attribute that is the vertex depth itself comes back as the fragment depth
+        assert!((perspective_interp(1.0, 2.0, 4.0, inv_depths, weights, depth) - depth).abs() < 1e-6);
+        // Not the affine average 70 / 3, the near vertex counts for more
+        let value = perspective_interp(10.0, 20.0, 40.0, inv_depths, weights, depth);
+        assert!((value - 120.0 / 7.0).abs() < 1e-5, "{value}");
+    }
+
+    #[test]
+    fn edge_setup_is_zero_along_each_edge_and_the_area_at_the_far_vertex() {
+        let (a, b, c) = (Point2D { x: 3.5, y: 2.0 }, Point2D { x: 1.25, y: 17.0 }, Point2D { x: 22.0, y: 9.5 });
+        let setup = EdgeSetup::new(a, b, c);
+        let midpoint = |p: Point2D, q: Point2D| Point2D { x: (p.x + q.x) * 0.5, y: (p.y + q.y) * 0.5 };
+        for (edge, start, end, opposite) in [(setup.ab, a, b, c), (setup.bc, b, c, a), (setup.ca, c, a, b)] {
+            assert_eq!(edge.eval(start), 0.0);
+            assert_eq!(edge.eval(end), 0.0);
+            assert!(edge.eval(midpoint(start, end)).abs() < 1e-4);
+            // Each edge function is the signed area of the triangle it makes with the point
+            assert!((edge.eval(opposite) - setup.area).abs() < 1e-3, "{} vs {}", edge.eval(opposite), setup.area);
+        }
+        assert!((setup.area - signed_triangle_area(a, b, c)).abs() < 1e-6);
+        assert!((setup.area * setup.inv_area - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::point3d::{self, Point3D};
+use crate::transform::Transform;
+use crate::triangle::Triangle3D;
+
+/// World-space debug segments from each triangle corner along its shading frame
+#[derive(Debug, Default)]
+pub struct FrameLines {
+    pub normals: Vec<(Point3D, Point3D)>,
+    pub tangents: Vec<(Point3D, Point3D)>,
+    pub bitangents: Vec<(Point3D, Point3D)>,
+}
+
+/// Normal, tangent and bitangent segments of `length` for every corner of every triangle placed by `instance`.
+/// Tangents come from the triangle's UV gradients orthogonalized against the corner normal, triangles with degenerate UVs only get normals
+pub fn vertex_frame_lines(triangles: &[Triangle3D], instance: &Transform, length: f32) -> FrameLines {
+    let mut lines = FrameLines::default();
+    for tri in triangles {
+        let uv_frame = uv_tangents(tri);
+        for (position, normal) in [(tri.a, tri.na), (tri.b, tri.nb), (tri.c, tri.nc)] {
+            let base = instance.to_world_point(position);
+            let n = point3d::normalize(normal);
+            lines.normals.push((base, base + point3d::normalize(instance.transform_direction(n)) * length));
+
+            if let Some((tangent, bitangent)) = uv_frame {
+                let t = point3d::normalize(tangent - n * point3d::dot3(n, tangent));
+                let b = point3d::normalize(bitangent - n * point3d::dot3(n, bitangent));
+                lines.tangents.push((base, base + instance.transform_direction(t) * length));
+                lines.bitangents.push((base, base + instance.transform_direction(b) * length));
+            }
+        }
+    }
+    lines
+}
+
+// Object-space directions of increasing u and v across the triangle
+fn uv_tangents(tri: &Triangle3D) -> Option<(Point3D, Point3D)> {
+    let (e1, e2) = (tri.b - tri.a, tri.c - tri.a);
+    let (duv1, duv2) = (tri.tb - tri.ta, tri.tc - tri.ta);
+    let det = duv1.x * duv2.y - duv2.x * duv1.y;
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let r = 1.0 / det;
+    Some(((e1 * duv2.y - e2 * duv1.y) * r, (e2 * duv1.x - e1 * duv2.x) * r))
+}
+
+/// World-space edges of every triangle placed by `instance`, only those using `material` when given.
+/// Edges shared by two triangles come out twice, which draws the same pixels
+pub fn wireframe_lines(triangles: &[Triangle3D], instance: &Transform, material: Option<usize>) -> Vec<(Point3D, Point3D)> {
+    triangles.iter()
+        .filter(|tri| material.is_none_or(|material| tri.material == material))
+        .flat_map(|tri| {
+            let (a, b, c) = (instance.to_world_point(tri.a), instance.to_world_point(tri.b), instance.to_world_point(tri.c));
+            [(a, b), (b, c), (c, a)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Camera, PitchMode};
+    use crate::geometry::vertex_to_screen;
+    use crate::obj::Vertex;
+    use crate::point2d::Point2D;
+
+    const IDENTITY: Transform = Transform { yaw: 0.0, pitch: 0.0, posistion: Point3D { x: 0.0, y: 0.0, z: 0.0 } };
+
+    #[test]
+    fn normal_gizmo_tips_move_along_the_normal_on_screen() {
+        let vertex = |x: f32, normal: Point3D| Vertex { position: Point3D { x, y: 0.0, z: 10.0 }, texcoord: Point2D { x: 0.0, y: 0.0 }, normal, alpha: 1.0 };
+        // First corner's normal points right (+x), the second's up (-y), the third's at the camera
+        let tri = Triangle3D::from_vertices(
+            vertex(-2.0, Point3D { x: 1.0, y: 0.0, z: 0.0 }),
+            vertex(0.0, Point3D { x: 0.0, y: -2.0, z: 0.0 }),
+            vertex(2.0, Point3D { x: 0.0, y: 0.0, z: -1.0 }),
+            0,
+        );
+        let lines = vertex_frame_lines(&[tri], &IDENTITY, 0.5);
+        assert_eq!(lines.normals.len(), 3);
+
+        let cam = Camera { fov: 90.0f32.to_radians(), camera_speed: 1.0, mouse_sensitivity: 0.0, transform: IDENTITY, pitch_mode: PitchMode::default() };
+        let resolution = Point2D { x: 64.0, y: 48.0 };
+        let scale = cam.scaled_inv_world_height(resolution.y);
+        let offsets: Vec<(f32, f32)> = lines.normals.iter().map(|&(base, tip)| {
+            let (base, tip) = (vertex_to_screen(base, &IDENTITY, &cam, resolution, scale), vertex_to_screen(tip, &IDENTITY, &cam, resolution, scale));
+            (tip.x - base.x, tip.y - base.y)
+        }).collect();
+        // Half a unit at depth 10 is 1.2 pixels at this scale, the normal's length doesn't matter
+        assert!((offsets[0].0 - 1.2).abs() < 1e-3 && offsets[0].1.abs() < 1e-3, "{:?}", offsets[0]);
+        assert!(offsets[1].0.abs() < 1e-3 && (offsets[1].1 + 1.2).abs() < 1e-3, "{:?}", offsets[1]);
+        // Toward the camera the tip grows away from the screen center, the vertex is right of it
+        assert!(offsets[2].0 > 0.0 && offsets[2].1.abs() < 1e-3, "{:?}", offsets[2]);
+    }
+}
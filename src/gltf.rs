@@ -0,0 +1,188 @@
+use anyhow::{Result, anyhow};
+
+use crate::error::RusterizerError;
+use crate::material::{BlendMode, Material};
+use crate::obj::Vertex;
+use crate::point2d::Point2D;
+use crate::point3d::{self, Point3D};
+use crate::render::Model;
+use crate::shading::linear_to_srgb;
+use crate::texture::{Texture, WrapMode};
+use crate::triangle::Triangle3D;
+
+// Column-major 4x4 matrix, glTF's layout for node transforms
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+/// Whether a model path should be loaded with load_gltf rather than as OBJ
+pub fn is_gltf_path(path: &str) -> bool {
+    let path = path.to_lowercase();
+    path.ends_with(".gltf") || path.ends_with(".glb")
+}
+
+/// Load every triangle mesh in a .gltf/.glb's default scene, placed by its node transforms, with base color textures.
+/// Materials are indexed like OBJ models, index 0 is the default material for primitives without one
+pub fn load_gltf(path: &str) -> std::result::Result<Model, RusterizerError> {
+    let (document, buffers, images) = ::gltf::import(path).map_err(|e| match e {
+        ::gltf::Error::Io(source) => RusterizerError::Io { path: path.to_string(), source },
+        e => RusterizerError::Gltf { path: path.to_string(), message: e.to_string() },
+    })?;
+    build_model(&document, &buffers, &images).map_err(|e| RusterizerError::Gltf { path: path.to_string(), message: e.to_string() })
+}
+
+fn build_model(document: &::gltf::Document, buffers: &[::gltf::buffer::Data], images: &[::gltf::image::Data]) -> Result<Model> {
+    let scene = document.default_scene().or_else(|| document.scenes().next()).ok_or_else(|| anyhow!("no scene"))?;
+
+    let mut materials = vec![Material::default()];
+    let mut textures = vec![Texture::solid(255, 255, 255, 255).into()];
+    for material in document.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let base_color = pbr.base_color_texture();
+        let wrap = match base_color.as_ref().map(|info| info.texture().sampler().wrap_s()) {
+            Some(::gltf::texture::WrappingMode::ClampToEdge) => WrapMode::Clamp,
+            _ => WrapMode::Repeat,
+        };
+        materials.push(Material {
+            name: material.name().unwrap_or("gltf").to_string(),
+            two_sided: material.double_sided(),
+            wrap,
+            // MASK is close enough to the alpha test every fragment already goes through
+            blend: if material.alpha_mode() == ::gltf::material::AlphaMode::Blend { BlendMode::Blend } else { BlendMode::Opaque },
+            ..Material::default()
+        });
+        // Untextured materials get their base color factor as a solid texture, the factor is linear and textures are sRGB
+        let [r, g, b, a] = pbr.base_color_factor();
+        let texture = match base_color {
+            Some(info) => image_texture(&images[info.texture().source().index()]),
+            None => Texture::solid(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), (a.clamp(0.0, 1.0) * 255.0).round() as u8),
+        };
+        textures.push(texture.into());
+    }
+
+    let mut triangles = Vec::new();
+    let mut pending: Vec<(::gltf::Node, Matrix)> = scene.nodes().map(|node| (node, IDENTITY)).collect();
+    while let Some((node, parent)) = pending.pop() {
+        let world = multiply(&parent, &node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                    eprintln!("Warning: skipping a glTF primitive drawn as {:?}, only triangle lists are supported", primitive.mode());
+                    continue
+                }
+                let material = primitive.material().index().map_or(0, |index| index + 1);
+                triangles.extend(primitive_triangles(&primitive, buffers, &world, material)?);
+            }
+        }
+        pending.extend(node.children().map(|child| (child, world)));
+    }
+
+    for (index, tri) in triangles.iter_mut().enumerate() {
+        tri.face = index as u32;
+    }
+    let mut model = Model::new(triangles, materials, textures);
+    model.sort_by_material();
+    Ok(model)
+}
+
+// One primitive's triangles in scene space
+fn primitive_triangles(primitive: &::gltf::Primitive, buffers: &[::gltf::buffer::Data], world: &Matrix, material: usize) -> Result<Vec<Triangle3D>> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()][..]));
+    let positions: Vec<Point3D> = reader.read_positions()
+        .ok_or_else(|| anyhow!("primitive without positions"))?
+        .map(|p| transform_point(world, Point3D { x: p[0], y: p[1], z: p[2] }))
+        .collect();
+    let normals: Option<Vec<Point3D>> = reader.read_normals()
+        .map(|normals| normals.map(|n| point3d::normalize(transform_direction(world, Point3D { x: n[0], y: n[1], z: n[2] }))).collect());
+    // glTF puts v = 0 at the top of the image, textures here have it at the bottom
+    let texcoords: Option<Vec<Point2D>> = reader.read_tex_coords(0)
+        .map(|texcoords| texcoords.into_f32().map(|t| Point2D { x: t[0], y: 1.0 - t[1] }).collect());
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+
+    let mut triangles = Vec::with_capacity(indices.len() / 3);
+    for corners in indices.chunks_exact(3) {
+        let corners = [corners[0] as usize, corners[1] as usize, corners[2] as usize];
+        if corners.iter().any(|&i| i >= positions.len()) {
+            return Err(anyhow!("index out of range of {} vertices", positions.len()));
+        }
+        // Without normals the triangle is shaded flat
+        let [a, b, c] = corners.map(|i| positions[i]);
+        let face_normal = point3d::normalize(point3d::cross3(b - a, c - a));
+        let vertex = |i: usize| Vertex {
+            position: positions[i],
+            texcoord: texcoords.as_ref().and_then(|t| t.get(i).copied()).unwrap_or(Point2D { x: 0.0, y: 0.0 }),
+            normal: normals.as_ref().and_then(|n| n.get(i).copied()).unwrap_or(face_normal),
+            alpha: 1.0,
+        };
+        triangles.push(Triangle3D::from_vertices(vertex(corners[0]), vertex(corners[1]), vertex(corners[2]), material));
+    }
+    Ok(triangles)
+}
+
+// Decoded glTF image to a texture, 16-bit and float images aren't supported and get the placeholder
+fn image_texture(image: &::gltf::image::Data) -> Texture {
+    use ::gltf::image::Format;
+    let rgba: Vec<u8> = match image.format {
+        Format::R8G8B8A8 => image.pixels.clone(),
+        Format::R8G8B8 => image.pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        Format::R8G8 => image.pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        Format::R8 => image.pixels.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+        format => {
+            eprintln!("Warning: glTF image format {:?} isn't supported, using placeholder", format);
+            return Texture::placeholder();
+        }
+    };
+    Texture::from_rgba_top_down(image.width, image.height, &rgba)
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0.0; 4]; 4];
+    for (col, out_col) in out.iter_mut().enumerate() {
+        for (row, value) in out_col.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[col][k]).sum();
+        }
+    }
+    out
+}
+
+fn transform_point(m: &Matrix, p: Point3D) -> Point3D {
+    transform_direction(m, p) + Point3D { x: m[3][0], y: m[3][1], z: m[3][2] }
+}
+
+// Upper 3x3 only, exact for normals under rotation and uniform scale which covers nearly every exported node
+fn transform_direction(m: &Matrix, d: Point3D) -> Point3D {
+    Point3D {
+        x: m[0][0] * d.x + m[1][0] * d.y + m[2][0] * d.z,
+        y: m[0][1] * d.x + m[1][1] * d.y + m[2][1] * d.z,
+        z: m[0][2] * d.x + m[1][2] * d.y + m[2][2] * d.z,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiny_cube_loads_its_triangles_and_texcoords() {
+        // Unit cube from (0, 0, 0) to (1, 1, 1), eight shared vertices each with uv (x, 0.25 + 0.5 z), no normals or materials
+        let model = load_gltf(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/cube.gltf")).unwrap();
+        assert_eq!(model.triangles.len(), 12);
+        assert!(model.triangles.iter().all(|tri| tri.material == 0));
+
+        let corner = Point3D { x: 1.0, y: 0.0, z: 0.0 };
+        let tri = model.triangles.iter().find(|tri| tri.a == corner).unwrap();
+        // v is flipped to put 0 at the bottom of the image
+        assert_eq!(tri.ta, Point2D { x: 1.0, y: 0.75 });
+
+        // Flat normals from the winding point out of the cube
+        let center = Point3D { x: 0.5, y: 0.5, z: 0.5 };
+        for tri in &model.triangles {
+            let centroid = (tri.a + tri.b + tri.c) * (1.0 / 3.0);
+            assert!(point3d::dot3(tri.na, centroid - center) > 0.0, "{:?}", tri);
+        }
+        assert!(is_gltf_path("Cube.GLB") && !is_gltf_path("cube.obj"));
+    }
+}
@@ -0,0 +1,34 @@
+#![feature(portable_simd)]
+
+pub mod point2d;
+pub mod point3d;
+pub mod triangle;
+pub mod screen;
+pub mod transform;
+pub mod texture;
+pub mod geometry;
+pub mod frustum;
+pub mod obj;
+pub mod gltf;
+pub mod material;
+pub mod rectangle;
+pub mod line;
+pub mod camera;
+pub mod timestep;
+pub mod screenshot;
+pub mod shading;
+pub mod light;
+pub mod gizmo;
+pub mod morph;
+pub mod skin;
+pub mod post;
+pub mod viewport;
+pub mod metrics;
+pub mod render;
+pub mod stereo;
+pub mod args;
+pub mod present;
+pub mod sequence;
+pub mod scene;
+pub mod error;
+pub mod debug;
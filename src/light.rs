@@ -0,0 +1,88 @@
+use raylib::prelude::*;
+
+use crate::camera::Camera;
+use crate::line::draw_line_aa;
+use crate::point2d::Point2D;
+use crate::point3d::{self, Point3D};
+use crate::screen::ScreenSpace;
+
+// Radians per second while an arrow key is held
+const LIGHT_TURN_SPEED: f32 = 1.5;
+
+/// Directional light stored as angles so it can be steered, azimuth turns around the vertical axis and elevation raises it toward -y (up)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    pub azimuth: f32,
+    pub elevation: f32,
+}
+
+impl DirectionalLight {
+    /// Unit vector pointing toward the light, what shading dots normals with
+    pub fn direction(&self) -> Point3D {
+        Point3D {
+            x: self.elevation.cos() * self.azimuth.sin(),
+            y: -self.elevation.sin(),
+            z: self.elevation.cos() * self.azimuth.cos(),
+        }
+    }
+
+    pub fn from_direction(direction: Point3D) -> Self {
+        let d = point3d::normalize(direction);
+        Self { azimuth: d.x.atan2(d.z), elevation: (-d.y).clamp(-1.0, 1.0).asin() }
+    }
+
+    /// Arrow keys steer the light, left/right for azimuth and up/down for elevation
+    pub fn update(&mut self, r1: &RaylibHandle) {
+        let turn = LIGHT_TURN_SPEED * r1.get_frame_time();
+        if r1.is_key_down(KeyboardKey::KEY_LEFT) {self.azimuth -= turn}
+        if r1.is_key_down(KeyboardKey::KEY_RIGHT) {self.azimuth += turn}
+        if r1.is_key_down(KeyboardKey::KEY_UP) {self.elevation += turn}
+        if r1.is_key_down(KeyboardKey::KEY_DOWN) {self.elevation -= turn}
+        self.elevation = self.elevation.clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+        self.azimuth = self.azimuth.rem_euclid(std::f32::consts::TAU);
+    }
+
+    /// Screen-space arrow from `center` toward the light as seen from the camera
+    pub fn draw_gizmo(&self, screen: &ScreenSpace, cam: &Camera, center: Point2D, length: f32, color: (u8, u8, u8)) {
+        let view = cam.transform.to_local_direction(self.direction());
+        let tip = Point2D { x: center.x + view.x * length, y: center.y + view.y * length };
+        draw_line_aa(screen, center, tip, color);
+
+        // Arrowhead, skipped when the light points along the view axis and the shaft has no direction on screen
+        let shaft = Point2D { x: view.x, y: view.y };
+        let shaft_len = (shaft.x * shaft.x + shaft.y * shaft.y).sqrt();
+        if shaft_len < 1e-3 {
+            return;
+        }
+        let back = shaft * (-1.0 / shaft_len);
+        let head = length * 0.25;
+        for angle in [0.5f32, -0.5] {
+            let (sin, cos) = angle.sin_cos();
+            let wing = Point2D { x: back.x * cos - back.y * sin, y: back.x * sin + back.y * cos };
+            draw_line_aa(screen, tip, tip + wing * head, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angles_round_trip_through_the_direction() {
+        for azimuth in [-3.0f32, -1.2, 0.0, 0.7, 2.5] {
+            for elevation in [-1.4f32, -0.3, 0.0, 0.9, 1.5] {
+                let light = DirectionalLight { azimuth, elevation };
+                let direction = light.direction();
+                assert!((point3d::dot3(direction, direction) - 1.0).abs() < 1e-5);
+                let back = DirectionalLight::from_direction(direction);
+                assert!((back.azimuth - azimuth).abs() < 1e-4 && (back.elevation - elevation).abs() < 1e-4, "{:?} from {:?}", back, light);
+            }
+        }
+        // Positive elevation raises the light, which is -y
+        assert!(DirectionalLight { azimuth: 0.0, elevation: 0.5 }.direction().y < 0.0);
+        // Unnormalized directions give the same angles
+        let d = Point3D { x: 3.0, y: -4.0, z: 5.0 };
+        assert_eq!(DirectionalLight::from_direction(d), DirectionalLight::from_direction(d * 10.0));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::point2d::Point2D;
+use crate::point3d::Point3D;
+use crate::screen::ScreenSpace;
+
+/// Aliased line with Bresenham's algorithm, endpoints are rounded to the nearest pixel
+pub fn draw_line(screen: &ScreenSpace, start: Point2D, end: Point2D, color: (u8, u8, u8)) {
+    let (mut x0, mut y0) = (start.x.round() as i32, start.y.round() as i32);
+    let (x1, y1) = (end.x.round() as i32, end.y.round() as i32);
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    loop {
+        plot(screen, x0, y0, color, 1.0);
+        if x0 == x1 && y0 == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x0 += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y0 += sy;
+        }
+    }
+}
+
+/// Anti-aliased line with Xiaolin Wu's algorithm, each step blends the two pixels straddling the line by coverage
+pub fn draw_line_aa(screen: &ScreenSpace, start: Point2D, end: Point2D, color: (u8, u8, u8)) {
+    let (mut x0, mut y0, mut x1, mut y1) = (start.x, start.y, end.x, end.y);
+    // Walk along the major axis, swap so it's always x
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if steep {
+        std::mem::swap(&mut x0, &mut y0);
+        std::mem::swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        std::mem::swap(&mut x0, &mut x1);
+        std::mem::swap(&mut y0, &mut y1);
+    }
+    let dx = x1 - x0;
+    let gradient = if dx == 0.0 { 1.0 } else { (y1 - y0) / dx };
+
+    // Plot with the axes swapped back for steep lines
+    let plot_major = |major: i32, minor: i32, coverage: f32| {
+        if steep { plot(screen, minor, major, color, coverage) } else { plot(screen, major, minor, color, coverage) }
+    };
+
+    // Endpoints are weighted by how much of their pixel the line covers along the major axis
+    let x_start = x0.round();
+    let y_start = y0 + gradient * (x_start - x0);
+    let gap_start = 1.0 - fpart(x0 + 0.5);
+    plot_major(x_start as i32, y_start.floor() as i32, (1.0 - fpart(y_start)) * gap_start);
+    plot_major(x_start as i32, y_start.floor() as i32 + 1, fpart(y_start) * gap_start);
+
+    let x_end = x1.round();
+    let y_end = y1 + gradient * (x_end - x1);
+    let gap_end = fpart(x1 + 0.5);
+    plot_major(x_end as i32, y_end.floor() as i32, (1.0 - fpart(y_end)) * gap_end);
+    plot_major(x_end as i32, y_end.floor() as i32 + 1, fpart(y_end) * gap_end);
+
+    let mut intersect_y = y_start + gradient;
+    for x in (x_start as i32 + 1)..(x_end as i32) {
+        let y = intersect_y.floor();
+        let frac = intersect_y - y;
+        plot_major(x, y as i32, 1.0 - frac);
+        plot_major(x, y as i32 + 1, frac);
+        intersect_y += gradient;
+    }
+}
+
+// Distance above the floor, f32::fract is negative for negative values and would give the pixel below negative coverage
+#[inline(always)]
+fn fpart(value: f32) -> f32 {
+    value - value.floor()
+}
+
+/// Depth tested line, endpoints are screen positions with inverted view depth in z as vertex_to_screen produces, depth is read but not written.
+/// `bias` pulls the line toward the camera by that fraction of its depth, so edges lying on a surface win the depth test against it
+pub fn draw_line_depth(screen: &ScreenSpace, start: Point3D, end: Point3D, color: (u8, u8, u8), bias: f32) {
+    let Some((t0, t1)) = clip_to_screen(screen, start, end) else { return };
+    let delta = end - start;
+    let (p0, p1) = (start + delta * t0, start + delta * t1);
+
+    // One step per pixel along the major axis, inverted depth is linear in screen space so it's interpolated directly
+    let steps = (p1.x - p0.x).abs().max((p1.y - p0.y).abs()).ceil().max(1.0);
+    let step = (p1 - p0) * (1.0 / steps);
+    let mut p = p0;
+    for _ in 0..=steps as u32 {
+        let (x, y) = (p.x.floor(), p.y.floor());
+        let depth = (1.0 - bias) / p.z;
+        if x >= 0.0 && y >= 0.0 && depth <= screen.get_depth(x as u32, y as u32) {
+            screen.unsafe_set_pixel(x as u32, y as u32, color.0, color.1, color.2, 255);
+        }
+        p += step;
+    }
+}
+
+// Liang-Barsky clip of the segment's parameter range to the screen, keeps near-plane clipped lines from walking millions of offscreen pixels
+fn clip_to_screen(screen: &ScreenSpace, start: Point3D, end: Point3D) -> Option<(f32, f32)> {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for (p, q) in [
+        (-dx, start.x),
+        (dx, screen.width as f32 - start.x),
+        (-dy, start.y),
+        (dy, screen.height as f32 - start.y),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = q / p;
+        if p < 0.0 { t0 = t0.max(t) } else { t1 = t1.min(t) }
+    }
+    (t0 <= t1).then_some((t0, t1))
+}
+
+// Negative coordinates would wrap when cast to u32, drop them here, the screen drops the rest
+#[inline(always)]
+fn plot(screen: &ScreenSpace, x: i32, y: i32, color: (u8, u8, u8), coverage: f32) {
+    if x < 0 || y < 0 {
+        return;
+    }
+    screen.unsafe_blend_pixel(x as u32, y as u32, color.0, color.1, color.2, coverage);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(screen: &ScreenSpace, x: u32, y: u32) -> u8 {
+        screen.get_pixel(x, y).0
+    }
+
+    #[test]
+    fn wu_lines_split_brightness_between_straddled_pixels() {
+        // A quarter pixel below row 4, white over black
+        let screen = ScreenSpace::new(32, 16);
+        draw_line_aa(&screen, Point2D { x: 2.0, y: 4.25 }, Point2D { x: 20.0, y: 4.25 }, (255, 255, 255));
+        assert_eq!((gray(&screen, 10, 4), gray(&screen, 10, 5)), (191, 64));
+        assert_eq!(gray(&screen, 10, 3), 0);
+    }
+
+    #[test]
+    fn wu_endpoints_above_the_screen_keep_positive_coverage() {
+        // Three quarters of the line's weight falls on row 0, the rest on row -1 which is dropped
+        let screen = ScreenSpace::new(32, 16);
+        draw_line_aa(&screen, Point2D { x: 2.0, y: -0.25 }, Point2D { x: 20.0, y: -0.25 }, (255, 255, 255));
+        assert_eq!(gray(&screen, 10, 0), 191);
+        // The start pixel is half covered along x
+        assert_eq!(gray(&screen, 2, 0), 96);
+        // Negative x endpoints get their gap from the floor too
+        let screen = ScreenSpace::new(32, 16);
+        draw_line_aa(&screen, Point2D { x: -3.25, y: 4.0 }, Point2D { x: 10.0, y: 4.0 }, (255, 255, 255));
+        assert!(gray(&screen, 5, 4) == 255 && gray(&screen, 0, 4) == 255);
+    }
+}
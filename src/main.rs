@@ -1,106 +1,200 @@
-#![feature(portable_simd)]
-
 // External crates
 use raylib::prelude::*;
-use rayon::prelude::*;
 use plotters::prelude::*;
 use plotters::style::Color;
 
 // STD library
-use std::time::Instant;
+use std::collections::VecDeque;
 use std::path::Path;
-use std::cell::UnsafeCell;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
 
 // Internal modules
-mod point2d;
-mod point3d;
-mod triangle;
-mod screen;
-mod transform;
-mod texture;
-mod geometry;
-mod obj;
-mod rectangle;
-mod camera;
+use rusterizer::{point3d, transform, texture, obj, gltf};
 
 // Internal imports
-use crate::rectangle::compute_subdivisions;
-use crate::screen::ScreenSpace;
-use crate::geometry::{draw_rectangles, vertex_to_screen, inv_triangle_area, point_in_triangle, subdivide};
-use crate::triangle::Triangle3D;
-use crate::point2d::Point2D;
-use crate::point3d::{Point3D, dot3};
-use crate::camera::Camera;
-
-
-fn depth_to_u8(depth: f32) -> u8 {
-        if depth <= 0.0 {
-                return 255
-        }
-        let y = 255.0 * ((-depth / 10.0) + 1.0).exp();
-        y.round().clamp(0.0, 255.0) as u8
-}
-
-fn shade_pixel(r: u8, g: u8, b: u8, a: u8, normal: point3d::Point3D, light: point3d::Point3D) -> (u8, u8, u8, u8) {
-        let normalized_normal = point3d::normalize(normal); //unit vector
-        let normalized_light = point3d::normalize(light);
-        let intensity = (point3d::dot3(normalized_normal, normalized_light) + 1.0) * 0.5;
-        (((r as f32) * intensity) as u8, ((g as f32) * intensity) as u8, ((b as f32) * intensity) as u8, a)
-}
+use rusterizer::geometry::{SamplePattern, grid_lines, subdivide_count};
+use rusterizer::render::{DrawStats, Model, Rasterizer, RenderCache};
+use rusterizer::point2d::Point2D;
+use rusterizer::point3d::Point3D;
+use rusterizer::rectangle::Rect;
+use rusterizer::camera::{Camera, PitchMode};
+use rusterizer::light::DirectionalLight;
+use rusterizer::timestep::FixedTimestep;
+use rusterizer::screen::{Backdrop, ColorKey, ScreenSpace};
+use rusterizer::screenshot::next_screenshot_filename;
+use rusterizer::shading::Fog;
+use rusterizer::metrics::{FrameTimer, MetricBuffer, TileController, micros, recommend_tile_count};
+use rusterizer::stereo::{StereoMode, StereoRig};
+use rusterizer::args::Args;
+use rusterizer::gizmo::{vertex_frame_lines, wireframe_lines};
+use rusterizer::morph::Morph;
+use rusterizer::skin::{self, BoneMatrix, Skin};
+use rusterizer::present::spawn_render_thread;
+use rusterizer::post::ColorGrade;
+use rusterizer::obj::{ObjLoadOptions, ObjMesh, UpAxis, UvGenMode};
+use rusterizer::scene::Scene;
+use rusterizer::viewport::{Viewport, ViewportFit};
+use rusterizer::error::RusterizerError;
+use rusterizer::debug::DebugOutput;
+use rusterizer::texture::TextureFilter;
+use rusterizer::sequence::{Sequence, render_frames};
+
+// Simulation rate for camera movement, independent of the render frame rate
+const SIM_RATE_HZ: f32 = 240.0;
+// Frames of timing history kept for the exit plot, about 40 seconds at 240 fps
+const METRIC_HISTORY: usize = 10_000;
+// Recent frames of per-tile times kept for the tile count recommendation at exit
+const TILE_PROFILE_FRAMES: usize = 120;
+// Finished frames the render thread may queue ahead of presentation, more evens out frame time spikes but adds latency
+const FRAMES_IN_FLIGHT: usize = 2;
+// Range --adaptive-tiles may move the tile count in, per logical core
+const ADAPTIVE_MIN_TILES_PER_CORE: usize = 1;
+const ADAPTIVE_MAX_TILES_PER_CORE: usize = 16;
+// Stereo eye distance in world units
+const EYE_SEPARATION: f32 = 6.4;
+// Length of the captured debug ray in world units
+const DEBUG_RAY_LENGTH: f32 = 1000.0;
+// Length of the per-vertex normal/tangent/bitangent gizmos in world units
+const FRAME_GIZMO_LENGTH: f32 = 2.0;
+// View depth mapped to white in the 16-bit depth export
+const DEPTH_EXPORT_FAR: f32 = 1000.0;
+// Ambient change per [ or ] press
+const AMBIENT_STEP: f32 = 0.05;
+// Exposure change in stops per - or = press
+const EXPOSURE_STEP: f32 = 0.25;
+// --render-sequence length and rate when not given
+const DEFAULT_SEQUENCE_SECONDS: f32 = 4.0;
+const DEFAULT_SEQUENCE_FPS: f32 = 30.0;
+// Wireframe overlay edges, pulled toward the camera by this fraction of their depth so they beat the surface they lie on
+const WIREFRAME_COLOR: (u8, u8, u8) = (255, 170, 40);
+const WIREFRAME_DEPTH_BIAS: f32 = 0.002;
+// --color-key distance (RGB, 0-255 units) keyed fully transparent, and the feather beyond it for antialiased edges
+const COLOR_KEY_TOLERANCE: f32 = 12.0;
+const COLOR_KEY_SOFTNESS: f32 = 60.0;
+
+// World-space line segments and the color to draw them in
+type Overlay<'a> = (&'a [(Point3D, Point3D)], (u8, u8, u8), f32);
 
 fn main() {
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("Usage: rusterizer [--flip-v] [--fix-winding] [--z-up] [--recenter] [--recompute-normals smooth|groups] [--split-triangles <area>] [--debug-output] [--morph-target <obj>] [--bone-weights <file>] [--adaptive-tiles <fps>] [--scene <json>] [--color-key <r,g,b>] [--spin <deg/s>] [--render-sequence <dir> [--sequence-seconds <s>] [--sequence-fps <n>]]");
+        std::process::exit(2);
+    });
+
     let cores = num_cpus::get();
     println!("Number of logical CPU cores: {}", cores);
     
     // Build thread pool based on number of cores
-    rayon::ThreadPoolBuilder::new()
+    exit_on_error(rayon::ThreadPoolBuilder::new()
         .num_threads(cores)
         .build_global()
-        .unwrap();
+        .map_err(|source| RusterizerError::ThreadPool { source }));
 
     // Define Render resolution
     let width = 1920;
     let height = 1080;
     let resolution = Point2D { x: width as f32, y: height as f32 };
 
-    // Compute depth to subdivide screen for given cores
-    let depth = compute_subdivisions(cores);
-    // Additional depth so threads can steal work if there are regions onscreen with less geometry
-    let rects = subdivide(width, height,depth+1);
+    // Two tiles per core so threads can steal work if there are regions onscreen with less geometry
+    let rects = subdivide_count(width, height, cores * 2);
     println!("Rectangle dimensions for threads: {:?}", rects);
 
-    for rect in &rects {
-        if ((rect.min_x - rect.max_x) % 4) != 0 {
-            println!("Rectangle dimensions invalid!");
-            panic!();
-        }
-    }
-
-    // Create multiple 'sub-screenspaces' for each thread to work and join later
-    let mut rect_buffers: Vec<ScreenSpace> = rects
-        .iter()
-        .map(|rect| {
-            ScreenSpace {
-                rect: *rect,
-                width: rect.width(),
-                height: rect.height(),
-                rgba: UnsafeCell::new(vec![0; (rect.width() * rect.height() * 4) as usize]),
-                depth: UnsafeCell::new(vec![f32::INFINITY; (rect.width() * rect.height()) as usize]),
-            }
-        })
-        .collect();
-    
     // Visualize screenSpace split
-    draw_rectangles(&rects, width, height, "rectangles.png");
-    println!("Saved rectangles.png");
+    let debug = DebugOutput::new(args.debug_output, ".");
+    save_tile_layout(&debug, &rects, width, height);
     
     // Load .obj file and texture file
-    let (positions, texcoords, normals, faces) = obj::parse_obj("socrates.obj").expect(".obj file parsing failed");
-    let triangles = obj::fan_triangulate_faces(&faces, &positions, &texcoords, &normals);
-    let obj_texture = texture::Texture::load("socrates.png").expect("texture image file parsing failed");
+    let scene = match &args.scene {
+        Some(path) => exit_on_error(Scene::load(path)),
+        None => Scene::default(),
+    };
+
+    let load_options = ObjLoadOptions {
+        recenter: args.recenter,
+        fix_winding: args.fix_winding,
+        up_axis: if args.z_up { UpAxis::Z } else { UpAxis::Y },
+        recompute_normals: args.recompute_normals,
+    };
+    let (mut model, mesh) = exit_on_error(Model::load(&scene.model, &scene.texture, &load_options, args.flip_v, args.split_triangles));
+    if model.triangles.is_empty() {
+        eprintln!("Warning: {} has no faces, nothing will be drawn", scene.model);
+    }
+
+    // Optional blend shape, the model oscillates between its own shape and the target's
+    let morph = args.morph_target.as_ref().and_then(|path| {
+        let Some(mesh) = &mesh else {
+            eprintln!("Warning: morph targets need an .obj model, ignoring {}", path);
+            return None;
+        };
+        if args.split_triangles.is_some() {
+            eprintln!("Warning: --split-triangles doesn't apply to a morphing model, its triangles are used as loaded");
+        }
+        let target = exit_on_error(obj::load_obj(path, &load_options));
+        let morph = exit_on_error(Morph::new(mesh, &target, args.flip_v, UvGenMode::Triplanar)
+            .map_err(|e| RusterizerError::Morph { path: path.clone(), message: e.to_string() }));
+        model.triangles = morph.base.clone();
+        model.update_bounds();
+        Some(morph)
+    });
+
+    // Optional skinning onto the stand-in bone animation, a morph already owns the triangles
+    let skin = args.bone_weights.as_ref().and_then(|path| {
+        let Some(mesh) = mesh.as_ref().filter(|_| morph.is_none()) else {
+            eprintln!("Warning: bone weights need an .obj model without a morph target, ignoring {}", path);
+            return None;
+        };
+        if args.split_triangles.is_some() {
+            eprintln!("Warning: --split-triangles doesn't apply to a skinned model, its triangles are used as loaded");
+        }
+        let skin_error = |e: anyhow::Error| RusterizerError::Skin { path: path.clone(), message: e.to_string() };
+        let weights = exit_on_error(skin::load_weights(path, mesh.positions.len()).map_err(skin_error));
+        let skin = exit_on_error(Skin::new(mesh, &weights, args.flip_v, UvGenMode::Triplanar).map_err(skin_error));
+        model.triangles = skin.base.clone();
+        model.update_bounds();
+        Some(skin)
+    });
+
+    // Create main screenspace split into per-thread tiles
+    let rasterizer = Rasterizer::new(width, height, rects);
+
+    // Initial conditions for objects
+    let mut transformation = transform::Transform { yaw: 0.0, pitch: 0.0, posistion: point3d::Point3D { x: 0.0, y: 0.0, z: 0.0 } };
+    let initial_transform = scene.object.to_transform();
+    let mut new_yaw: f32 = initial_transform.yaw;
+    let new_pitch: f32 = initial_transform.pitch;
+    // A recentered mesh is moved back to its file coordinates
+    let new_posistion = initial_transform.posistion + mesh.as_ref().map_or(Point3D { x: 0.0, y: 0.0, z: 0.0 }, ObjMesh::origin_offset);
+    
+    // Initial conditions for camera
+    let mut cam: Camera = scene.camera.to_camera();
+    let mut timestep = FixedTimestep::new(SIM_RATE_HZ);
+    let mut previous_cam_posistion = cam.transform.posistion;
+
+    // Light steered with the arrow keys
+    let mut light = scene.light();
+    // Gray ambient level, adjusted with [ and ]
+    let mut ambient: f32 = scene.settings.ambient;
+    // Turntable rate in radians per second
+    let spin = args.spin.unwrap_or(scene.settings.spin).to_radians();
+    // Final color grade, exposure adjusted with - and =
+    let mut grade = ColorGrade { exposure: scene.settings.exposure, ..ColorGrade::default() };
+
+    let backdrop = scene.backdrop();
+    let color_key = args.color_key.map(|color| ColorKey { color, tolerance: COLOR_KEY_TOLERANCE, softness: COLOR_KEY_SOFTNESS });
+
+    // Offline turntable, no window needed
+    if let Some(dir) = &args.render_sequence {
+        let sequence = Sequence::new(args.sequence_seconds.unwrap_or(DEFAULT_SEQUENCE_SECONDS), args.sequence_fps.unwrap_or(DEFAULT_SEQUENCE_FPS));
+        transformation.update_transform(new_yaw, new_pitch, new_posistion);
+        if let Err(e) = render_sequence(Path::new(dir), &sequence, &rasterizer, &mut model, morph.as_ref(), skin.as_ref(), backdrop.as_ref(), transformation, &cam, light.direction()) {
+            eprintln!("Failed to render sequence: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    // Create main screenspace
-    let mut screen = screen::ScreenSpace::new(width, height);
     let image = raylib::prelude::Image::gen_image_color(width as i32, height as i32,raylib::prelude::Color::BLACK);
 
     // Create raylib handle
@@ -110,196 +204,588 @@ fn main() {
         .resizable()
         .build();
     r1.set_target_fps(240);
-    let mut texture = r1.load_texture_from_image(&thread, &image).expect("raylib texture loading failed");
-    
-    // Initial conditions for objects
-    let mut transformation = transform::Transform { yaw: 0.0, pitch: 0.0, posistion: point3d::Point3D { x: 0.0, y: 0.0, z: 0.0 } };
-    let mut new_yaw: f32 = 90.0_f32.to_radians();
-    let new_pitch: f32 = 180.0_f32.to_radians();
-    let mut new_posistion = point3d::Point3D { x: 0.0, y: 55.0, z: 300.0 };
-    
-    // Initial conditions for camera
-    let mut cam: Camera = Camera { fov: 30.0_f32.to_radians(), camera_speed: 1.0, mouse_sensitivity: 0.002, transform: transform::Transform { yaw: 0.0, pitch: 0.0, posistion: point3d::Point3D { x: 0.0, y: 0.0, z: 0.0 }} };
-
-    // Vectors to store timing metrics
-    let mut transform_times: Vec<f64> = Vec::new();
-    let mut triangle_times: Vec<f64> = Vec::new();
-    let mut merge_times: Vec<f64> = Vec::new();
-    let mut frame_times: Vec<f64> = Vec::new();
+    let mut texture = exit_on_error(r1.load_texture_from_image(&thread, &image).map_err(|e| RusterizerError::Window { message: e.to_string() }));
+
+    // Distance fog, toggled with F
+    let mut fog_enabled = scene.settings.fog;
+    // Ground grid for orientation, toggled with G
+    let mut grid_enabled = scene.settings.grid;
+
+    // Ray through the cursor captured with C, drawn until captured again so it can be inspected from elsewhere
+    let mut debug_ray: Option<(Point3D, Point3D)> = None;
+
+    // Per-vertex normal (blue), tangent (red) and bitangent (green) gizmos, toggled with N
+    let mut frame_gizmos_enabled = false;
+    let mut wireframe_enabled = false;
+    // How the frame is fit into the window, L switches to stretching
+    let mut viewport_fit = ViewportFit::default();
+    // Stereo modes cycled with V, side-by-side renders each eye at half width and anaglyph at full size
+    let mut stereo_mode = StereoMode::Off;
+    let mut texture_filter = if scene.settings.bilinear { TextureFilter::Bilinear } else { TextureFilter::Nearest };
+    let mut triangle_colors = false;
+    let mut edge_aa = false;
+
+    // Bounded timing history, R clears it to profile a specific stretch of the session
+    let mut transform_times = MetricBuffer::new(METRIC_HISTORY);
+    let mut triangle_times = MetricBuffer::new(METRIC_HISTORY);
+    let mut merge_times = MetricBuffer::new(METRIC_HISTORY);
+    let mut frame_times = MetricBuffer::new(METRIC_HISTORY);
+
+    // Everything that draws moves to the render thread, this thread only handles input and presents finished frames
+    // so a slow frame doesn't hold up the camera or the keys
+    let mut worker = RenderWorker {
+        tile_controller: args.adaptive_tiles.map(|fps| TileController::new(
+            rasterizer.tiles.len(),
+            cores * ADAPTIVE_MIN_TILES_PER_CORE,
+            cores * ADAPTIVE_MAX_TILES_PER_CORE,
+            Duration::from_secs_f32(1.0 / fps),
+        )),
+        tile_profile: VecDeque::with_capacity(TILE_PROFILE_FRAMES),
+        rasterizer,
+        side_by_side: StereoRig::new(width, height, cores * 2, EYE_SEPARATION),
+        anaglyph: StereoRig::with_eye_size(width, height, cores * 2, EYE_SEPARATION),
+        animated: morph.is_some() || skin.is_some() || model.materials.iter().any(|material| material.uv_velocity != Point2D { x: 0.0, y: 0.0 }),
+        model,
+        morph,
+        skin,
+        backdrop,
+        fog: Fog { color: (140, 150, 165), start: 250.0, end: 500.0 },
+        grid: grid_lines(32, 25.0, scene.settings.grid_height),
+        render_cache: RenderCache::new(),
+        debug: debug.clone(),
+        camera_inside: false,
+        picked_point: None,
+    };
+    let (requests, request_rx) = mpsc::channel();
+    let (render_thread, frames) = spawn_render_thread(FRAMES_IN_FLIGHT, move |producer| {
+        while let Some(request) = next_frame_request(&request_rx, &mut worker) {
+            if let Some(stats) = worker.render(&request) {
+                return Some((producer.copy_screen(&worker.rasterizer.screen), stats));
+            }
+        }
+        worker.report_tile_recommendation();
+        None
+    });
 
+    let start_time = std::time::Instant::now();
     while !r1.window_should_close() {
         if r1.is_key_pressed(raylib::consts::KeyboardKey::KEY_ESCAPE) {
             break;
         }
 
-        let frame_start = std::time::Instant::now();
-
-        cam.camera_update(&r1);
-                    
-        // Clear buffers each frame
-/* 
-        for thread_buf in &mut rect_buffers {
-            thread_buf.clear(0,0,0,255);
-        }
-*/
-       
-        screen.clear(0, 0, 0, 255);
-        
-        //new_yaw += 0.01;
-        let lighting_direction = transformation.transform_direction(Point3D { x: -1.0, y: 0.0, z: 0.0 });
-        
-        let world_height = (cam.fov * 0.5).tan() * 2.0;
-        let scaled_inv_world_height = resolution.y / world_height;
-
-        transformation.update_transform(new_yaw, new_pitch, new_posistion);
-        
-        let screenspacetriangles: Vec<triangle::Triangle3D> = triangles
-            .par_iter() // parallel iterator instead of .iter()
-            .map(|tri| {
-
-                let sa = vertex_to_screen(tri.a, &transformation, &cam, resolution, scaled_inv_world_height);
-                let sb = vertex_to_screen(tri.b, &transformation, &cam, resolution, scaled_inv_world_height);
-                let sc = vertex_to_screen(tri.c, &transformation, &cam, resolution, scaled_inv_world_height);
-
-                let min_x = sa.x.min(sb.x).min(sc.x);
-                let min_y = sa.y.min(sb.y).min(sc.y);
-                let max_x = sa.x.max(sb.x).max(sc.x);
-                let max_y = sa.y.max(sb.y).max(sc.y);
-
-                let block_start_x = (min_x.floor() as u32).clamp(0, screen.width - 1);
-                let block_start_y = (min_y.floor() as u32).clamp(0, screen.height - 1);
-                let block_end_x = (max_x.ceil() as u32).clamp(0, screen.width - 1);
-                let block_end_y = (max_y.ceil() as u32).clamp(0, screen.height - 1);
-
-                Triangle3D {
-                    a: sa,
-                    b: sb,
-                    c: sc,
-                    ta: tri.ta,
-                    tb: tri.tb,
-                    tc: tri.tc,
-                    na: tri.na,
-                    nb: tri.nb,
-                    nc: tri.nc,
-                    bb_start_x: block_start_x,
-                    bb_start_y: block_start_y,
-                    bb_end_x: block_end_x,
-                    bb_end_y: block_end_y,
-                }
-            })
-            .collect();
-        
-        let transform_time = frame_start.elapsed();
-        let triangle_start = Instant::now();
-        
-        // Look into alternatives that let us use unsafe buffer access accross threeads since we can guarantee no collisions
-        rect_buffers.par_iter_mut().for_each(|rect_s| {
-            for tri in screenspacetriangles.iter() {
-                let (area, inv_area) = inv_triangle_area(
-                    Point2D { x: tri.a.x, y: tri.a.y }, 
-                    Point2D { x: tri.b.x, y: tri.b.y }, 
-                    Point2D { x: tri.c.x, y: tri.c.y }, 
-                );
-                // Use pre-computed bounding boxes + bounds of current thread rectangle
-                for y in tri.bb_start_y.max(rect_s.rect.min_y)..tri.bb_end_y.min(rect_s.rect.max_y) {
-                    for x in tri.bb_start_x.max(rect_s.rect.min_x)..tri.bb_end_x.min(rect_s.rect.max_x) {
-                        let p = Point2D {
-                            x: x as f32 + 0.5,
-                            y: y as f32 + 0.5,
-                        };
-                        let mut weights: Point3D = Point3D { x: 0.0, y: 0.0, z: 0.0 };
-
-                        if point_in_triangle(
-                            Point2D { x: tri.a.x, y: tri.a.y }, 
-                            Point2D { x: tri.b.x, y: tri.b.y }, 
-                            Point2D { x: tri.c.x, y: tri.c.y },
-                            p, 
-                            area,
-                            inv_area,
-                            &mut weights
-                        ) {
-                            let depths: Point3D = Point3D { x: tri.a.z, y: tri.b.z, z: tri.c.z };
-                            let depth: f32 = 1.0 / dot3(depths, weights);
-                            
-                            if depth > screen.get_depth(x, y) {
-                                continue;
-                            }
-
-                            let texture_coord: Point2D = Point2D { 
-                                x: dot3(Point3D { x: tri.ta.x * depths.x, y: tri.tb.x * depths.y, z: tri.tc.x * depths.z }, weights), 
-                                y: dot3(Point3D { x: tri.ta.y * depths.x, y: tri.tb.y * depths.y, z: tri.tc.y * depths.z }, weights),
-                            } * depth;
-
-                            let normal: Point3D = Point3D { 
-                                x: dot3(Point3D { x: tri.na.x * depths.x, y: tri.nb.x * depths.y, z: tri.nc.x * depths.z }, weights), 
-                                y: dot3(Point3D { x: tri.na.y * depths.x, y: tri.nb.y * depths.y, z: tri.nc.y * depths.z }, weights),
-                                z: dot3(Point3D { x: tri.na.z * depths.x, y: tri.nb.z * depths.y, z: tri.nc.z * depths.z }, weights),
-                            } * depth;
-
-                            screen.unsafe_set_depth(x, y, depth);
-
-                            let show_depth: bool = false;
-                            if show_depth {
-                                let depth_gray: u8 = depth_to_u8(depth);
-                                screen.unsafe_set_pixel(x, y, depth_gray, depth_gray, depth_gray, 255);
-                            } else {
-                                let (r,g,b,a) = obj_texture.sample(texture_coord.x, texture_coord.y);
-                                let (r,g,b,a) = shade_pixel(r, g, b, a, normal, lighting_direction );
-                                screen.unsafe_set_pixel(x, y, r, g, b, a);
-                            }
+        let mut timer = FrameTimer::new();
+
+        // Dropping an .obj or glTF replaces the model, dropping anything else is tried as a texture for every material.
+        // Files load here and are handed over whole, the render thread picks them up before its next frame
+        if r1.is_file_dropped() {
+            for path in r1.load_dropped_files().paths() {
+                if path.to_lowercase().ends_with(".obj") || gltf::is_gltf_path(path) {
+                    match Model::load(path, &scene.texture, &load_options, args.flip_v, args.split_triangles) {
+                        Ok((loaded, _)) => {
+                            println!("Loaded {} ({} triangles)", path, loaded.triangles.len());
+                            let _ = requests.send(RenderMessage::Command(RenderCommand::LoadModel(loaded)));
+                        }
+                        Err(e) => eprintln!("{}", e),
+                    }
+                } else {
+                    match texture::Texture::open(path) {
+                        Ok(texture) => {
+                            println!("Loaded texture {}", path);
+                            let _ = requests.send(RenderMessage::Command(RenderCommand::ReplaceDiffuse(texture)));
                         }
+                        Err(e) => eprintln!("{}", e),
                     }
                 }
             }
-        });
-        let triangle_time = triangle_start.elapsed();
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_F) {
+            fog_enabled = !fog_enabled;
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_G) {
+            grid_enabled = !grid_enabled;
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET) || r1.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) {
+            let step = if r1.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) { AMBIENT_STEP } else { -AMBIENT_STEP };
+            ambient = (ambient + step).clamp(0.0, 1.0);
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_P) {
+            cam.pitch_mode = if cam.pitch_mode == PitchMode::Free { PitchMode::default() } else { PitchMode::Free };
+            // Pull an upside-down camera back into the clamp right away rather than on the next drag
+            cam.rotate(0.0, 0.0);
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_MINUS) {
+            grade.exposure -= EXPOSURE_STEP;
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_EQUAL) {
+            grade.exposure += EXPOSURE_STEP;
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_B) {
+            texture_filter = if texture_filter == TextureFilter::Nearest { TextureFilter::Bilinear } else { TextureFilter::Nearest };
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_T) {
+            triangle_colors = !triangle_colors;
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_M) {
+            edge_aa = !edge_aa;
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_L) {
+            viewport_fit = if viewport_fit == ViewportFit::Letterbox { ViewportFit::Stretch } else { ViewportFit::Letterbox };
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_O) {
+            wireframe_enabled = !wireframe_enabled;
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_N) {
+            frame_gizmos_enabled = !frame_gizmos_enabled;
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_V) {
+            stereo_mode = stereo_mode.next();
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_R) {
+            transform_times.clear();
+            triangle_times.clear();
+            merge_times.clear();
+            frame_times.clear();
+        }
+
+        // Look every frame, move in fixed steps and render at a position interpolated between the last two steps
+        cam.update_look(&r1);
+        for _ in 0..timestep.advance(r1.get_frame_time()) {
+            previous_cam_posistion = cam.transform.posistion;
+            cam.update_movement(&r1);
+        }
+        let mut render_cam = cam;
+        render_cam.transform.posistion = point3d::lerp(previous_cam_posistion, cam.transform.posistion, timestep.alpha());
+
+        // The window can be resized and letterboxed, map the cursor back to render resolution. Presses over the bars are ignored
+        let window = Point2D { x: r1.get_screen_width() as f32, y: r1.get_screen_height() as f32 };
+        let mouse = r1.get_mouse_position();
+        let cursor = Viewport::fit(resolution, window, viewport_fit).to_render(Point2D { x: mouse.x, y: mouse.y }, resolution);
+        if let Some(Point2D { x, y }) = cursor.filter(|_| r1.is_key_pressed(KeyboardKey::KEY_C)) {
+            let (origin, direction) = render_cam.pixel_ray(x, y, resolution);
+            debug_ray = Some((origin, origin + direction * DEBUG_RAY_LENGTH));
+            let _ = requests.send(RenderMessage::Command(RenderCommand::Pick { x, y, cam: render_cam }));
+        }
+
+        new_yaw += spin * r1.get_frame_time();
+        light.update(&r1);
+
+        transformation.update_transform(new_yaw, new_pitch, new_posistion);
+
+        // Exports read the render thread's buffers, which hold the frame shown last or one about to replace it
+        if r1.is_key_pressed(KeyboardKey::KEY_F11) {
+            let png = next_screenshot_filename("depth", "png");
+            let raw = png.replace(".png", ".f32");
+            let _ = requests.send(RenderMessage::Command(RenderCommand::ExportDepth { png, raw }));
+        }
+        if r1.is_key_pressed(KeyboardKey::KEY_F12) {
+            let path = next_screenshot_filename("screenshot", "png");
+            // With shift everything nothing was drawn to is left transparent for compositing
+            let cutout = r1.is_key_down(KeyboardKey::KEY_LEFT_SHIFT);
+            let _ = requests.send(RenderMessage::Command(RenderCommand::Screenshot { path, cutout, key: color_key }));
+        }
+
+        let request = FrameRequest {
+            cam: render_cam,
+            transformation,
+            light,
+            elapsed: start_time.elapsed().as_secs_f32(),
+            fog_enabled,
+            grid_enabled,
+            ambient,
+            stereo_mode,
+            debug_ray,
+            frame_gizmos_enabled,
+            wireframe_enabled,
+            texture_filter,
+            triangle_colors,
+            edge_aa,
+            grade,
+        };
+        if requests.send(RenderMessage::Frame(request)).is_err() {
+            eprintln!("The render thread stopped unexpectedly");
+            break;
+        }
 
         // Put it in a window!
-        let _ = texture.update_texture(unsafe {&*screen.rgba.get()});
+        // Only the newest finished frame is shown, any that queued up behind it are handed straight back.
+        // raylib's texture keeps the previous frame when none finished since
+        let mut latest = None;
+        while let Some(frame) = frames.try_recv() {
+            if let Some(older) = latest.replace(frame) {
+                frames.recycle(older);
+            }
+        }
+        let rendered = latest.is_some();
+        if let Some(frame) = latest {
+            timer.record("transform", frame.stats.transform_time);
+            timer.record("triangle", frame.stats.triangle_time);
+            {
+                let _upload = timer.span("upload");
+                let _ = texture.update_texture(&frame.rgba);
+            }
+            frames.recycle(frame);
+        }
         let window_width = r1.get_screen_width();
         let window_height = r1.get_screen_height();
-        let frame_time = frame_start.elapsed();
+        let viewport = Viewport::fit(resolution, Point2D { x: window_width as f32, y: window_height as f32 }, viewport_fit);
+        let report = timer.finish();
 
-        // Collect timing data
-        transform_times.push(transform_time.as_micros() as f64);
-        triangle_times.push(triangle_time.as_micros() as f64);
-        frame_times.push(frame_time.as_micros() as f64);
+        // Collect timing data, loops that presented no new frame would otherwise log zero render times and drag the plot down
+        if rendered {
+            transform_times.push(report.micros("transform"));
+            triangle_times.push(report.micros("triangle"));
+        }
+        frame_times.push(micros(report.total));
 
         let mut d = r1.begin_drawing(&thread);
         d.clear_background(raylib::prelude::Color::BLACK);
         d.draw_texture_pro(
             &texture,
             raylib::prelude::Rectangle { x: 0.0, y: 0.0, width: resolution.x, height: resolution.y},
-            raylib::prelude::Rectangle { x: 0.0, y: 0.0, width: window_width as f32, height: window_height as f32 },
+            raylib::prelude::Rectangle { x: viewport.origin.x, y: viewport.origin.y, width: viewport.size.x, height: viewport.size.y },
             raylib::prelude::Vector2 { x: 0.0, y: 0.0 },
             0.0,
             raylib::prelude::Color::WHITE
         );
         // Perf stats
-        d.draw_text(&format!("Transform time: {:.2?}\nTriangle time: {:.2?}\nFrame time: {:.2?}", transform_time, triangle_time, frame_time), 10, 10, 20, raylib::prelude::Color::LIME);
+        d.draw_text(&format!("Transform time: {:.2?}\nTriangle time: {:.2?}\nUpload time: {:.2?}\nFrame time: {:.2?}", report.get("transform"), report.get("triangle"), report.get("upload"), report.total), 10, 10, 20, raylib::prelude::Color::LIME);
+    }
+    // Hanging up ends the render loop, frames it was blocked submitting are drained so it can get there
+    drop(requests);
+    while frames.recv().is_some() {}
+    if render_thread.join().is_err() {
+        eprintln!("Warning: the render thread panicked");
+    }
+    let plotted = debug.path("performance_metrics.png").map(|path| plot_all_metrics(&transform_times, &triangle_times, &merge_times, &frame_times, &path));
+    if let Some(Err(e)) = plotted {
+        eprintln!("Warning: {}", e);
+    }
+}
+
+// View and toggles of one frame, sent from the window thread each loop. Also the render cache key, so it has to cover everything
+// that changes the image
+#[derive(Clone, Copy, PartialEq)]
+struct FrameRequest {
+    cam: Camera,
+    transformation: transform::Transform,
+    light: DirectionalLight,
+    // Seconds since startup, only part of the key while something animates
+    elapsed: f32,
+    fog_enabled: bool,
+    grid_enabled: bool,
+    ambient: f32,
+    stereo_mode: StereoMode,
+    debug_ray: Option<(Point3D, Point3D)>,
+    frame_gizmos_enabled: bool,
+    wireframe_enabled: bool,
+    texture_filter: TextureFilter,
+    triangle_colors: bool,
+    edge_aa: bool,
+    grade: ColorGrade,
+}
+
+// One-off work for the render thread, applied in the order sent before it draws its next frame
+enum RenderCommand {
+    LoadModel(Model),
+    ReplaceDiffuse(texture::Texture),
+    // Report the surface point under a render-resolution pixel of the frame shown
+    Pick { x: f32, y: f32, cam: Camera },
+    Screenshot { path: String, cutout: bool, key: Option<ColorKey> },
+    ExportDepth { png: String, raw: String },
+}
+
+enum RenderMessage {
+    Frame(FrameRequest),
+    Command(RenderCommand),
+}
+
+// Everything that draws, owned by the render thread
+struct RenderWorker {
+    rasterizer: Rasterizer,
+    side_by_side: StereoRig,
+    anaglyph: StereoRig,
+    model: Model,
+    morph: Option<Morph>,
+    skin: Option<Skin>,
+    // Morphs, skins and scrolling textures change every frame even with nothing else moving
+    animated: bool,
+    backdrop: Option<Backdrop>,
+    fog: Fog,
+    grid: Vec<(Point3D, Point3D)>,
+    render_cache: RenderCache<FrameRequest>,
+    tile_controller: Option<TileController>,
+    // Recent frames of per-tile times for the recommendation at exit
+    tile_profile: VecDeque<Vec<Duration>>,
+    // Keep tiles.json in step with adaptive retiling
+    debug: DebugOutput,
+    // Whether the last rendered frame looked like it was taken from inside a mesh
+    camera_inside: bool,
+    // Last surface point picked with C, for measuring distances
+    picked_point: Option<Point3D>,
+}
+
+impl RenderWorker {
+    fn apply(&mut self, command: RenderCommand) {
+        match command {
+            RenderCommand::LoadModel(model) => {
+                self.model = model;
+                // The morph target and bone weights were paired with the old mesh's topology
+                self.morph = None;
+                self.skin = None;
+                self.animated = self.model.materials.iter().any(|material| material.uv_velocity != Point2D { x: 0.0, y: 0.0 });
+                // Neither the mesh nor the textures are part of the frame key
+                self.render_cache.invalidate();
+            }
+            RenderCommand::ReplaceDiffuse(texture) => {
+                self.model.replace_diffuse(texture);
+                self.render_cache.invalidate();
+            }
+            RenderCommand::Pick { x, y, cam } => {
+                // The depth buffer still holds the last frame, so this is the surface under the cursor as shown
+                if let Some(point) = self.rasterizer.world_position_at(x, y, &cam) {
+                    match self.picked_point {
+                        Some(previous) => {
+                            let offset: Point3D = point - previous;
+                            println!("Picked ({:.2}, {:.2}, {:.2}), {:.2} from the last pick", point.x, point.y, point.z, point3d::dot3(offset, offset).sqrt());
+                        }
+                        None => println!("Picked ({:.2}, {:.2}, {:.2})", point.x, point.y, point.z),
+                    }
+                    self.picked_point = Some(point);
+                }
+            }
+            RenderCommand::Screenshot { path, cutout, key } => {
+                let result = if cutout {
+                    self.rasterizer.screen.write_png_cutout(&path)
+                } else if let Some(key) = key {
+                    self.rasterizer.screen.write_png_keyed(&path, &key)
+                } else {
+                    self.rasterizer.screen.write_png(&path)
+                };
+                match result {
+                    Ok(()) => println!("Saved {}", path),
+                    Err(e) => eprintln!("Failed to save {}: {}", path, e),
+                }
+            }
+            RenderCommand::ExportDepth { png, raw } => {
+                let screen = &self.rasterizer.screen;
+                match screen.write_depth_png16(&png, 0.0, DEPTH_EXPORT_FAR).and_then(|()| screen.write_depth_raw(&raw)) {
+                    Ok(()) => println!("Saved {} and {}", png, raw),
+                    Err(e) => eprintln!("Failed to save depth: {}", e),
+                }
+            }
+        }
+    }
+
+    /// Draw `request` into the rasterizer's screen, None when it matches the last frame drawn and the screen already holds it
+    fn render(&mut self, request: &FrameRequest) -> Option<DrawStats> {
+        let key = FrameRequest { elapsed: if self.animated { request.elapsed } else { 0.0 }, ..*request };
+        if !self.render_cache.needs_render(key) {
+            return None;
+        }
+        let cam = &request.cam;
+        let lighting_direction = request.light.direction();
+        let active_fog = if request.fog_enabled { Some(&self.fog) } else { None };
+        let ambient = Point3D { x: request.ambient, y: request.ambient, z: request.ambient };
+        for target in [&mut self.rasterizer, &mut self.side_by_side.left, &mut self.side_by_side.right, &mut self.anaglyph.left, &mut self.anaglyph.right] {
+            target.ambient = ambient;
+            target.texture_filter = request.texture_filter;
+            target.triangle_colors = request.triangle_colors;
+            target.edge_aa = request.edge_aa.then(SamplePattern::default);
+        }
+
+        clear_screen(&self.rasterizer.screen, self.backdrop.as_ref());
+        if self.animated {
+            self.model.update_uv_scroll(request.elapsed);
+            if let Some(morph) = &self.morph {
+                morph.blend_into(0.5 - 0.5 * request.elapsed.cos(), &mut self.model.triangles);
+                self.model.update_bounds();
+            }
+            if let Some(skin) = &self.skin {
+                skin.skin_into(&bone_pose(request.elapsed), &mut self.model.triangles);
+                self.model.update_bounds();
+            }
+        }
+
+        let stereo = match request.stereo_mode {
+            StereoMode::Off => None,
+            StereoMode::SideBySide => Some(&self.side_by_side),
+            StereoMode::Anaglyph => Some(&self.anaglyph),
+        };
+
+        // Depth tested line overlays drawn after the model
+        let ray_lines: Vec<(Point3D, Point3D)> = request.debug_ray.into_iter().collect();
+        let frame_lines = request.frame_gizmos_enabled.then(|| vertex_frame_lines(&self.model.triangles, &request.transformation, FRAME_GIZMO_LENGTH));
+        let wire_lines = request.wireframe_enabled.then(|| wireframe_lines(&self.model.triangles, &request.transformation, None));
+        let mut overlays: Vec<Overlay> = vec![(&ray_lines, (255, 60, 60), 0.0)];
+        if request.grid_enabled {
+            overlays.push((&self.grid, (90, 90, 90), 0.0));
+        }
+        if let Some(lines) = &wire_lines {
+            overlays.push((lines, WIREFRAME_COLOR, WIREFRAME_DEPTH_BIAS));
+        }
+        if let Some(lines) = &frame_lines {
+            overlays.push((&lines.normals, (80, 140, 255), 0.0));
+            overlays.push((&lines.tangents, (255, 80, 80), 0.0));
+            overlays.push((&lines.bitangents, (80, 255, 80), 0.0));
+        }
+
+        let stats = if let Some(stereo) = stereo {
+            stereo.clear(0, 0, 0, 255);
+            let mut stats = stereo.draw_instanced(&self.model, &[request.transformation], cam, lighting_direction, active_fog);
+            for (lines, color, bias) in &overlays {
+                stereo.draw_lines(lines, cam, *color, *bias);
+            }
+            // Translucent surfaces go over the opaque model and the overlays behind them
+            stats.add_pass(stereo.flush_blended());
+            if request.stereo_mode == StereoMode::Anaglyph {
+                stereo.composite_anaglyph(&self.rasterizer.screen);
+            } else {
+                stereo.composite_side_by_side(&self.rasterizer.screen);
+            }
+            stats
+        } else {
+            let mut stats = self.rasterizer.draw_instanced(&self.model, &[request.transformation], cam, lighting_direction, active_fog);
+            for (lines, color, bias) in &overlays {
+                self.rasterizer.draw_lines(lines, cam, *color, *bias);
+            }
+            stats.add_pass(self.rasterizer.flush_blended());
+            stats
+        };
+        let resolution = self.rasterizer.resolution;
+        request.grade.apply(&self.rasterizer.screen, &self.rasterizer.tiles);
+        request.light.draw_gizmo(&self.rasterizer.screen, cam, Point2D { x: resolution.x - 80.0, y: resolution.y - 80.0 }, 60.0, (255, 220, 80));
+        // Stereo frames use different tiling, only profile the main rasterizer's tiles
+        if stereo.is_none() {
+            if self.tile_profile.len() == TILE_PROFILE_FRAMES {
+                self.tile_profile.pop_front();
+            }
+            self.tile_profile.push_back(stats.tile_times.clone());
+            // Retiling only moves work between threads, the rendered image and the cache stay valid
+            let retile = self.tile_controller.as_mut().and_then(|controller| controller.observe(stats.triangle_time, &stats.tile_times));
+            if let (Some(controller), Some(tile_count)) = (self.tile_controller.as_mut(), retile) {
+                self.rasterizer.tiles = subdivide_count(self.rasterizer.screen.width, self.rasterizer.screen.height, tile_count);
+                controller.retiled(self.rasterizer.tiles.len());
+                self.tile_profile.clear();
+                println!("Adaptive tiling: {} tiles", self.rasterizer.tiles.len());
+                save_tile_layout(&self.debug, &self.rasterizer.tiles, self.rasterizer.screen.width, self.rasterizer.screen.height);
+            }
+        }
+        // Warn once each time the camera enters geometry, back faces are culled so the view turns confusingly empty or inside out
+        let inside = stats.camera_inside_geometry(resolution.x * resolution.y);
+        if inside && !self.camera_inside {
+            eprintln!("Warning: the camera looks to be inside geometry, most of the view is back faces");
+        }
+        self.camera_inside = inside;
+        Some(stats)
+    }
+
+    fn report_tile_recommendation(&self) {
+        let profiled_frames: Vec<Vec<Duration>> = self.tile_profile.iter().cloned().collect();
+        if !profiled_frames.is_empty() {
+            println!("Recommended tile count for this scene: {} (currently {})", recommend_tile_count(&profiled_frames), self.rasterizer.tiles.len());
+        }
+    }
+}
+
+// Block until the next frame request, applying commands on the way. Requests that queued up while a slow frame drew are
+// skipped for the newest one. None once the window thread hangs up
+fn next_frame_request(messages: &Receiver<RenderMessage>, worker: &mut RenderWorker) -> Option<FrameRequest> {
+    let mut latest = None;
+    loop {
+        let message = match latest {
+            None => messages.recv().ok()?,
+            Some(request) => match messages.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => return Some(request),
+                Err(TryRecvError::Disconnected) => return None,
+            },
+        };
+        match message {
+            RenderMessage::Frame(request) => latest = Some(request),
+            RenderMessage::Command(command) => worker.apply(command),
+        }
+    }
+}
+
+// Write the debug pictures of the current tiling, a failure only costs the debug output
+fn save_tile_layout(debug: &DebugOutput, tiles: &[Rect], width: u32, height: u32) {
+    match debug.save_tiles(tiles, width, height) {
+        Ok(written) => written.iter().for_each(|path| println!("Saved {}", path.display())),
+        Err(e) => eprintln!("Warning: couldn't save the tile layout: {}", e),
+    }
+}
+
+// Stand-in for a loaded skeleton: bone 0 holds the bind pose and bone 1 nods back and forth about the model origin
+fn bone_pose(elapsed: f32) -> [BoneMatrix; 2] {
+    let nod = transform::Transform { yaw: 0.0, pitch: 0.5 * elapsed.sin(), posistion: Point3D { x: 0.0, y: 0.0, z: 0.0 } };
+    [BoneMatrix::IDENTITY, BoneMatrix::from_transform(&nod)]
+}
+
+/// Render `sequence` to numbered PNGs in `dir`, the model turns once around its vertical axis over the sequence and morphs/skins/scrolls play at their normal rate
+#[allow(clippy::too_many_arguments)]
+fn render_sequence(dir: &Path, sequence: &Sequence, rasterizer: &Rasterizer, model: &mut Model, morph: Option<&Morph>, skin: Option<&Skin>, backdrop: Option<&Backdrop>, transformation: transform::Transform, cam: &Camera, light: Point3D) -> anyhow::Result<()> {
+    render_frames(dir, sequence, rasterizer, model, cam, light, backdrop, |elapsed, model| {
+        model.update_uv_scroll(elapsed);
+        if let Some(morph) = morph {
+            morph.blend_into(0.5 - 0.5 * elapsed.cos(), &mut model.triangles);
+            model.update_bounds();
+        }
+        if let Some(skin) = skin {
+            skin.skin_into(&bone_pose(elapsed), &mut model.triangles);
+            model.update_bounds();
+        }
+        transform::Transform { yaw: transformation.yaw + std::f32::consts::TAU * elapsed / sequence.duration(), ..transformation }
+    })?;
+    println!("Saved {} frames to {}", sequence.frame_count, dir.display());
+    Ok(())
+}
+
+// Asset errors at startup are reported without a panic's backtrace noise
+fn exit_on_error<T>(result: Result<T, RusterizerError>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    })
+}
+
+// Black, or the backdrop with black bars
+fn clear_screen(screen: &ScreenSpace, backdrop: Option<&Backdrop>) {
+    match backdrop {
+        Some(backdrop) => screen.clear_to_backdrop(backdrop, 0, 0, 0, 255),
+        None => screen.clear(0, 0, 0, 255),
     }
-    use std::env;
-    let current_dir = env::current_dir().unwrap();
-    plot_all_metrics(&transform_times, &triangle_times, &merge_times, &frame_times, &current_dir.join("performance_metrics.png")).unwrap();
 }
 
 fn plot_all_metrics(
-    transform_times: &Vec<f64>,
-    triangle_times: &Vec<f64>,
-    merge_times: &Vec<f64>,
-    frame_times: &Vec<f64>,
+    transform_times: &MetricBuffer,
+    triangle_times: &MetricBuffer,
+    merge_times: &MetricBuffer,
+    frame_times: &MetricBuffer,
     filename: &Path,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), RusterizerError> {
+    let plot_error = |message: String| RusterizerError::Plot { path: filename.display().to_string(), message };
+    // Quitting before the first frame leaves nothing to plot
     if transform_times.is_empty() {
-        return Err("No data to plot".into());
+        return Err(plot_error("no frames were rendered".to_string()));
     }
+    draw_metrics_plot(transform_times, triangle_times, merge_times, frame_times, filename).map_err(|e| plot_error(e.to_string()))?;
+    println!("Successfully saved {}", filename.display());
+    Ok(())
+}
 
+fn draw_metrics_plot(
+    transform_times: &MetricBuffer,
+    triangle_times: &MetricBuffer,
+    merge_times: &MetricBuffer,
+    frame_times: &MetricBuffer,
+    filename: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
     let max_time = [transform_times, triangle_times, merge_times, frame_times]
         .iter()
         .flat_map(|v| v.iter())
         .fold(0.0f64, |acc, &x| acc.max(x));
 
-    let root = BitMapBackend::new(filename.to_str().unwrap(), (1200, 800)).into_drawing_area();
+    let root = BitMapBackend::new(filename, (1200, 800)).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -337,7 +823,5 @@ fn plot_all_metrics(
         .background_style(&RGBAColor(255, 255, 255, 0.8))
         .border_style(&BLACK)
         .draw()?;
-
-    println!("Successfully saved {}", filename.display());
     Ok(())
 }
\ No newline at end of file
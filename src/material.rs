@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use anyhow::{Result, bail};
+use crate::point2d::Point2D;
+use crate::texture::WrapMode;
+
+/// How a material's fragments combine with what's already drawn
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    // Overwrites the pixel and depth, alpha only matters for the alpha test
+    #[default]
+    Opaque,
+    // Alpha blended over the pixel without writing depth, drawn after every opaque triangle and sorted back to front
+    Blend,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub diffuse_texture: Option<String>,
+    // From map_Ke
+    pub emissive_texture: Option<String>,
+    pub dissolve: f32,
+    // Render both faces instead of culling back-facing triangles
+    pub two_sided: bool,
+    // UV units per second for scrolling textures (water, conveyors)
+    pub uv_velocity: Point2D,
+    // Current scroll, added to every sampled UV
+    pub uv_offset: Point2D,
+    // From the map_Kd -clamp option
+    pub wrap: WrapMode,
+    pub blend: BlendMode,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: String::from("default"),
+            diffuse_texture: None,
+            emissive_texture: None,
+            dissolve: 1.0,
+            two_sided: false,
+            uv_velocity: Point2D { x: 0.0, y: 0.0 },
+            uv_offset: Point2D { x: 0.0, y: 0.0 },
+            wrap: WrapMode::Repeat,
+            blend: BlendMode::Opaque,
+        }
+    }
+}
+
+impl Material {
+    fn new(name: &str) -> Self {
+        Self { name: name.to_string(), ..Self::default() }
+    }
+
+    /// Set the scroll for a time since the animation started, wrapped to [0, 1) since sampling repeats anyway
+    pub fn update_uv_scroll(&mut self, elapsed: f32) {
+        let scroll = self.uv_velocity * elapsed;
+        self.uv_offset = Point2D { x: scroll.x.rem_euclid(1.0), y: scroll.y.rem_euclid(1.0) };
+    }
+}
+
+/// Parse a .mtl file, MTL has no two-sided keyword so any material with transparency (d < 1 or Tr > 0) is treated as two-sided (foliage, cutouts)
+/// and blended with its dissolve as opacity
+pub fn parse_mtl<P: AsRef<Path>>(path: P) -> Result<Vec<Material>> {
+    // Texture paths are relative to the library, not the working directory
+    let mtl_dir = path.as_ref().parent().unwrap_or(Path::new("")).to_path_buf();
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+
+    let mut materials: Vec<Material> = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() || tokens[0].starts_with('#') {
+            continue
+        }
+
+        if tokens[0] == "newmtl" {
+            materials.push(Material::new(tokens.get(1).copied().unwrap_or("")));
+            continue
+        }
+        // Ignore statements before the first newmtl
+        let Some(material) = materials.last_mut() else { continue };
+
+        match tokens[0] {
+            "map_Kd" => {
+                // Texture options (-s, -o, ...) come first, the path is always last
+                if let Some(path) = tokens.last() {
+                    material.diffuse_texture = Some(mtl_dir.join(path).to_string_lossy().into_owned());
+                }
+                if let Some(option) = tokens.iter().position(|&t| t == "-clamp") {
+                    material.wrap = if tokens.get(option + 1) == Some(&"on") { WrapMode::Clamp } else { WrapMode::Repeat };
+                }
+            }
+            "map_Ke" => {
+                if let Some(path) = tokens.last() {
+                    material.emissive_texture = Some(mtl_dir.join(path).to_string_lossy().into_owned());
+                }
+            }
+            "d" => {
+                let Some(value) = tokens.get(1) else { bail!("d in material {} needs a value", material.name) };
+                material.dissolve = value.parse()?;
+                material.two_sided = material.dissolve < 1.0;
+                material.blend = if material.dissolve < 1.0 { BlendMode::Blend } else { BlendMode::Opaque };
+            }
+            "Tr" => {
+                let Some(value) = tokens.get(1) else { bail!("Tr in material {} needs a value", material.name) };
+                let transparency: f32 = value.parse()?;
+                material.dissolve = 1.0 - transparency;
+                material.two_sided = transparency > 0.0;
+                material.blend = if transparency > 0.0 { BlendMode::Blend } else { BlendMode::Opaque };
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parse `source` as an .mtl file
+    fn parse(name: &str, source: &str) -> Result<Vec<Material>> {
+        let path = std::env::temp_dir().join(format!("rusterizer-{}-{}.mtl", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let materials = parse_mtl(&path);
+        std::fs::remove_file(&path).unwrap();
+        materials
+    }
+
+    #[test]
+    fn only_transparent_materials_are_two_sided() {
+        let materials = parse("two-sided", "newmtl leaves\nd 0.5\nnewmtl bark\nd 1.0\nnewmtl glass\nTr 0.25\n").unwrap();
+        let two_sided: Vec<bool> = materials.iter().map(|material| material.two_sided).collect();
+        assert_eq!(two_sided, [true, false, true]);
+        assert_eq!(materials[2].dissolve, 0.75);
+    }
+
+    #[test]
+    fn bare_dissolve_is_an_error_not_a_panic() {
+        assert!(parse("bare-d", "newmtl leaves\nd\n").is_err());
+        assert!(parse("bare-tr", "newmtl leaves\nTr\n").is_err());
+    }
+}
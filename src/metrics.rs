@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Slowest tile time over the mean tile time above which tiling is considered unbalanced
+const TILE_IMBALANCE_THRESHOLD: f64 = 1.5;
+// Largest single step the tile count recommendation takes
+const MAX_TILE_COUNT_FACTOR: usize = 4;
+// Frames TileController averages before each decision, so it reacts to the scene rather than to one noisy frame
+const TILE_CONTROL_WINDOW: usize = 30;
+// Mean tile time below which tiles are too small for their scheduling overhead to be worth it
+const MIN_USEFUL_TILE_TIME: Duration = Duration::from_micros(50);
+
+/// Fixed capacity history of a per-frame metric, the oldest value is dropped once full so long sessions stay bounded
+#[derive(Debug, Clone)]
+pub struct MetricBuffer {
+    values: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl MetricBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { values: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = &f64> {
+        self.values.iter()
+    }
+}
+
+/// Suggest a tile count from several frames of per-tile times (all frames using the same tiling).
+/// Tiles are averaged across frames so one noisy frame doesn't count, if the slowest tile is well above the mean it bounds the frame time
+/// and the count is scaled up by roughly how many times slower it is, so the hot region ends up split across more threads
+pub fn recommend_tile_count(frames: &[Vec<Duration>]) -> usize {
+    let Some(tile_count) = frames.first().map(|f| f.len()) else { return 0 };
+    let Some((mean, slowest)) = tile_balance(frames) else { return 0 };
+    if mean <= 0.0 || slowest / mean < TILE_IMBALANCE_THRESHOLD {
+        return tile_count;
+    }
+    tile_count * ((slowest / mean).ceil() as usize).min(MAX_TILE_COUNT_FACTOR)
+}
+
+// Mean and slowest per-frame tile time in seconds, each tile averaged over the frames sharing the first frame's tiling
+fn tile_balance(frames: &[Vec<Duration>]) -> Option<(f64, f64)> {
+    let tile_count = frames.first()?.len();
+    if tile_count == 0 {
+        return None;
+    }
+    let mut averages = vec![0.0f64; tile_count];
+    let mut frame_count = 0;
+    for frame in frames.iter().filter(|f| f.len() == tile_count) {
+        for (average, time) in averages.iter_mut().zip(frame) {
+            *average += time.as_secs_f64();
+        }
+        frame_count += 1;
+    }
+    let mean = averages.iter().sum::<f64>() / (tile_count * frame_count) as f64;
+    let slowest = averages.iter().cloned().fold(0.0, f64::max) / frame_count as f64;
+    Some((mean, slowest))
+}
+
+/// Adjusts the tile count between frames: doubled while frames miss the target and the slowest tile bounds the frame,
+/// halved while tiles are so small their scheduling overhead dominates. Decides once per window of frames on one tiling
+#[derive(Debug, Clone)]
+pub struct TileController {
+    pub target_frame_time: Duration,
+    pub min_tiles: usize,
+    pub max_tiles: usize,
+    tile_count: usize,
+    // Rasterization time and per-tile times of the frames since the last decision
+    frame_times: Vec<Duration>,
+    tile_times: Vec<Vec<Duration>>,
+}
+
+impl TileController {
+    pub fn new(tile_count: usize, min_tiles: usize, max_tiles: usize, target_frame_time: Duration) -> Self {
+        Self {
+            target_frame_time,
+            min_tiles: min_tiles.max(1),
+            max_tiles: max_tiles.max(min_tiles),
+            tile_count,
+            frame_times: Vec::with_capacity(TILE_CONTROL_WINDOW),
+            tile_times: Vec::with_capacity(TILE_CONTROL_WINDOW),
+        }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tile_count
+    }
+
+    /// Report the tiling actually built, subdivision stops early once tiles reach their minimum size
+    pub fn retiled(&mut self, tile_count: usize) {
+        self.tile_count = tile_count;
+        self.frame_times.clear();
+        self.tile_times.clear();
+    }
+
+    /// Record a frame's rasterization time and per-tile times, returns the new tile count when the tiling should change.
+    /// Frames drawn with a different tiling than the controller's (stereo, a resize) are ignored
+    pub fn observe(&mut self, frame_time: Duration, tile_times: &[Duration]) -> Option<usize> {
+        if tile_times.len() != self.tile_count {
+            return None;
+        }
+        self.frame_times.push(frame_time);
+        self.tile_times.push(tile_times.to_vec());
+        if self.frame_times.len() < TILE_CONTROL_WINDOW {
+            return None;
+        }
+
+        let mean_frame = self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32;
+        let balance = tile_balance(&self.tile_times);
+        self.frame_times.clear();
+        self.tile_times.clear();
+        let (mean_tile, slowest_tile) = balance?;
+
+        let next = if mean_frame > self.target_frame_time && mean_tile > 0.0 && slowest_tile / mean_tile >= TILE_IMBALANCE_THRESHOLD {
+            (self.tile_count * 2).min(self.max_tiles)
+        } else if mean_tile < MIN_USEFUL_TILE_TIME.as_secs_f64() {
+            (self.tile_count / 2).max(self.min_tiles)
+        } else {
+            self.tile_count
+        };
+        if next == self.tile_count {
+            return None;
+        }
+        self.tile_count = next;
+        Some(next)
+    }
+}
+
+/// Metric values are stored as f64 microseconds, this is the one place durations get converted
+pub fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
+
+/// Collects named stage timings for one frame, either timed here with a span guard or recorded from elsewhere (DrawStats)
+#[derive(Debug, Clone)]
+pub struct FrameTimer {
+    start: Instant,
+    spans: Vec<(&'static str, Duration)>,
+}
+
+/// Times a stage from creation until dropped, then records it on the FrameTimer it came from
+pub struct Span<'a> {
+    timer: &'a mut FrameTimer,
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        self.timer.record(self.name, self.start.elapsed());
+    }
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameTimer {
+    /// Starts the frame clock
+    pub fn new() -> Self {
+        Self { start: Instant::now(), spans: Vec::new() }
+    }
+
+    pub fn span(&mut self, name: &'static str) -> Span<'_> {
+        Span { timer: self, name, start: Instant::now() }
+    }
+
+    /// Add a duration measured elsewhere, repeated names accumulate
+    pub fn record(&mut self, name: &'static str, duration: Duration) {
+        match self.spans.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, total)) => *total += duration,
+            None => self.spans.push((name, duration)),
+        }
+    }
+
+    /// Stop the frame clock
+    pub fn finish(self) -> FrameReport {
+        FrameReport { total: self.start.elapsed(), spans: self.spans }
+    }
+}
+
+/// Stage timings of a finished frame, in the order the stages were first recorded
+#[derive(Debug, Clone)]
+pub struct FrameReport {
+    pub total: Duration,
+    pub spans: Vec<(&'static str, Duration)>,
+}
+
+impl FrameReport {
+    /// Zero for a stage that didn't run this frame
+    pub fn get(&self, name: &str) -> Duration {
+        self.spans.iter().find(|(n, _)| *n == name).map_or(Duration::ZERO, |(_, d)| *d)
+    }
+
+    pub fn micros(&self, name: &str) -> f64 {
+        micros(self.get(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn micros_frame(times: &[u64]) -> Vec<Duration> {
+        times.iter().map(|&t| Duration::from_micros(t)).collect()
+    }
+
+    #[test]
+    fn hot_region_raises_the_recommended_tile_count() {
+        // 8 tiles, two of which cover the model and take 4x as long, over a few frames with some noise
+        let frames: Vec<Vec<Duration>> = (0..5)
+            .map(|i| micros_frame(&[100 + i, 100, 400, 410 - i, 100, 100, 100, 100]))
+            .collect();
+        let recommended = recommend_tile_count(&frames);
+        assert!(recommended > 8, "recommended {}", recommended);
+        assert!(recommended <= 8 * MAX_TILE_COUNT_FACTOR);
+    }
+
+    #[test]
+    fn balanced_tiles_keep_their_count() {
+        let frames: Vec<Vec<Duration>> = (0..5).map(|i| micros_frame(&[200 + i, 205, 198, 201])).collect();
+        assert_eq!(recommend_tile_count(&frames), 4);
+        assert_eq!(recommend_tile_count(&[]), 0);
+    }
+
+    #[test]
+    fn tile_controller_moves_toward_the_target() {
+        // 16.7 ms target, tiles allowed between 4 and 64
+        let mut controller = TileController::new(16, 4, 64, Duration::from_micros(16_700));
+        // Slow frames bounded by one hot tile: more tiles, decided only once the window is full
+        let hot = micros_frame(&[6000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000]);
+        for _ in 1..TILE_CONTROL_WINDOW {
+            assert_eq!(controller.observe(Duration::from_millis(25), &hot), None);
+        }
+        assert_eq!(controller.observe(Duration::from_millis(25), &hot), Some(32));
+        controller.retiled(32);
+
+        // Fast frames of tiny tiles: fewer tiles, down to the minimum and no further
+        let tiny = |count: usize| vec![Duration::from_micros(10); count];
+        let mut count = 32;
+        for expected in [16, 8, 4] {
+            let decision = (0..TILE_CONTROL_WINDOW).filter_map(|_| controller.observe(Duration::from_millis(1), &tiny(count))).last();
+            assert_eq!(decision, Some(expected));
+            controller.retiled(expected);
+            count = expected;
+        }
+        assert!((0..TILE_CONTROL_WINDOW).all(|_| controller.observe(Duration::from_millis(1), &tiny(4)).is_none()));
+
+        // Frames from another tiling (stereo) are ignored rather than counted
+        assert!((0..2 * TILE_CONTROL_WINDOW).all(|_| controller.observe(Duration::from_millis(25), &hot).is_none()));
+        assert_eq!(controller.tile_count(), 4);
+    }
+
+    #[test]
+    fn balanced_frames_on_target_keep_the_tiling() {
+        let mut controller = TileController::new(8, 4, 64, Duration::from_micros(16_700));
+        let balanced = micros_frame(&[1000, 1010, 990, 1005, 1000, 995, 1002, 998]);
+        // Slow but balanced: more tiles wouldn't help
+        assert!((0..2 * TILE_CONTROL_WINDOW).all(|_| controller.observe(Duration::from_millis(25), &balanced).is_none()));
+        assert_eq!(controller.tile_count(), 8);
+    }
+
+    #[test]
+    fn metric_buffer_keeps_only_the_newest_values() {
+        let mut buffer = MetricBuffer::new(4);
+        for i in 0..10 {
+            buffer.push(i as f64);
+        }
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.iter().copied().collect::<Vec<f64>>(), [6.0, 7.0, 8.0, 9.0]);
+        buffer.clear();
+        assert!(buffer.is_empty());
+        // A zero cap records nothing
+        let mut disabled = MetricBuffer::new(0);
+        disabled.push(1.0);
+        assert!(disabled.is_empty());
+    }
+
+    #[test]
+    fn span_records_a_sleep_within_tolerance() {
+        let mut timer = FrameTimer::new();
+        {
+            let _span = timer.span("sleep");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        timer.record("sleep", Duration::from_millis(5));
+        let report = timer.finish();
+        // Sleeps never return early, the upper bound leaves room for a busy test machine
+        let slept = report.get("sleep");
+        assert!(slept >= Duration::from_millis(25) && slept < Duration::from_millis(200), "{slept:?}");
+        assert!(report.total >= Duration::from_millis(20));
+        assert_eq!(report.micros("sleep"), micros(slept));
+        assert_eq!(report.get("missing"), Duration::ZERO);
+    }
+}
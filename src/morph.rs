@@ -0,0 +1,114 @@
+use anyhow::{Result, bail};
+
+use crate::obj::{ObjMesh, UvGenMode, fan_triangulate_faces};
+use crate::point3d;
+use crate::triangle::Triangle3D;
+
+/// Two triangulations of meshes with identical topology, blended per vertex for simple vertex animation
+pub struct Morph {
+    pub base: Vec<Triangle3D>,
+    pub target: Vec<Triangle3D>,
+}
+
+/// Same vertex and normal counts and the same faces referencing the same indices, only positions and normals may differ
+pub fn topology_matches(a: &ObjMesh, b: &ObjMesh) -> bool {
+    a.positions.len() == b.positions.len()
+        && a.normals.len() == b.normals.len()
+        && a.faces.len() == b.faces.len()
+        && a.faces.iter().zip(&b.faces).all(|(fa, fb)| {
+            fa.v_indices == fb.v_indices && fa.vt_indices == fb.vt_indices && fa.vn_indices == fb.vn_indices
+        })
+}
+
+impl Morph {
+    /// Triangulate both meshes, UVs, alphas and materials come from the base.
+    /// Both are grouped by the base's materials the way Model::sort_by_material orders them, so the base can be used as the model's triangles directly
+    pub fn new(base: &ObjMesh, target: &ObjMesh, flip_v: bool, uv_gen: UvGenMode) -> Result<Self> {
+        if !topology_matches(base, target) {
+            bail!("Morph target topology doesn't match the base mesh");
+        }
+        let base_triangles = fan_triangulate_faces(&base.faces, &base.positions, &base.alphas, &base.texcoords, &base.normals, flip_v, uv_gen);
+        let target_triangles = fan_triangulate_faces(&target.faces, &target.positions, &target.alphas, &target.texcoords, &target.normals, flip_v, UvGenMode::None);
+        // The target's usemtl statements don't matter, reorder both by the base's materials so triangles stay paired
+        let mut order: Vec<usize> = (0..base_triangles.len()).collect();
+        order.sort_by_key(|&i| base_triangles[i].material);
+        Ok(Self {
+            base: order.iter().map(|&i| base_triangles[i]).collect(),
+            target: order.iter().map(|&i| target_triangles[i]).collect(),
+        })
+    }
+
+    /// Positions and normals at `weight` between base (0) and target (1), written over `out` which must match the base's triangle order
+    pub fn blend_into(&self, weight: f32, out: &mut [Triangle3D]) {
+        for ((dst, from), to) in out.iter_mut().zip(&self.base).zip(&self.target) {
+            let (a, b, c) = (point3d::lerp(from.a, to.a, weight), point3d::lerp(from.b, to.b, weight), point3d::lerp(from.c, to.c, weight));
+            *dst = Triangle3D {
+                a,
+                b,
+                c,
+                na: point3d::lerp(from.na, to.na, weight),
+                nb: point3d::lerp(from.nb, to.nb, weight),
+                nc: point3d::lerp(from.nc, to.nc, weight),
+                wa: a,
+                wb: b,
+                wc: c,
+                ..*from
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::obj::Face;
+    use crate::point2d::Point2D;
+    use crate::point3d::Point3D;
+
+    // A quad split into two triangles on different materials, the second face's material sorts first
+    fn quad(offset: Point3D, normal: Point3D) -> ObjMesh {
+        let positions = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+            .iter()
+            .map(|&(x, y)| Point3D { x: x + offset.x, y: y + offset.y, z: offset.z })
+            .collect();
+        let face = |v_indices: Vec<usize>, material| Face { vt_indices: v_indices.clone(), vn_indices: vec![0; 3], v_indices, material, smoothing_group: 0 };
+        ObjMesh {
+            positions,
+            alphas: vec![1.0; 4],
+            texcoords: vec![Point2D { x: 0.0, y: 0.0 }, Point2D { x: 1.0, y: 0.0 }, Point2D { x: 1.0, y: 1.0 }, Point2D { x: 0.0, y: 1.0 }],
+            normals: vec![normal],
+            faces: vec![face(vec![0, 1, 2], 1), face(vec![0, 2, 3], 0)],
+            materials: vec![Material::default(), Material::default()],
+            origin: [0.0; 3],
+        }
+    }
+
+    #[test]
+    fn half_weight_blends_to_the_midpoints() {
+        let base = quad(Point3D { x: 0.0, y: 0.0, z: 0.0 }, Point3D { x: 0.0, y: 0.0, z: 1.0 });
+        let target = quad(Point3D { x: 2.0, y: 4.0, z: -2.0 }, Point3D { x: 0.0, y: 1.0, z: 0.0 });
+        let morph = Morph::new(&base, &target, false, UvGenMode::None).unwrap();
+        let mut blended = morph.base.clone();
+        morph.blend_into(0.5, &mut blended);
+
+        let midpoint = |a: Point3D, b: Point3D| Point3D { x: (a.x + b.x) * 0.5, y: (a.y + b.y) * 0.5, z: (a.z + b.z) * 0.5 };
+        for ((tri, from), to) in blended.iter().zip(&morph.base).zip(&morph.target) {
+            assert_eq!([tri.a, tri.b, tri.c], [midpoint(from.a, to.a), midpoint(from.b, to.b), midpoint(from.c, to.c)]);
+            assert_eq!([tri.wa, tri.wb, tri.wc], [tri.a, tri.b, tri.c]);
+            assert_eq!(tri.na, Point3D { x: 0.0, y: 0.5, z: 0.5 });
+            assert_eq!((tri.ta, tri.material), (from.ta, from.material));
+        }
+        // The first vertex is shared by both triangles and sits at the origin in the base
+        assert_eq!(blended[0].a, Point3D { x: 1.0, y: 2.0, z: -1.0 });
+        assert_eq!(blended.iter().map(|tri| tri.material).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn mismatched_topology_is_rejected() {
+        let base = quad(Point3D { x: 0.0, y: 0.0, z: 0.0 }, Point3D { x: 0.0, y: 0.0, z: 1.0 });
+        let mut target = quad(Point3D { x: 0.0, y: 0.0, z: 0.0 }, Point3D { x: 0.0, y: 0.0, z: 1.0 });
+        target.faces[1].v_indices = vec![0, 3, 2];
+        assert!(Morph::new(&base, &target, false, UvGenMode::None).is_err());
+    }
+}
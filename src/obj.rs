@@ -1,25 +1,158 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 use anyhow::{Result, anyhow};
+use crate::error::RusterizerError;
+use crate::geometry::EdgeSetup;
+use crate::material::{Material, parse_mtl};
 use crate::point2d::Point2D;
-use crate::point3d::Point3D;
+use crate::point3d::{self, Point3D};
 use crate::triangle::Triangle3D;
 
+// Texcoords past this are far beyond any sensible tiling and usually mean a broken export
+const UV_SANITY_LIMIT: f32 = 100.0;
+
 #[derive(Debug)]
 pub struct Face {
     pub v_indices: Vec<usize>,
     pub vt_indices: Vec<usize>,
     pub vn_indices: Vec<usize>,
+    // Index into ObjMesh::materials
+    pub material: usize,
+    // From `s`, 0 when smoothing is off
+    pub smoothing_group: u32,
+}
+
+#[derive(Debug)]
+pub struct ObjMesh {
+    pub positions: Vec<Point3D>,
+    // Per-position alpha from the `v x y z r g b a` vertex color extension, 1.0 when absent
+    pub alphas: Vec<f32>,
+    pub texcoords: Vec<Point2D>,
+    pub normals: Vec<Point3D>,
+    pub faces: Vec<Face>,
+    // Index 0 is always the default material used before any usemtl
+    pub materials: Vec<Material>,
+    // Where the file's origin is relative to the positions, nonzero only when loaded with recenter
+    pub origin: [f64; 3],
+}
+
+impl ObjMesh {
+    /// The recenter shift as a world translation. Added to the object's position it puts the mesh back where the file has it,
+    /// turning about its centroid. Rounded to f32 once, so every vertex moves together and the small features survive
+    pub fn origin_offset(&self) -> Point3D {
+        Point3D { x: self.origin[0] as f32, y: self.origin[1] as f32, z: self.origin[2] as f32 }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ObjLoadOptions {
+    // Shift positions so their centroid is at the origin before converting to f32, keeps small features of
+    // large-coordinate (geo-referenced) meshes from being lost to f32 rounding
+    pub recenter: bool,
+    // Flip faces whose winding disagrees with their vertex normals, for meshes of unknown winding
+    pub fix_winding: bool,
+    // Axis convention of the source file, positions and normals are rotated into this renderer's Y-up
+    pub up_axis: UpAxis,
+    // Replace the file's normals with ones rebuilt from the faces, for exports with broken normals
+    pub recompute_normals: RecomputeNormals,
 }
 
-pub fn parse_obj(path: &str) -> Result<(Vec<Point3D>, Vec<Point2D>, Vec<Point3D>, Vec<Face>)> {
+/// Whether and how the loader rebuilds vertex normals, see recompute_vertex_normals
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RecomputeNormals {
+    // Keep the file's normals
+    #[default]
+    Off,
+    // Every face sharing a position shares its normal
+    Smooth,
+    // Only faces in the same `s` group share normals, faces with smoothing off are flat
+    SmoothingGroups,
+}
+
+/// Which axis points up in a source file, all conventions are right-handed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    // Blender, 3ds Max and most CAD tools, -Y is forward
+    Z,
+}
+
+impl UpAxis {
+    /// Rotate a source-space vector to Y-up. A rotation rather than an axis swap, so winding and handedness are kept
+    #[inline(always)]
+    pub fn to_y_up(self, v: Point3D) -> Point3D {
+        match self {
+            UpAxis::Y => v,
+            UpAxis::Z => Point3D { x: v.x, y: v.z, z: -v.y },
+        }
+    }
+}
+
+/// One unique (position, uv, normal) combination, the unit an indexed pipeline works in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vertex {
+    pub position: Point3D,
+    pub texcoord: Point2D,
+    pub normal: Point3D,
+    pub alpha: f32,
+}
+
+/// Deduplicated vertices with three indices per triangle
+#[derive(Debug, Default)]
+pub struct IndexedMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    // Material of each triangle, one entry per three indices
+    pub materials: Vec<usize>,
+    // Source face of each triangle, like materials
+    pub faces: Vec<u32>,
+}
+
+impl IndexedMesh {
+    /// Expand the index buffer back into standalone triangles for the rasterizer
+    pub fn to_triangles(&self) -> Vec<Triangle3D> {
+        self.indices
+            .chunks_exact(3)
+            .zip(self.materials.iter().zip(&self.faces))
+            .map(|(tri, (&material, &face))| Triangle3D {
+                face,
+                ..Triangle3D::from_vertices(self.vertices[tri[0] as usize], self.vertices[tri[1] as usize], self.vertices[tri[2] as usize], material)
+            })
+            .collect()
+    }
+}
+
+/// parse_obj_with with the failure classified, for callers that match on the kind of error
+pub fn load_obj(path: &str, options: &ObjLoadOptions) -> std::result::Result<ObjMesh, RusterizerError> {
+    parse_obj_with(path, options).map_err(|e| RusterizerError::obj(path, e))
+}
+
+/// How many texcoords lie beyond UV_SANITY_LIMIT, the loader warns when any do
+pub fn count_wild_texcoords(texcoords: &[Point2D]) -> usize {
+    texcoords.iter().filter(|t| t.x.abs() > UV_SANITY_LIMIT || t.y.abs() > UV_SANITY_LIMIT).count()
+}
+
+pub fn parse_obj(path: &str) -> Result<ObjMesh> {
+    parse_obj_with(path, &ObjLoadOptions::default())
+}
+
+pub fn parse_obj_with(path: &str, options: &ObjLoadOptions) -> Result<ObjMesh> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
+    let obj_dir = Path::new(path).parent().unwrap_or(Path::new(""));
 
-    let mut positions: Vec<Point3D> = Vec::new();
+    // Positions stay f64 until the optional recenter is done
+    let mut positions: Vec<[f64; 3]> = Vec::new();
+    let mut alphas: Vec<f32> = Vec::new();
     let mut texcoords: Vec<Point2D> = Vec::new();
     let mut normals: Vec<Point3D> = Vec::new();
     let mut faces: Vec<Face> = Vec::new();
+    let mut materials: Vec<Material> = vec![Material::default()];
+    let mut current_material: usize = 0;
+    let mut current_smoothing_group: u32 = 0;
 
     for line in reader.lines() {
         let line = line?;
@@ -33,7 +166,12 @@ pub fn parse_obj(path: &str) -> Result<(Vec<Point3D>, Vec<Point2D>, Vec<Point3D>
                 let x = tokens[1].parse()?;
                 let y = tokens[2].parse()?;
                 let z = tokens[3].parse()?;
-                positions.push(Point3D { x, y, z })
+                positions.push([x, y, z]);
+                let alpha = match tokens.get(7) {
+                    Some(a) => a.parse()?,
+                    None => 1.0,
+                };
+                alphas.push(alpha)
             }
             "vt" => {
                 let u = tokens[1].parse()?;
@@ -57,13 +195,165 @@ pub fn parse_obj(path: &str) -> Result<(Vec<Point3D>, Vec<Point2D>, Vec<Point3D>
                     face_vt_indices.push(vt_index.unwrap_or(0));
                     face_vn_indices.push(vn_index.unwrap_or(0));
                 }
-                faces.push(Face { v_indices: face_v_indices, vt_indices: face_vt_indices, vn_indices: face_vn_indices })
+                faces.push(Face { v_indices: face_v_indices, vt_indices: face_vt_indices, vn_indices: face_vn_indices, material: current_material, smoothing_group: current_smoothing_group })
+            }
+            "mtllib" => {
+                // A missing material library shouldn't stop the mesh from loading
+                for name in &tokens[1..] {
+                    match parse_mtl(obj_dir.join(name)) {
+                        Ok(library) => materials.extend(library),
+                        Err(e) => eprintln!("Warning: failed to load material library {name}: {e}"),
+                    }
+                }
+            }
+            "s" => {
+                current_smoothing_group = match tokens.get(1) {
+                    Some(&"off") | None => 0,
+                    Some(group) => group.parse()?,
+                };
+            }
+            "usemtl" => {
+                let name = tokens.get(1).copied().unwrap_or("");
+                current_material = match materials.iter().position(|m| m.name == name) {
+                    Some(index) => index,
+                    None => {
+                        eprintln!("Warning: unknown material {name}, using default");
+                        0
+                    }
+                };
             }
             _ => {}
         }
     }
 
-    Ok((positions, texcoords, normals, faces))
+    if positions.is_empty() {
+        return Err(anyhow!("OBJ file {path} has no vertices"));
+    }
+    let wild_texcoords = count_wild_texcoords(&texcoords);
+    if wild_texcoords > 0 {
+        eprintln!("Warning: {path} has {wild_texcoords} texcoords beyond +-{UV_SANITY_LIMIT}, likely an export bug");
+    }
+
+    // Some broken exporters write nan/inf, a single such vertex projects to garbage across the whole frame so faces using one are dropped
+    let finite_position = |i: usize| positions.get(i).is_none_or(|p| p.iter().all(|c| c.is_finite())) && alphas.get(i).is_none_or(|a| a.is_finite());
+    let finite_texcoord = |i: usize| texcoords.get(i).is_none_or(|t| t.x.is_finite() && t.y.is_finite());
+    let finite_normal = |i: usize| normals.get(i).is_none_or(|n| n.x.is_finite() && n.y.is_finite() && n.z.is_finite());
+    let face_count = faces.len();
+    faces.retain(|face| {
+        face.v_indices.iter().all(|&i| finite_position(i))
+            && face.vt_indices.iter().all(|&i| finite_texcoord(i))
+            && face.vn_indices.iter().all(|&i| finite_normal(i))
+    });
+    if faces.len() < face_count {
+        eprintln!("Warning: {path} has non-finite (nan/inf) vertex data, skipped {} faces using it", face_count - faces.len());
+    }
+
+    let mut origin = [0.0f64; 3];
+    if options.recenter {
+        let finite: Vec<&[f64; 3]> = positions.iter().filter(|p| p.iter().all(|c| c.is_finite())).collect();
+        for position in &finite {
+            for axis in 0..3 {
+                origin[axis] += position[axis];
+            }
+        }
+        if !finite.is_empty() {
+            origin = origin.map(|sum| sum / finite.len() as f64);
+        }
+    }
+    let positions = positions
+        .iter()
+        .map(|p| options.up_axis.to_y_up(Point3D { x: (p[0] - origin[0]) as f32, y: (p[1] - origin[1]) as f32, z: (p[2] - origin[2]) as f32 }))
+        .collect();
+    let normals = normals.into_iter().map(|n| options.up_axis.to_y_up(n)).collect();
+    // The origin is reported in the same Y-up space as the positions
+    let origin = match options.up_axis {
+        UpAxis::Y => origin,
+        UpAxis::Z => [origin[0], origin[2], -origin[1]],
+    };
+
+    let mut mesh = ObjMesh { positions, alphas, texcoords, normals, faces, materials, origin };
+    if options.fix_winding {
+        let flipped = fix_winding(&mut mesh);
+        if flipped > 0 {
+            eprintln!("Warning: {path} has {flipped} of {} faces wound against their normals, flipped them", mesh.faces.len());
+        }
+    }
+    // After the winding fix, which needs the file's normals and leaves the faces consistent for the rebuild
+    match options.recompute_normals {
+        RecomputeNormals::Off => {}
+        RecomputeNormals::Smooth => recompute_vertex_normals(&mut mesh, false),
+        RecomputeNormals::SmoothingGroups => recompute_vertex_normals(&mut mesh, true),
+    }
+    Ok(mesh)
+}
+
+/// Reverse every face whose geometric normal (counter-clockwise front) points away from the average of its vertex normals,
+/// returns how many were flipped. Faces without normals, or whose normals cancel out, are left alone
+pub fn fix_winding(mesh: &mut ObjMesh) -> usize {
+    let ObjMesh { positions, normals, faces, .. } = mesh;
+    if normals.is_empty() {
+        return 0;
+    }
+    let mut flipped = 0;
+    for face in faces.iter_mut() {
+        if face.v_indices.len() < 3 {
+            continue
+        }
+        let face_normal = polygon_normal(positions, &face.v_indices);
+        let mut shading_normal = Point3D { x: 0.0, y: 0.0, z: 0.0 };
+        for normal in face.vn_indices.iter().filter_map(|&vn| normals.get(vn)) {
+            shading_normal += *normal;
+        }
+        if point3d::dot3(face_normal, shading_normal) < 0.0 {
+            face.v_indices.reverse();
+            face.vt_indices.reverse();
+            face.vn_indices.reverse();
+            flipped += 1;
+        }
+    }
+    flipped
+}
+
+/// Throw away the mesh's normals and rebuild them from face geometry, each face contributes its area-weighted normal to its corners.
+/// With smoothing groups respected, faces only share normals within the same group and faces with smoothing off come out flat
+pub fn recompute_vertex_normals(mesh: &mut ObjMesh, respect_smoothing_groups: bool) {
+    let ObjMesh { positions, normals, faces, .. } = mesh;
+    let mut accumulated: Vec<Point3D> = Vec::new();
+    // (position, smoothing group, face for flat shading) -> accumulated normal
+    let mut lookup: HashMap<(usize, u32, usize), usize> = HashMap::new();
+
+    for (face_index, face) in faces.iter_mut().enumerate() {
+        if face.v_indices.len() < 3 {
+            continue
+        }
+        let face_normal = polygon_normal(positions, &face.v_indices);
+
+        face.vn_indices = face.v_indices.iter().map(|&v| {
+            let key = match (respect_smoothing_groups, face.smoothing_group) {
+                (false, _) => (v, 0, 0),
+                (true, 0) => (v, 0, face_index),
+                (true, group) => (v, group, 0),
+            };
+            let index = *lookup.entry(key).or_insert_with(|| {
+                accumulated.push(Point3D { x: 0.0, y: 0.0, z: 0.0 });
+                accumulated.len() - 1
+            });
+            accumulated[index] += face_normal;
+            index
+        }).collect();
+    }
+
+    *normals = accumulated.into_iter().map(point3d::normalize).collect();
+}
+
+// The fan's cross products sum to the polygon normal (counter-clockwise front) scaled by twice its area
+fn polygon_normal(positions: &[Point3D], v_indices: &[usize]) -> Point3D {
+    let origin = positions[v_indices[0]];
+    let mut normal = Point3D { x: 0.0, y: 0.0, z: 0.0 };
+    for i in 1..v_indices.len() - 1 {
+        normal += point3d::cross3(positions[v_indices[i]] - origin, positions[v_indices[i + 1]] - origin);
+    }
+    normal
 }
 
 fn parse_face_vertex(s: &str) -> Result<(usize, Option<usize>, Option<usize>)> {
@@ -81,32 +371,410 @@ fn parse_face_vertex(s: &str) -> Result<(usize, Option<usize>, Option<usize>)> {
     Ok((v, vt, vn))
 }
 
-pub fn fan_triangulate_faces(faces: &[Face], vertices: &[Point3D], texture_coords: &[Point2D], vertex_normals: &[Point3D]) -> Vec<Triangle3D> {
+/// `flip_v` maps texcoords to (u, 1 - v) for exporters with the opposite V convention.
+/// A mesh with no texcoords at all gets them from `uv_gen`, texcoords in the file always win
+pub fn fan_triangulate_faces(faces: &[Face], vertices: &[Point3D], vertex_alphas: &[f32], texture_coords: &[Point2D], vertex_normals: &[Point3D], flip_v: bool, uv_gen: UvGenMode) -> Vec<Triangle3D> {
+    let texcoord = |index: usize| flip_texcoord(texture_coords.get(index).copied().unwrap_or(Point2D { x: 0.0, y: 0.0 }), flip_v);
+    let uv_gen = if texture_coords.is_empty() { uv_gen } else { UvGenMode::None };
+    let projection = UvProjection::new(vertices);
     let mut triangles: Vec<Triangle3D> = Vec::new();
 
-    for face in faces {
+    for (face_index, face) in faces.iter().enumerate() {
         let v_indices: &Vec<usize> = &face.v_indices;
         let vt_indices: &Vec<usize> = &face.vt_indices;
         let vn_indices: &Vec<usize> = &face.vn_indices;
         if v_indices.len() < 3 {
             continue // skip faces already triangled
         }
+        // Corners without a vn in the file are shaded flat
+        let face_normal = point3d::normalize(polygon_normal(vertices, v_indices));
+        let normal = |corner: usize| vn_indices.get(corner).and_then(|&vn| vertex_normals.get(vn)).copied().unwrap_or(face_normal);
 
         for i in 1..v_indices.len() - 1 {
             let a: Point3D = vertices[v_indices[0]];
             let b: Point3D = vertices[v_indices[i]];
             let c: Point3D = vertices[v_indices[i + 1]];
 
-            let ta: Point2D = texture_coords[vt_indices[0]];
-            let tb: Point2D = texture_coords[vt_indices[i]];
-            let tc: Point2D = texture_coords[vt_indices[i+1]];
+            let ta: Point2D = texcoord(vt_indices[0]);
+            let tb: Point2D = texcoord(vt_indices[i]);
+            let tc: Point2D = texcoord(vt_indices[i+1]);
+
+            let alpha_a: f32 = vertex_alphas[v_indices[0]];
+            let alpha_b: f32 = vertex_alphas[v_indices[i]];
+            let alpha_c: f32 = vertex_alphas[v_indices[i + 1]];
+
+            let na: Point3D = normal(0);
+            let nb: Point3D = normal(i);
+            let nc: Point3D = normal(i + 1);
 
-            let na: Point3D = vertex_normals[vn_indices[0]];
-            let nb: Point3D = vertex_normals[vn_indices[i]];
-            let nc: Point3D = vertex_normals[vn_indices[i+1]];
+            let axis = match uv_gen {
+                UvGenMode::None => None,
+                UvGenMode::Planar => Some(projection.thinnest_axis),
+                UvGenMode::Triplanar => Some(dominant_axis(point3d::cross3(b - a, c - a))),
+            };
+            let (ta, tb, tc) = match axis {
+                Some(axis) => (projection.project(a, axis), projection.project(b, axis), projection.project(c, axis)),
+                None => (ta, tb, tc),
+            };
 
-            triangles.push(Triangle3D { a, b, c, ta, tb, tc, na, nb, nc, bb_start_x: 0, bb_start_y: 0, bb_end_x: 0, bb_end_y: 0 });
+            triangles.push(Triangle3D { a, b, c, ta, tb, tc, na, nb, nc, alpha_a, alpha_b, alpha_c, wa: a, wb: b, wc: c, material: face.material, face: face_index as u32, bb_start_x: 0, bb_start_y: 0, bb_end_x: 0, bb_end_y: 0, setup: EdgeSetup::default(), id: 0 });
         }
     }
     triangles
 }
+
+/// Fan triangulate into an indexed mesh, vertices are keyed on the full (v, vt, vn) triple so a position shared by faces with different UVs or normals (seams, hard edges) becomes separate vertices.
+/// Corners without a normal in the file are shaded flat with their face's normal
+pub fn fan_triangulate_indexed(faces: &[Face], vertices: &[Point3D], vertex_alphas: &[f32], texture_coords: &[Point2D], vertex_normals: &[Point3D], flip_v: bool) -> IndexedMesh {
+    let mut mesh = IndexedMesh::default();
+    let mut lookup: HashMap<(usize, usize, usize), u32> = HashMap::new();
+
+    for (face_index, face) in faces.iter().enumerate() {
+        if face.v_indices.len() < 3 {
+            continue
+        }
+        let face_normal = point3d::normalize(polygon_normal(vertices, &face.v_indices));
+        let mut index_of = |corner: usize| -> u32 {
+            // Keys past the file's normals stand for this face's flat normal so faces don't share it
+            let vn = match vertex_normals.get(face.vn_indices[corner]) {
+                Some(_) => face.vn_indices[corner],
+                None => vertex_normals.len() + face_index,
+            };
+            let key = (face.v_indices[corner], face.vt_indices[corner], vn);
+            *lookup.entry(key).or_insert_with(|| {
+                mesh.vertices.push(Vertex {
+                    position: vertices[key.0],
+                    texcoord: flip_texcoord(texture_coords.get(key.1).copied().unwrap_or(Point2D { x: 0.0, y: 0.0 }), flip_v),
+                    normal: vertex_normals.get(key.2).copied().unwrap_or(face_normal),
+                    alpha: vertex_alphas[key.0],
+                });
+                u32::try_from(mesh.vertices.len() - 1).expect("indexed mesh has more than u32::MAX unique vertices")
+            })
+        };
+
+        let first = index_of(0);
+        for i in 1..face.v_indices.len() - 1 {
+            let b = index_of(i);
+            let c = index_of(i + 1);
+            mesh.indices.extend_from_slice(&[first, b, c]);
+            mesh.materials.push(face.material);
+            mesh.faces.push(face_index as u32);
+        }
+    }
+    mesh
+}
+
+/// Texcoords generated from positions, for meshes exported without any
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UvGenMode {
+    // Only the file's texcoords, (0, 0) if it has none
+    #[default]
+    None,
+    // One projection for the whole mesh along its bounding box's thinnest axis, spanning [0, 1] across the box
+    Planar,
+    // Each face projected along the axis closest to its normal (box mapping). Blending all three projections needs
+    // three texture samples per fragment, with one texcoord per vertex each face takes its best one
+    Triplanar,
+}
+
+// Bounding box the generated texcoords are normalized to
+struct UvProjection {
+    min: Point3D,
+    extent: Point3D,
+    thinnest_axis: usize,
+}
+
+impl UvProjection {
+    fn new(vertices: &[Point3D]) -> Self {
+        let inf = Point3D { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY };
+        let (min, max) = vertices.iter().fold((inf, inf * -1.0), |(lo, hi), p| {
+            (Point3D { x: lo.x.min(p.x), y: lo.y.min(p.y), z: lo.z.min(p.z) }, Point3D { x: hi.x.max(p.x), y: hi.y.max(p.y), z: hi.z.max(p.z) })
+        });
+        let extent = max - min;
+        let extents = [extent.x, extent.y, extent.z];
+        let thinnest_axis = (0..3).min_by(|&i, &j| extents[i].total_cmp(&extents[j])).unwrap_or(1);
+        Self { min, extent, thinnest_axis }
+    }
+
+    // Drop `axis` and normalize the other two coordinates to [0, 1] over the box, a flat extent maps to 0
+    fn project(&self, p: Point3D, axis: usize) -> Point2D {
+        let normalized = |value: f32, min: f32, extent: f32| if extent > 0.0 { (value - min) / extent } else { 0.0 };
+        let x = normalized(p.x, self.min.x, self.extent.x);
+        let y = normalized(p.y, self.min.y, self.extent.y);
+        let z = normalized(p.z, self.min.z, self.extent.z);
+        match axis {
+            0 => Point2D { x: z, y },
+            1 => Point2D { x, y: z },
+            _ => Point2D { x, y },
+        }
+    }
+}
+
+// Index of the largest component of `n` by magnitude
+fn dominant_axis(n: Point3D) -> usize {
+    let (x, y, z) = (n.x.abs(), n.y.abs(), n.z.abs());
+    if x >= y && x >= z { 0 } else if y >= z { 1 } else { 2 }
+}
+
+#[inline(always)]
+fn flip_texcoord(texcoord: Point2D, flip_v: bool) -> Point2D {
+    if flip_v { Point2D { x: texcoord.x, y: 1.0 - texcoord.y } } else { texcoord }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::WrapMode;
+
+    // Unit cube with one normal per face and the same four texcoords on every face, like most exporters write it
+    fn cube() -> ObjMesh {
+        let positions: Vec<Point3D> = (0..8)
+            .map(|i| Point3D { x: (i & 1) as f32, y: ((i >> 1) & 1) as f32, z: ((i >> 2) & 1) as f32 })
+            .collect();
+        let normals = vec![
+            Point3D { x: -1.0, y: 0.0, z: 0.0 },
+            Point3D { x: 1.0, y: 0.0, z: 0.0 },
+            Point3D { x: 0.0, y: -1.0, z: 0.0 },
+            Point3D { x: 0.0, y: 1.0, z: 0.0 },
+            Point3D { x: 0.0, y: 0.0, z: -1.0 },
+            Point3D { x: 0.0, y: 0.0, z: 1.0 },
+        ];
+        let texcoords = vec![
+            Point2D { x: 0.0, y: 0.0 },
+            Point2D { x: 1.0, y: 0.0 },
+            Point2D { x: 1.0, y: 1.0 },
+            Point2D { x: 0.0, y: 1.0 },
+        ];
+        // Counter-clockwise seen from outside, in the same order as the normals
+        let quads = [[0, 4, 6, 2], [1, 3, 7, 5], [0, 1, 5, 4], [2, 6, 7, 3], [0, 2, 3, 1], [4, 5, 7, 6]];
+        let faces = quads.iter().enumerate().map(|(normal, quad)| Face {
+            v_indices: quad.to_vec(),
+            vt_indices: vec![0, 1, 2, 3],
+            vn_indices: vec![normal; 4],
+            material: 0,
+            smoothing_group: 0,
+        }).collect();
+        ObjMesh { alphas: vec![1.0; positions.len()], positions, texcoords, normals, faces, materials: vec![Material::default()], origin: [0.0; 3] }
+    }
+
+    #[test]
+    fn recomputed_normals_point_out_of_a_convex_mesh() {
+        let center = Point3D { x: 0.5, y: 0.5, z: 0.5 };
+        for respect_smoothing_groups in [false, true] {
+            let mut mesh = cube();
+            // Every file normal pointing inward, as from an export with the normals flipped
+            for normal in &mut mesh.normals {
+                *normal = *normal * -1.0;
+            }
+            recompute_vertex_normals(&mut mesh, respect_smoothing_groups);
+            for face in &mesh.faces {
+                for (&v, &vn) in face.v_indices.iter().zip(&face.vn_indices) {
+                    let normal = mesh.normals[vn];
+                    assert!((point3d::dot3(normal, normal) - 1.0).abs() < 1e-5);
+                    assert!(point3d::dot3(normal, mesh.positions[v] - center) > 0.0);
+                }
+            }
+            // Shared corners without groups, one flat normal per face corner with smoothing off everywhere
+            assert_eq!(mesh.normals.len(), if respect_smoothing_groups { 24 } else { 8 });
+        }
+    }
+
+    #[test]
+    fn indexed_cube_splits_vertices_on_normal_seams() {
+        let mesh = cube();
+        let indexed = fan_triangulate_indexed(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, false);
+        assert_eq!(indexed.vertices.len(), 24);
+        assert_eq!(indexed.indices.len(), 36);
+        assert_eq!(indexed.materials.len(), 12);
+    }
+
+    #[test]
+    fn indexed_triangles_match_direct_triangulation() {
+        let mesh = cube();
+        let indexed = fan_triangulate_indexed(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, true);
+        let direct = fan_triangulate_faces(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, true, UvGenMode::None);
+        assert_eq!(indexed.to_triangles(), direct);
+    }
+
+    #[test]
+    fn huge_texcoords_are_flagged_and_clamp_is_read_per_material() {
+        let dir = std::env::temp_dir().join(format!("rusterizer-uv-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("uv.mtl"), "newmtl tiled\nmap_Kd a.png\nnewmtl clamped\nmap_Kd -clamp on b.png\n").unwrap();
+        std::fs::write(dir.join("uv.obj"), "mtllib uv.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1.001 0\nvt 0 250\nusemtl clamped\nf 1/1 2/2 3/3\n").unwrap();
+        let mesh = parse_obj(dir.join("uv.obj").to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Only the 250 is wild, the 1.001 is ordinary float error
+        assert_eq!(count_wild_texcoords(&mesh.texcoords), 1);
+        let wraps: Vec<WrapMode> = mesh.materials.iter().map(|material| material.wrap).collect();
+        assert_eq!(wraps, [WrapMode::Repeat, WrapMode::Repeat, WrapMode::Clamp]);
+        assert_eq!(mesh.faces[0].material, 2);
+    }
+
+    #[test]
+    fn faces_wound_against_their_normals_are_flipped() {
+        let original = cube();
+        let mut mesh = cube();
+        // Two faces reversed, as from an exporter that mirrored part of the mesh
+        for face in [1, 4] {
+            mesh.faces[face].v_indices.reverse();
+            mesh.faces[face].vt_indices.reverse();
+        }
+        assert_eq!(fix_winding(&mut mesh), 2);
+        for (fixed, original) in mesh.faces.iter().zip(&original.faces) {
+            let a = mesh.positions[fixed.v_indices[0]];
+            let face_normal = point3d::cross3(mesh.positions[fixed.v_indices[1]] - a, mesh.positions[fixed.v_indices[2]] - a);
+            assert!(point3d::dot3(face_normal, mesh.normals[fixed.vn_indices[0]]) > 0.0);
+            // Texcoords stay attached to their corners
+            let corners = |face: &Face| {
+                let mut corners: Vec<(usize, usize)> = face.v_indices.iter().copied().zip(face.vt_indices.iter().copied()).collect();
+                corners.sort();
+                corners
+            };
+            assert_eq!(corners(fixed), corners(original));
+        }
+        // Consistent faces are left alone
+        assert_eq!(fix_winding(&mut mesh), 0);
+    }
+
+    #[test]
+    fn z_up_import_puts_the_tallest_vertex_on_top() {
+        let path = std::env::temp_dir().join(format!("rusterizer-z-up-{}.obj", std::process::id()));
+        // A tent whose peak is the only vertex with a large Z, with a normal pointing up in Z
+        std::fs::write(&path, "v 0 0 0\nv 2 0 0\nv 0 -2 0\nv 1 -1 5\nvn 0 0 1\nf 1//1 2//1 4//1\nf 2//1 3//1 4//1\n").unwrap();
+        let options = ObjLoadOptions { up_axis: UpAxis::Z, ..ObjLoadOptions::default() };
+        let mesh = load_obj(path.to_str().unwrap(), &options);
+        std::fs::remove_file(&path).unwrap();
+        let mesh = mesh.unwrap();
+
+        let top = mesh.positions.iter().copied().fold(mesh.positions[0], |top, p| if p.y > top.y { p } else { top });
+        assert_eq!(top, mesh.positions[3]);
+        assert_eq!(top.y - mesh.positions[0].y, 5.0);
+        // -Y forward becomes +Z, and normals turn with the positions
+        assert_eq!(mesh.positions[2].z - mesh.positions[0].z, 2.0);
+        assert_eq!(mesh.normals[0], Point3D { x: 0.0, y: 1.0, z: 0.0 });
+    }
+
+    #[test]
+    fn indexed_faces_without_normals_are_shaded_flat() {
+        let path = std::env::temp_dir().join(format!("rusterizer-no-vn-{}.obj", std::process::id()));
+        // Two faces sharing an edge at a right angle, with texcoords but no vn
+        std::fs::write(&path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nvt 0 0\nvt 1 0\nvt 0 1\nf 1/1 2/2 3/3\nf 1/1 4/3 2/2\n").unwrap();
+        let mesh = parse_obj(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let mesh = mesh.unwrap();
+        assert!(mesh.normals.is_empty());
+
+        let indexed = fan_triangulate_indexed(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, false);
+        // The shared corners are split so each face keeps its own normal
+        assert_eq!(indexed.vertices.len(), 6);
+        let triangles = indexed.to_triangles();
+        assert_eq!([triangles[0].na, triangles[0].nb, triangles[0].nc], [Point3D { x: 0.0, y: 0.0, z: 1.0 }; 3]);
+        assert_eq!([triangles[1].na, triangles[1].nb, triangles[1].nc], [Point3D { x: 0.0, y: 1.0, z: 0.0 }; 3]);
+    }
+
+    #[test]
+    fn relative_texture_paths_resolve_from_the_material_library() {
+        // model/scene.obj -> model/materials/scene.mtl -> model/textures/rgba.png, none of it under the working directory
+        let dir = std::env::temp_dir().join(format!("rusterizer-relative-{}", std::process::id())).join("model");
+        std::fs::create_dir_all(dir.join("materials")).unwrap();
+        std::fs::create_dir_all(dir.join("textures")).unwrap();
+        std::fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/rgba_2x2.png"), dir.join("textures/rgba.png")).unwrap();
+        std::fs::write(dir.join("materials/scene.mtl"), "newmtl painted\nmap_Kd ../textures/rgba.png\n").unwrap();
+        std::fs::write(dir.join("scene.obj"), "mtllib materials/scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl painted\nf 1 2 3\n").unwrap();
+        let mesh = parse_obj(dir.join("scene.obj").to_str().unwrap()).unwrap();
+        let texture = mesh.materials[mesh.faces[0].material].diffuse_texture.clone().map(crate::texture::Texture::load);
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+
+        let texture = texture.expect("painted has a map_Kd").unwrap();
+        assert_eq!((texture.width, texture.height), (2, 2));
+    }
+
+    #[test]
+    fn flip_v_inverts_only_the_v_coordinate() {
+        let mesh = cube();
+        let triangulate = |flip_v| fan_triangulate_faces(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, flip_v, UvGenMode::None);
+        let (plain, flipped) = (triangulate(false), triangulate(true));
+        assert_eq!(plain.len(), flipped.len());
+        for (plain, flipped) in plain.iter().zip(&flipped) {
+            for (p, f) in [(plain.ta, flipped.ta), (plain.tb, flipped.tb), (plain.tc, flipped.tc)] {
+                assert_eq!((f.x, f.y), (p.x, 1.0 - p.y));
+            }
+        }
+    }
+
+    #[test]
+    fn empty_obj_files_fail_with_a_descriptive_error() {
+        for (name, source) in [("empty", ""), ("blank", "  \n\t\n# only a comment\n")] {
+            let path = std::env::temp_dir().join(format!("rusterizer-{}-{}.obj", name, std::process::id()));
+            std::fs::write(&path, source).unwrap();
+            let path = path.to_str().unwrap();
+            let result = load_obj(path, &ObjLoadOptions::default());
+            std::fs::remove_file(path).unwrap();
+            match result {
+                Err(RusterizerError::Obj { message, .. }) => assert!(message.contains("has no vertices"), "{}", message),
+                other => panic!("{} file gave {:?}", name, other.map(|mesh| mesh.faces.len())),
+            }
+        }
+    }
+
+    #[test]
+    fn recentering_keeps_centimeter_detail_at_a_million_units() {
+        let path = std::env::temp_dir().join(format!("rusterizer-recenter-{}.obj", std::process::id()));
+        // A 1cm triangle a million units out, where f32 spacing is 6cm and the corners would collapse together
+        std::fs::write(&path, "v 1000000.00 2000000.00 -1000000.00\nv 1000000.01 2000000.00 -1000000.00\nv 1000000.00 2000000.01 -1000000.00\nf 1 2 3\n").unwrap();
+        let plain = load_obj(path.to_str().unwrap(), &ObjLoadOptions::default());
+        let options = ObjLoadOptions { recenter: true, ..ObjLoadOptions::default() };
+        let recentered = load_obj(path.to_str().unwrap(), &options);
+        std::fs::remove_file(&path).unwrap();
+        let (plain, recentered) = (plain.unwrap(), recentered.unwrap());
+
+        assert_eq!(plain.origin, [0.0; 3]);
+        assert_eq!(plain.positions[0], plain.positions[1]);
+        let p = &recentered.positions;
+        assert!((p[1].x - p[0].x - 0.01).abs() < 1e-5 && (p[2].y - p[0].y - 0.01).abs() < 1e-5);
+        // The centroid is at the origin and the shift puts it back at the file's coordinates
+        assert!((p[0].x + p[1].x + p[2].x).abs() < 1e-5 && (p[0].y + p[1].y + p[2].y).abs() < 1e-5);
+        let offset = recentered.origin_offset();
+        assert!((offset.x - 1_000_000.0).abs() < 1.0 && (offset.y - 2_000_000.0).abs() < 1.0 && (offset.z + 1_000_000.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn faces_using_an_inf_vertex_are_skipped() {
+        let path = std::env::temp_dir().join(format!("rusterizer-inf-{}.obj", std::process::id()));
+        // The second face shares two good corners with the first and uses the inf vertex as its third
+        std::fs::write(&path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nv inf 1 0\nvn 0 0 1\nvn nan 0 1\nf 1//1 2//1 3//1\nf 2//1 4//1 3//1\nf 1//2 2//2 3//2\n").unwrap();
+        let mesh = load_obj(path.to_str().unwrap(), &ObjLoadOptions::default());
+        std::fs::remove_file(&path).unwrap();
+        let mesh = mesh.unwrap();
+
+        // Only the face with finite positions and normals is left, and nothing it uses is non-finite
+        assert_eq!(mesh.faces.len(), 1);
+        assert_eq!(mesh.faces[0].v_indices, [0, 1, 2]);
+        let triangles = fan_triangulate_faces(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, false, UvGenMode::None);
+        assert!(triangles.iter().all(|t| [t.a, t.b, t.c, t.na, t.nb, t.nc].iter().all(|p| p.x.is_finite() && p.y.is_finite() && p.z.is_finite())));
+    }
+
+    #[test]
+    fn position_only_faces_get_flat_normals_and_planar_uvs_spanning_the_unit_square() {
+        let path = std::env::temp_dir().join(format!("rusterizer-positions-only-{}.obj", std::process::id()));
+        // A 4x2 quad on the z = 3 plane split into two faces, with no vt or vn anywhere
+        std::fs::write(&path, "v -1 5 3\nv 3 5 3\nv 3 7 3\nv -1 7 3\nf 1 2 3\nf 1 3 4\n").unwrap();
+        let mesh = parse_obj(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let mesh = mesh.unwrap();
+        assert!(mesh.texcoords.is_empty() && mesh.normals.is_empty());
+
+        let triangles = fan_triangulate_faces(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, false, UvGenMode::Planar);
+        assert_eq!(triangles.len(), 2);
+        let up = Point3D { x: 0.0, y: 0.0, z: 1.0 };
+        assert!(triangles.iter().all(|t| [t.na, t.nb, t.nc] == [up; 3]));
+        // The flat axis is dropped and the other two are stretched over [0, 1]
+        let uvs: Vec<Point2D> = triangles.iter().flat_map(|t| [t.ta, t.tb, t.tc]).collect();
+        let range = |coord: fn(&Point2D) -> f32| uvs.iter().map(coord).fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), c| (lo.min(c), hi.max(c)));
+        assert_eq!(range(|uv| uv.x), (0.0, 1.0));
+        assert_eq!(range(|uv| uv.y), (0.0, 1.0));
+        assert_eq!(triangles[0].tb, Point2D { x: 1.0, y: 0.0 });
+    }
+}
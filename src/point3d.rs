@@ -1,6 +1,5 @@
 use std::simd::f32x4;
 use std::simd::StdFloat;
-use std::simd::num::SimdFloat;
 use std::simd::cmp::SimdPartialEq;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -131,24 +130,65 @@ pub fn dot3_simd(a: Point3Dx4, b: Point3Dx4) -> f32x4 {
     a.x * b.x + a.y * b.y + a.z * b.z
 }
 
+#[inline(always)]
+pub fn cross3(a: Point3D, b: Point3D) -> Point3D {
+    Point3D {
+        x: a.y * b.z - a.z * b.y,
+        y: a.z * b.x - a.x * b.z,
+        z: a.x * b.y - a.y * b.x,
+    }
+}
+
+#[inline(always)]
+pub fn lerp(a: Point3D, b: Point3D, t: f32) -> Point3D {
+    a + (b - a) * t
+}
+
+/// Unit vector in the direction of `vec`, a zero vector is returned unchanged
 #[inline(always)]
 pub fn normalize(vec: Point3D) -> Point3D {
     let length = dot3(vec, vec).sqrt();
     if length != 0.0 { vec / length } else { vec }
 }
 
+/// Four-lane normalize, matches the scalar version lane for lane: zero-length lanes are returned unchanged
 #[inline(always)]
 pub fn normalize_simd(vec: Point3Dx4) -> Point3Dx4 {
     let length = dot3_simd(vec, vec).sqrt();
-    
-    let mask = length.simd_ne(f32x4::splat(0.0));
-
-    // Safe reciprocal: if length != 0 use 1/length else 1.0
-    let inv_length = mask.select(length.recip(), f32x4::splat(1.0));
+    let nonzero = length.simd_ne(f32x4::splat(0.0));
 
+    // Divide like the scalar path rather than multiplying by a reciprocal, so both round the same way
     Point3Dx4 {
-        x: vec.x * inv_length,
-        y: vec.y * inv_length,
-        z: vec.z * inv_length,
+        x: nonzero.select(vec.x / length, vec.x),
+        y: nonzero.select(vec.y / length, vec.y),
+        z: nonzero.select(vec.z / length, vec.z),
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simd_normalize_matches_scalar_lane_for_lane() {
+        let vectors = [
+            Point3D { x: 0.0, y: 0.0, z: 0.0 },
+            Point3D { x: 0.0, y: 1.0, z: 0.0 },
+            Point3D { x: 3.0, y: 4.0, z: 0.0 },
+            Point3D { x: -1.0, y: 2.0, z: 7.5 },
+        ];
+        let lanes = Point3Dx4 {
+            x: f32x4::from_array(vectors.map(|v| v.x)),
+            y: f32x4::from_array(vectors.map(|v| v.y)),
+            z: f32x4::from_array(vectors.map(|v| v.z)),
+        };
+        let normalized = normalize_simd(lanes);
+        for (lane, &vector) in vectors.iter().enumerate() {
+            let simd = Point3D { x: normalized.x[lane], y: normalized.y[lane], z: normalized.z[lane] };
+            assert_eq!(simd, normalize(vector));
+        }
+        // The zero vector comes back unchanged and the unit vector as it was
+        assert_eq!(normalize(vectors[0]), vectors[0]);
+        assert_eq!(normalize(vectors[1]), vectors[1]);
+        assert_eq!(normalize(vectors[2]), Point3D { x: 0.6, y: 0.8, z: 0.0 });
+    }
+}
@@ -0,0 +1,196 @@
+use rayon::prelude::*;
+
+use crate::rectangle::Rect;
+use crate::screen::ScreenSpace;
+use crate::shading::{linear_to_srgb, srgb_to_linear};
+
+// Linear mid-gray, contrast pivots around it so midtones keep their brightness
+const MID_GRAY: f32 = 0.18;
+// Rec. 709 luma weights for linear RGB
+const LUMA_WEIGHTS: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Copy of one tile plus a halo of neighboring pixels, taken before any tile is written so neighbor reads never see filtered output
+pub struct TileView {
+    rgba: Vec<u8>,
+    // Screen position and size of the copied region, the halo is cut short at screen edges
+    origin_x: u32,
+    origin_y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl TileView {
+    fn capture(screen: &ScreenSpace, tile: &Rect, radius: u32) -> Self {
+        let origin_x = tile.min_x.saturating_sub(radius);
+        let origin_y = tile.min_y.saturating_sub(radius);
+        let width = (tile.max_x + radius).min(screen.width) - origin_x;
+        let height = (tile.max_y + radius).min(screen.height) - origin_y;
+        let src = unsafe { &*screen.rgba.get() };
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in origin_y..origin_y + height {
+            let start = (y as usize * screen.width as usize + origin_x as usize) * 4;
+            rgba.extend_from_slice(&src[start..start + width as usize * 4]);
+        }
+        Self { rgba, origin_x, origin_y, width, height }
+    }
+
+    /// Pixel at a screen position, positions past the screen edge clamp to it
+    #[inline(always)]
+    pub fn get(&self, x: i32, y: i32) -> [u8; 4] {
+        let local_x = (x - self.origin_x as i32).clamp(0, self.width as i32 - 1) as usize;
+        let local_y = (y - self.origin_y as i32).clamp(0, self.height as i32 - 1) as usize;
+        let i = (local_y * self.width as usize + local_x) * 4;
+        [self.rgba[i], self.rgba[i + 1], self.rgba[i + 2], self.rgba[i + 3]]
+    }
+}
+
+/// Run a filter over the screen one tile per thread, `filter` may read up to `radius` pixels around the pixel it's computing
+pub fn post_process_tiled<F>(screen: &ScreenSpace, tiles: &[Rect], radius: u32, filter: F)
+where
+    F: Fn(&TileView, u32, u32) -> [u8; 4] + Sync,
+{
+    // Every halo is captured before anything is written, otherwise a tile could read a neighbor's already filtered pixels
+    let views: Vec<TileView> = tiles.par_iter().map(|tile| TileView::capture(screen, tile, radius)).collect();
+    tiles.par_iter().zip(views.par_iter()).for_each(|(tile, view)| {
+        for y in tile.min_y..tile.max_y {
+            for x in tile.min_x..tile.max_x {
+                let [r, g, b, a] = filter(view, x, y);
+                screen.unsafe_set_pixel(x, y, r, g, b, a);
+            }
+        }
+    });
+}
+
+/// Mean of the (2 * radius + 1)^2 square around a pixel, use with post_process_tiled and the same radius
+pub fn box_blur(view: &TileView, x: u32, y: u32, radius: u32) -> [u8; 4] {
+    let r = radius as i32;
+    let mut sum = [0u32; 4];
+    for dy in -r..=r {
+        for dx in -r..=r {
+            let texel = view.get(x as i32 + dx, y as i32 + dy);
+            for channel in 0..4 {
+                sum[channel] += texel[channel] as u32;
+            }
+        }
+    }
+    let count = ((2 * r + 1) * (2 * r + 1)) as u32;
+    sum.map(|s| ((s + count / 2) / count) as u8)
+}
+
+/// Final look adjustments over the finished frame. The screen holds sRGB bytes, grading decodes them to linear light first so
+/// exposure behaves like a camera's and saturation doesn't shift hues, then re-encodes. Alpha is untouched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGrade {
+    // In stops, each +1 doubles the light
+    pub exposure: f32,
+    // Power curve around mid-gray, 1 is neutral, above 1 darkens shadows and brightens highlights
+    pub contrast: f32,
+    // 0 is grayscale, 1 is neutral
+    pub saturation: f32,
+}
+
+impl Default for ColorGrade {
+    fn default() -> Self {
+        Self { exposure: 0.0, contrast: 1.0, saturation: 1.0 }
+    }
+}
+
+impl ColorGrade {
+    pub fn is_neutral(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn grade_pixel(&self, [r, g, b, a]: [u8; 4]) -> [u8; 4] {
+        let scale = self.exposure.exp2();
+        let graded = [r, g, b].map(|c| {
+            let c = srgb_to_linear(c) * scale;
+            if c > 0.0 { MID_GRAY * (c / MID_GRAY).powf(self.contrast) } else { 0.0 }
+        });
+        let luma: f32 = graded.iter().zip(LUMA_WEIGHTS).map(|(c, w)| c * w).sum();
+        let [r, g, b] = graded.map(|c| linear_to_srgb((luma + (c - luma) * self.saturation).max(0.0)));
+        [r, g, b, a]
+    }
+
+    /// Grade the whole screen in place, one tile per thread. Does nothing at the neutral settings
+    pub fn apply(&self, screen: &ScreenSpace, tiles: &[Rect]) {
+        if self.is_neutral() {
+            return;
+        }
+        post_process_tiled(screen, tiles, 0, |view, x, y| self.grade_pixel(view.get(x as i32, y as i32)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::subdivide_count;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn tiled_box_blur_matches_a_serial_blur_across_tile_borders() {
+        let (width, height, radius) = (37u32, 23u32, 2u32);
+        let screen = ScreenSpace::new(width, height);
+        let mut rng = StdRng::seed_from_u64(418);
+        for y in 0..height {
+            for x in 0..width {
+                screen.unsafe_set_pixel(x, y, rng.gen_range(0..=255u8), rng.gen_range(0..=255u8), rng.gen_range(0..=255u8), 255);
+            }
+        }
+        let source: Vec<(u8, u8, u8, u8)> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| screen.get_pixel(x, y)).collect();
+
+        // Serial reference straight from the unfiltered pixels, clamping at the screen edge
+        let serial = |x: u32, y: u32| {
+            let r = radius as i32;
+            let mut sum = [0u32; 4];
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    let sx = (x as i32 + dx).clamp(0, width as i32 - 1) as u32;
+                    let sy = (y as i32 + dy).clamp(0, height as i32 - 1) as u32;
+                    let (pr, pg, pb, pa) = source[(sy * width + sx) as usize];
+                    for (channel, value) in [pr, pg, pb, pa].into_iter().enumerate() {
+                        sum[channel] += value as u32;
+                    }
+                }
+            }
+            let count = (2 * radius + 1) * (2 * radius + 1);
+            let [r, g, b, a] = sum.map(|s| ((s + count / 2) / count) as u8);
+            (r, g, b, a)
+        };
+
+        let tiles = subdivide_count(width, height, 6);
+        post_process_tiled(&screen, &tiles, radius, |view, x, y| box_blur(view, x, y, radius));
+        let mut border_pixels = 0;
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(screen.get_pixel(x, y), serial(x, y), "({}, {})", x, y);
+                // Within the radius of another tile, where the halo is read
+                let tile = tiles.iter().find(|t| (t.min_x..t.max_x).contains(&x) && (t.min_y..t.max_y).contains(&y)).unwrap();
+                let near_x = (x - tile.min_x < radius && tile.min_x > 0) || (tile.max_x - x <= radius && tile.max_x < width);
+                let near_y = (y - tile.min_y < radius && tile.min_y > 0) || (tile.max_y - y <= radius && tile.max_y < height);
+                border_pixels += (near_x || near_y) as u32;
+            }
+        }
+        assert!(border_pixels > 100, "{}", border_pixels);
+    }
+
+    #[test]
+    fn contrast_keeps_mid_gray_and_squares_the_distance_from_it() {
+        let mid = linear_to_srgb(MID_GRAY);
+        let half = linear_to_srgb(MID_GRAY * 0.5);
+        let screen = ScreenSpace::new(8, 6);
+        screen.clear(mid, mid, mid, 255);
+        screen.unsafe_set_pixel(3, 2, half, half, half, 255);
+        let grade = ColorGrade { contrast: 2.0, ..ColorGrade::default() };
+        grade.apply(&screen, &subdivide_count(8, 6, 2));
+
+        // Mid-gray is the pivot, within the byte rounding of its sRGB encoding
+        let (r, g, b, a) = screen.get_pixel(0, 0);
+        assert!(r.abs_diff(mid) <= 1 && (r, r, 255) == (g, b, a), "{mid} became {r}");
+        // Half of mid-gray in linear light becomes a quarter at contrast 2
+        let (r, g, b, _) = screen.get_pixel(3, 2);
+        let quarter = linear_to_srgb(MID_GRAY * 0.25);
+        assert!(r.abs_diff(quarter) <= 1 && r == g && r == b, "{half} became {r}, expected {quarter}");
+        assert!(r < half);
+    }
+}
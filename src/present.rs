@@ -0,0 +1,148 @@
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use crate::render::DrawStats;
+use crate::screen::ScreenSpace;
+
+/// A finished frame handed from the render thread to the presenting thread
+pub struct Frame {
+    // Counts up from 0 in render order, a gap means a frame was dropped
+    pub index: u64,
+    pub rgba: Vec<u8>,
+    pub stats: DrawStats,
+}
+
+/// Render side of a frame queue, frames are moved into the queue whole so the presenter can never read one mid-write
+pub struct FrameProducer {
+    frames: SyncSender<Frame>,
+    recycled: Receiver<Vec<u8>>,
+    next_index: u64,
+}
+
+/// Present side of a frame queue
+pub struct FrameConsumer {
+    frames: Receiver<Frame>,
+    recycled: Sender<Vec<u8>>,
+}
+
+/// Bounded queue of at most `depth` frames in flight, the render thread blocks once it's that far ahead of presentation
+pub fn frame_queue(depth: usize) -> (FrameProducer, FrameConsumer) {
+    let (frame_tx, frame_rx) = mpsc::sync_channel(depth);
+    let (recycle_tx, recycle_rx) = mpsc::channel();
+    (
+        FrameProducer { frames: frame_tx, recycled: recycle_rx, next_index: 0 },
+        FrameConsumer { frames: frame_rx, recycled: recycle_tx },
+    )
+}
+
+impl FrameProducer {
+    /// A buffer of `len` bytes to render into, reusing one the presenter has finished with when possible
+    pub fn buffer(&self, len: usize) -> Vec<u8> {
+        let mut buffer = self.recycled.try_recv().unwrap_or_default();
+        buffer.resize(len, 0);
+        buffer
+    }
+
+    /// Queue a finished frame, returns false once the presenter has hung up so the render loop can stop
+    pub fn submit(&mut self, rgba: Vec<u8>, stats: DrawStats) -> bool {
+        let frame = Frame { index: self.next_index, rgba, stats };
+        self.next_index += 1;
+        self.frames.send(frame).is_ok()
+    }
+
+    /// Copy of the screen's color buffer in a recycled buffer, the screen is free to be cleared for the next frame as soon as this returns
+    pub fn copy_screen(&self, screen: &ScreenSpace) -> Vec<u8> {
+        // Safety: called between draws, no tile threads are writing to the screen
+        let rgba = unsafe { &*screen.rgba.get() };
+        let mut buffer = self.buffer(rgba.len());
+        buffer.copy_from_slice(rgba);
+        buffer
+    }
+
+    /// Queue a copy of the screen's color buffer
+    pub fn submit_screen(&mut self, screen: &ScreenSpace, stats: DrawStats) -> bool {
+        let buffer = self.copy_screen(screen);
+        self.submit(buffer, stats)
+    }
+}
+
+impl FrameConsumer {
+    /// Next frame in render order, blocking until one is ready, None once the render thread has exited
+    pub fn recv(&self) -> Option<Frame> {
+        self.frames.recv().ok()
+    }
+
+    /// Next frame if one is ready, for a present loop that keeps handling input while the renderer catches up
+    pub fn try_recv(&self) -> Option<Frame> {
+        self.frames.try_recv().ok()
+    }
+
+    /// Hand a presented frame's buffer back to the render thread so it isn't reallocated every frame
+    pub fn recycle(&self, frame: Frame) {
+        // The render thread may already have exited, the buffer is just dropped then
+        let _ = self.recycled.send(frame.rgba);
+    }
+}
+
+/// Run `render_frame` on its own thread until it returns None or the consumer is dropped, each returned frame is queued for presentation
+pub fn spawn_render_thread<F>(depth: usize, mut render_frame: F) -> (JoinHandle<()>, FrameConsumer)
+where
+    F: FnMut(&FrameProducer) -> Option<(Vec<u8>, DrawStats)> + Send + 'static,
+{
+    let (mut producer, consumer) = frame_queue(depth);
+    let handle = thread::spawn(move || {
+        while let Some((rgba, stats)) = render_frame(&producer) {
+            if !producer.submit(rgba, stats) {
+                break;
+            }
+        }
+    });
+    (handle, consumer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME_BYTES: usize = 4096;
+
+    #[test]
+    fn every_frame_arrives_whole_and_in_order() {
+        // Each frame is filled with its own number, a frame read mid-write would mix two values
+        let mut rendered = 0u64;
+        let (handle, consumer) = spawn_render_thread(2, move |producer| {
+            if rendered == 100 {
+                return None;
+            }
+            let mut rgba = producer.buffer(FRAME_BYTES);
+            for (i, byte) in rgba.iter_mut().enumerate() {
+                *byte = rendered as u8;
+                // Give the presenter a chance to look at a half-filled buffer
+                if i == FRAME_BYTES / 2 {
+                    thread::yield_now();
+                }
+            }
+            rendered += 1;
+            Some((rgba, DrawStats::default()))
+        });
+
+        let mut expected = 0;
+        while let Some(frame) = consumer.recv() {
+            assert_eq!(frame.index, expected);
+            assert_eq!(frame.rgba.len(), FRAME_BYTES);
+            assert!(frame.rgba.iter().all(|&byte| byte == expected as u8), "frame {} was torn", expected);
+            expected += 1;
+            consumer.recycle(frame);
+        }
+        assert_eq!(expected, 100);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn render_thread_stops_when_the_presenter_hangs_up() {
+        let (handle, consumer) = spawn_render_thread(1, |producer| Some((producer.buffer(16), DrawStats::default())));
+        assert!(consumer.recv().is_some());
+        drop(consumer);
+        handle.join().unwrap();
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Copy)]
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct Rect {
     pub min_x: u32,
     pub min_y: u32,
@@ -14,6 +16,16 @@ impl Rect {
     pub fn height(&self) -> u32 {
         self.max_y - self.min_y
     }
+    /// Overlap of two rectangles, None when they don't share any pixel
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let rect = Rect {
+            min_x: self.min_x.max(other.min_x),
+            min_y: self.min_y.max(other.min_y),
+            max_x: self.max_x.min(other.max_x),
+            max_y: self.max_y.min(other.max_y),
+        };
+        (rect.min_x < rect.max_x && rect.min_y < rect.max_y).then_some(rect)
+    }
 }
 
 // Compute minimum depth to get at least n rectangles for # of CPU cores
@@ -0,0 +1,1755 @@
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use rayon::prelude::*;
+
+use crate::camera::Camera;
+use crate::frustum::{Frustum, bounding_sphere};
+use crate::geometry::{EdgeSetup, PixelCenter, SamplePattern, perspective_interp, perspective_interp3, reconstruct_view_pos, subdivide_count, triangle_misses_rect, view_to_screen};
+use crate::line::draw_line_depth;
+use crate::material::{BlendMode, Material};
+use crate::error::RusterizerError;
+use crate::gltf;
+use crate::obj::{self, ObjLoadOptions, ObjMesh, UvGenMode, fan_triangulate_faces, fan_triangulate_indexed};
+use crate::point2d::Point2D;
+use crate::point3d::{self, Point3D, dot3};
+use crate::rectangle::Rect;
+use crate::screen::{MaskMode, ScreenSpace};
+use crate::shading::{FarPlane, Fog, add_emissive, depth_to_u8, shade_pixel};
+use crate::texture::{HdrTexture, MaterialTextures, Texture, TextureFilter};
+use crate::transform::Transform;
+use crate::triangle::{Triangle3D, id_color, split_large_triangles};
+
+// Fragments with less alpha than this are discarded (alpha test)
+const ALPHA_CUTOFF: u8 = 1;
+// View depth lines are clipped to and triangles are culled against by default, the projection blows up at z = 0.
+// Vertices carry 1/z and the interpolated 1/z has an absolute error of roughly f32::EPSILON / z_min, where z_min is the
+// triangle's nearest vertex. Converted back, the depth error at z is about f32::EPSILON * z^2 / z_min, so triangles reaching
+// close to the camera lose depth precision across their far end. Raising the near plane bounds z_min and with it that error
+const DEFAULT_NEAR_PLANE: f32 = 0.1;
+// Depth shown as black in the depth view by default
+const DEFAULT_SHOW_DEPTH_FAR: f32 = 500.0;
+
+// Per instance, whether any of it can be in view. Transforms are rigid, so the object-space sphere only moves and
+// instances it puts fully outside skip every triangle test
+fn visible_instances(frustum: &Frustum, model: &Model, instances: &[Transform], cam: &Camera) -> Vec<bool> {
+    let (center, radius) = model.bounding_sphere();
+    instances.iter()
+        .map(|instance| !frustum.sphere_outside(cam.transform.to_local_point(instance.to_world_point(center)), radius))
+        .collect()
+}
+
+/// A loaded mesh with everything needed to shade it
+pub struct Model {
+    pub triangles: Vec<Triangle3D>,
+    pub materials: Vec<Material>,
+    // Texture maps for each material, indexed like materials
+    pub textures: Vec<MaterialTextures>,
+    // Object-space sphere around every vertex, recomputed by update_bounds
+    bounds: (Point3D, f32),
+}
+
+impl Model {
+    pub fn new(triangles: Vec<Triangle3D>, materials: Vec<Material>, textures: Vec<MaterialTextures>) -> Self {
+        let mut model = Self { triangles, materials, textures, bounds: (Point3D { x: 0.0, y: 0.0, z: 0.0 }, 0.0) };
+        model.update_bounds();
+        model
+    }
+
+    /// Triangulate a parsed OBJ and decode its materials' textures in parallel. Materials without a map_Kd use `default_texture`,
+    /// textures that fail to load are replaced by the placeholder (diffuse) or left out (emissive) with a warning
+    pub fn from_mesh(mesh: &ObjMesh, default_texture: &str, flip_v: bool, uv_gen: UvGenMode) -> Self {
+        // Files with texcoords go through the deduplicated vertex buffer, the same form an indexed pipeline would consume.
+        // Generated texcoords are chosen per face, so those meshes are triangulated corner by corner
+        let triangles = if mesh.texcoords.is_empty() && uv_gen != UvGenMode::None {
+            fan_triangulate_faces(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, flip_v, uv_gen)
+        } else {
+            fan_triangulate_indexed(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, flip_v).to_triangles()
+        };
+        let texture_paths: Vec<String> = mesh.materials.iter()
+            .map(|material| material.diffuse_texture.clone().unwrap_or_else(|| default_texture.to_string()))
+            .collect();
+        let emissive_paths: Vec<&String> = mesh.materials.iter().filter_map(|material| material.emissive_texture.as_ref()).collect();
+        let mut emissive_textures = HdrTexture::load_parallel(&emissive_paths).into_iter().zip(&emissive_paths);
+        let textures = Texture::load_parallel(&texture_paths)
+            .into_iter()
+            .zip(&texture_paths)
+            .zip(&mesh.materials)
+            .map(|((result, path), material)| {
+                let diffuse = result.unwrap_or_else(|e| {
+                    eprintln!("Warning: failed to load texture {}: {}, using placeholder", path, e);
+                    Texture::placeholder()
+                });
+                let emissive = material.emissive_texture.as_ref().and_then(|_| {
+                    let (result, path) = emissive_textures.next()?;
+                    result.map_err(|e| eprintln!("Warning: failed to load emissive texture {}: {}", path, e)).ok()
+                });
+                MaterialTextures { diffuse: Arc::new(diffuse), emissive: emissive.map(Arc::new) }
+            })
+            .collect();
+        let mut model = Self::new(triangles, mesh.materials.clone(), textures);
+        model.sort_by_material();
+        model
+    }
+
+    /// Load a model by extension, glTF files bring their own textures and OBJ meshes are also returned for morphing.
+    /// With `split_area` triangles larger than it are subdivided, pieces stay in place so the material grouping is kept.
+    /// Runs at startup and again for every model dropped on the window, the result replaces the old model whole
+    pub fn load(path: &str, default_texture: &str, load_options: &ObjLoadOptions, flip_v: bool, split_area: Option<f32>) -> Result<(Self, Option<ObjMesh>), RusterizerError> {
+        let (mut model, mesh) = if gltf::is_gltf_path(path) {
+            (gltf::load_gltf(path)?, None)
+        } else {
+            let mesh = obj::load_obj(path, load_options)?;
+            // Meshes without texcoords get box-mapped ones so the texture still shows
+            (Self::from_mesh(&mesh, default_texture, flip_v, UvGenMode::Triplanar), Some(mesh))
+        };
+        if let Some(max_area) = split_area {
+            model.triangles = split_large_triangles(&model.triangles, max_area);
+            model.update_bounds();
+        }
+        Ok((model, mesh))
+    }
+
+    /// Use one diffuse texture for every material, emissive maps are kept
+    pub fn replace_diffuse(&mut self, texture: Texture) {
+        let texture = Arc::new(texture);
+        for textures in &mut self.textures {
+            textures.diffuse = Arc::clone(&texture);
+        }
+    }
+
+    /// Group triangles by material so the rasterizer sees long runs of one material, the sort is stable so draw order within a material is kept
+    pub fn sort_by_material(&mut self) {
+        self.triangles.sort_by_key(|tri| tri.material);
+    }
+
+    /// Object-space sphere around every vertex, as of the last update_bounds
+    pub fn bounding_sphere(&self) -> (Point3D, f32) {
+        self.bounds
+    }
+
+    /// Refit the bounding sphere, needed whenever the triangles are rewritten (morphing, splitting)
+    pub fn update_bounds(&mut self) {
+        let points: Vec<Point3D> = self.triangles.iter().flat_map(|tri| [tri.a, tri.b, tri.c]).collect();
+        self.bounds = bounding_sphere(&points);
+    }
+
+    /// Advance every material's UV scroll to `elapsed` seconds
+    pub fn update_uv_scroll(&mut self, elapsed: f32) {
+        for material in &mut self.materials {
+            material.update_uv_scroll(elapsed);
+        }
+    }
+}
+
+/// Comparison between a fragment's view depth and the stored depth, smaller depth is nearer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DepthFunc {
+    Less,
+    #[default]
+    LessEqual,
+    Greater,
+    Always,
+    Never,
+}
+
+impl DepthFunc {
+    #[inline(always)]
+    pub fn passes(self, depth: f32, stored: f32) -> bool {
+        match self {
+            DepthFunc::Less => depth < stored,
+            DepthFunc::LessEqual => depth <= stored,
+            DepthFunc::Greater => depth > stored,
+            DepthFunc::Always => true,
+            DepthFunc::Never => false,
+        }
+    }
+
+    /// passes() certainly fails, decided from the interpolated 1/z without the reciprocal. z < stored is 1/z * stored > 1 for
+    /// positive depths, the margin covers the rounding of both paths so this never rejects a fragment passes() would keep
+    #[inline(always)]
+    pub fn rejects_inv_depth(self, inv_depth: f32, stored: f32) -> bool {
+        const MARGIN: f32 = 4.0 * f32::EPSILON;
+        let ratio = inv_depth * stored;
+        match self {
+            DepthFunc::Less | DepthFunc::LessEqual => ratio < 1.0 - MARGIN,
+            DepthFunc::Greater => ratio > 1.0 + MARGIN,
+            DepthFunc::Always => false,
+            DepthFunc::Never => true,
+        }
+    }
+}
+
+/// Depth nudge for coplanar geometry like decals and for surfaces closer together than the depth precision, negative values pull fragments toward the camera.
+/// `bias` is a constant in view depth units, `factor` scales the triangle's view depth slope per pixel, `units` scales the f32 precision at the fragment's depth
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PolygonOffset {
+    pub bias: f32,
+    pub factor: f32,
+    pub units: f32,
+}
+
+impl PolygonOffset {
+    #[inline(always)]
+    pub fn apply(&self, depth: f32, slope: f32) -> f32 {
+        depth + self.bias + self.factor * slope + self.units * depth * f32::EPSILON
+    }
+}
+
+/// How the per-fragment reciprocal for perspective-correct depth is computed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    // Full division
+    #[default]
+    Exact,
+    // Bit-trick estimate refined by Newton steps, relative error about 5% with none, 0.3% with one, 0.001% with two
+    Fast { newton_steps: u8 },
+}
+
+impl Precision {
+    #[inline(always)]
+    pub fn recip(self, x: f32) -> f32 {
+        match self {
+            Precision::Exact => 1.0 / x,
+            Precision::Fast { newton_steps } => {
+                let mut estimate = f32::from_bits(0x7EF3_11C7u32.wrapping_sub(x.to_bits()));
+                for _ in 0..newton_steps {
+                    estimate = estimate * (2.0 - x * estimate);
+                }
+                estimate
+            }
+        }
+    }
+}
+
+/// Interpolated attributes of one fragment, handed to a custom fragment shader
+#[derive(Debug, Clone, Copy)]
+pub struct FragmentInput {
+    pub x: u32,
+    pub y: u32,
+    pub uv: Point2D,
+    // Interpolated, not renormalized
+    pub normal: Point3D,
+    // View depth
+    pub depth: f32,
+    pub world_pos: Point3D,
+    pub vertex_alpha: f32,
+    pub material: usize,
+}
+
+/// Replaces texture sampling and lighting, returns the fragment color, alpha still goes through the alpha test
+pub type FragmentShader = Box<dyn Fn(FragmentInput) -> (u8, u8, u8, u8) + Send + Sync>;
+
+/// Restricts a draw to part of a mesh for isolating problem geometry, an unset criterion accepts everything
+#[derive(Debug, Default, Clone)]
+pub struct TriangleFilter {
+    // Source faces (Triangle3D::face), so ranges keep meaning file order after Model::sort_by_material
+    pub range: Option<Range<u32>>,
+    pub material: Option<usize>,
+}
+
+impl TriangleFilter {
+    #[inline(always)]
+    pub fn accepts(&self, tri: &Triangle3D) -> bool {
+        self.range.as_ref().is_none_or(|range| range.contains(&tri.face))
+            && self.material.is_none_or(|material| material == tri.material)
+    }
+}
+
+/// Time spent in each pipeline stage of a draw
+#[derive(Debug, Default, Clone)]
+pub struct DrawStats {
+    pub transform_time: Duration,
+    pub triangle_time: Duration,
+    // Rasterization time of each tile, in the order of Rasterizer::tiles
+    pub tile_times: Vec<Duration>,
+    // Screen area in pixels of the triangles facing toward and away from the camera, each triangle capped at the screen's area.
+    // Overlapping triangles all count, so these can add up to more than the screen
+    pub front_facing_area: f32,
+    pub back_facing_area: f32,
+}
+
+// Back faces covering more than this many screens' worth, at least twice the front faces, suggest the camera is inside a mesh
+const INSIDE_BACK_FACING_SCREENS: f32 = 0.5;
+
+impl DrawStats {
+    /// Fold the times and areas of another pass over the same tiles into these
+    pub fn add_pass(&mut self, pass: DrawStats) {
+        self.transform_time += pass.transform_time;
+        self.triangle_time += pass.triangle_time;
+        if self.tile_times.is_empty() {
+            self.tile_times = vec![Duration::ZERO; pass.tile_times.len()];
+        }
+        for (total, time) in self.tile_times.iter_mut().zip(pass.tile_times) {
+            *total += time;
+        }
+        self.front_facing_area += pass.front_facing_area;
+        self.back_facing_area += pass.back_facing_area;
+    }
+
+    /// Heuristic for the camera being inside geometry. Seen from outside, a closed mesh projects as much back-facing area
+    /// (hidden behind its front) as front-facing, seen from inside nearly everything faces away
+    pub fn camera_inside_geometry(&self, screen_area: f32) -> bool {
+        self.back_facing_area > INSIDE_BACK_FACING_SCREENS * screen_area && self.back_facing_area > 2.0 * self.front_facing_area
+    }
+}
+
+// Split transformed triangles into the opaque pass and the blended pass, each keeping its submission order
+fn split_blended(triangles: Vec<Triangle3D>, model: &Model) -> (Vec<Triangle3D>, Vec<Triangle3D>) {
+    triangles.into_iter().partition(|tri| model.materials[tri.material].blend == BlendMode::Opaque)
+}
+
+// Farthest first by mean inverted depth so nearer blended surfaces composite over farther ones. Stable, equal depths keep
+// submission order and the result stays deterministic. Per triangle, so intersecting blended triangles can still sort wrong
+fn sort_back_to_front(triangles: &mut [(usize, Triangle3D)]) {
+    triangles.sort_by(|(_, p), (_, q)| (p.a.z + p.b.z + p.c.z).total_cmp(&(q.a.z + q.b.z + q.c.z)));
+}
+
+// One draw call's transformed blended triangles waiting for flush_blended, with the materials they use renumbered from 0
+struct BlendedBatch {
+    triangles: Vec<Triangle3D>,
+    materials: Vec<Material>,
+    textures: Vec<MaterialTextures>,
+    light: Point3D,
+    fog: Option<Fog>,
+}
+
+// Front- and back-facing screen area of transformed triangles, for DrawStats
+fn facing_areas(triangles: &[Triangle3D], screen_area: f32) -> (f32, f32) {
+    triangles.iter().fold((0.0, 0.0), |(front, back), tri| {
+        let area = tri.setup.area.abs().min(screen_area);
+        if tri.setup.area > 0.0 { (front + area, back) } else { (front, back + area) }
+    })
+}
+
+/// Independently sized color and depth buffers with their own tiling, for secondary passes (shadow maps, reflections) that don't need the main resolution
+pub struct RenderTarget {
+    pub screen: ScreenSpace,
+    pub tiles: Vec<Rect>,
+    pub resolution: Point2D,
+}
+
+impl RenderTarget {
+    pub fn new(width: u32, height: u32, tile_count: usize) -> Self {
+        Self {
+            screen: ScreenSpace::new(width, height),
+            tiles: subdivide_count(width, height, tile_count),
+            resolution: Point2D { x: width as f32, y: height as f32 },
+        }
+    }
+}
+
+/// Remembers the inputs of the last rendered frame so an unchanged frame can be skipped, leaving the previous one in the screen.
+/// The key has to cover every input and setting that affects the image, anything left out won't trigger a redraw
+pub struct RenderCache<K> {
+    last: Option<K>,
+    // Frames actually rendered and frames skipped
+    pub renders: u64,
+    pub skips: u64,
+}
+
+impl<K: PartialEq> Default for RenderCache<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PartialEq> RenderCache<K> {
+    pub fn new() -> Self {
+        Self { last: None, renders: 0, skips: 0 }
+    }
+
+    /// True if `key` differs from the last rendered frame's, in which case it's recorded as rendered
+    pub fn needs_render(&mut self, key: K) -> bool {
+        if self.last.as_ref() == Some(&key) {
+            self.skips += 1;
+            return false;
+        }
+        self.last = Some(key);
+        self.renders += 1;
+        true
+    }
+
+    /// Force the next frame to render, for changes the key doesn't capture (reloaded assets, resized targets)
+    pub fn invalidate(&mut self) {
+        self.last = None;
+    }
+}
+
+pub struct Rasterizer {
+    pub screen: ScreenSpace,
+    // Screen regions rasterized independently by the thread pool
+    pub tiles: Vec<Rect>,
+    pub resolution: Point2D,
+    // Write depth as grayscale instead of shading
+    pub show_depth: bool,
+    // View depths mapped to white and black by show_depth
+    pub show_depth_range: (f32, f32),
+    // Triangles entirely nearer than this are culled and lines are clipped to it, see DEFAULT_NEAR_PLANE for its effect on precision
+    pub near_plane: f32,
+    // Custom per-fragment shading, None uses the built-in texture and half-Lambert path
+    pub fragment_shader: Option<FragmentShader>,
+    pub depth_func: DepthFunc,
+    // Linear RGB added to every fragment's diffuse lighting so unlit sides keep a minimum brightness
+    pub ambient: Point3D,
+    // Speed/accuracy of the interpolation reciprocal, approximations show up as texture swimming
+    pub precision: Precision,
+    // Applied to every fragment of a draw before the depth test and write
+    pub polygon_offset: PolygonOffset,
+    // Applied before the transform stage, rejected triangles are never projected
+    pub triangle_filter: TriangleFilter,
+    // Turned off for decals and overlays that should test against depth without occluding what's drawn after them
+    pub depth_write: bool,
+    // Fragments past it are discarded, None draws at any depth
+    pub far_plane: Option<FarPlane>,
+    // Cover every pixel a triangle touches at all instead of only those whose center it contains, so thin triangles never leave gaps
+    pub conservative: bool,
+    pub texture_filter: TextureFilter,
+    // Fragments failing the test against screen.mask are discarded before the depth test
+    pub mask_mode: MaskMode,
+    // Only pixels inside this rectangle are rasterized and triangles missing it are dropped in the transform stage, the rest of the
+    // screen keeps the previous frame. Pair with ScreenSpace::clear_rect to redraw just a dirty region
+    pub scissor: Option<Rect>,
+    // Fill each triangle flat with a color hashed from its id instead of texturing and shading, to see topology and density
+    pub triangle_colors: bool,
+    // Limit on transformed triangles held at once, draw_instanced streams the mesh in chunks of this many when set
+    pub chunk_size: Option<usize>,
+    // Sample position within each pixel, TopLeft reproduces renderers that don't offset to the center
+    pub pixel_center: PixelCenter,
+    // Blend each pixel by the share of this pattern's samples its triangle covers, softening edges against what's behind them.
+    // Only pixels whose own sample is covered are drawn, so edges shared inside a mesh can show faint seams
+    pub edge_aa: Option<SamplePattern>,
+    // Secondary render targets, drawn into with with_target
+    pub targets: Vec<RenderTarget>,
+    // Blended triangles of earlier draws, held until flush_blended so they sort back to front across draw calls
+    blended_queue: Mutex<Vec<BlendedBatch>>,
+}
+
+impl Rasterizer {
+    pub fn new(width: u32, height: u32, tiles: Vec<Rect>) -> Self {
+        Self {
+            screen: ScreenSpace::new(width, height),
+            tiles,
+            resolution: Point2D { x: width as f32, y: height as f32 },
+            show_depth: false,
+            show_depth_range: (DEFAULT_NEAR_PLANE, DEFAULT_SHOW_DEPTH_FAR),
+            near_plane: DEFAULT_NEAR_PLANE,
+            fragment_shader: None,
+            depth_func: DepthFunc::default(),
+            ambient: Point3D { x: 0.0, y: 0.0, z: 0.0 },
+            precision: Precision::default(),
+            polygon_offset: PolygonOffset::default(),
+            triangle_filter: TriangleFilter::default(),
+            depth_write: true,
+            far_plane: None,
+            conservative: false,
+            texture_filter: TextureFilter::default(),
+            mask_mode: MaskMode::default(),
+            scissor: None,
+            triangle_colors: false,
+            chunk_size: None,
+            pixel_center: PixelCenter::default(),
+            edge_aa: None,
+            targets: Vec::new(),
+            blended_queue: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Add a secondary render target and return its index for with_target
+    pub fn add_target(&mut self, width: u32, height: u32, tile_count: usize) -> usize {
+        self.targets.push(RenderTarget::new(width, height, tile_count));
+        self.targets.len() - 1
+    }
+
+    /// Run draws against target `index` instead of the main screen, every other setting (depth func, shader, ...) is shared.
+    /// Blended triangles are flushed on the way in and out, so each lands on the screen it was drawn for
+    pub fn with_target<R>(&mut self, index: usize, draw: impl FnOnce(&Rasterizer) -> R) -> R {
+        self.flush_blended();
+        self.swap_target(index);
+        let result = draw(self);
+        self.flush_blended();
+        self.swap_target(index);
+        result
+    }
+
+    // Exchange the main screen and tiling with a secondary target's, swapping twice restores both
+    fn swap_target(&mut self, index: usize) {
+        let target = &mut self.targets[index];
+        std::mem::swap(&mut self.screen, &mut target.screen);
+        std::mem::swap(&mut self.tiles, &mut target.tiles);
+        std::mem::swap(&mut self.resolution, &mut target.resolution);
+    }
+
+    /// Render one mesh at several object transforms without duplicating its triangles.
+    /// Output is deterministic: the same inputs give a byte-identical framebuffer regardless of thread count or scheduling,
+    /// which golden-image comparisons rely on. transform_instances and rasterize each document the half of this they uphold.
+    /// Triangles with a blended material are only queued, flush_blended draws them once every opaque draw of the frame is done
+    pub fn draw_instanced(&self, model: &Model, instances: &[Transform], cam: &Camera, light: Point3D, fog: Option<&Fog>) -> DrawStats {
+        if let Some(chunk_size) = self.chunk_size {
+            return self.draw_instanced_chunked(model, instances, cam, light, fog, chunk_size);
+        }
+        let transform_start = Instant::now();
+        let screen_triangles = self.transform_instances(model, instances, cam);
+        let transform_time = transform_start.elapsed();
+
+        let (front_facing_area, back_facing_area) = facing_areas(&screen_triangles, self.resolution.x * self.resolution.y);
+
+        let triangle_start = Instant::now();
+        let (opaque, blended) = split_blended(screen_triangles, model);
+        let tile_times = self.rasterize(&opaque, model, light, fog);
+        self.defer_blended(blended, model, light, fog);
+        let triangle_time = triangle_start.elapsed();
+
+        DrawStats { transform_time, triangle_time, tile_times, front_facing_area, back_facing_area }
+    }
+
+    /// Immediate-mode submission of an arbitrary batch, e.g. procedural or CSG geometry, composited with earlier draws through the depth buffer.
+    /// Every triangle uses `material` regardless of its material index and is shaded untextured (white), the batch is copied per call
+    pub fn draw_triangles(&self, triangles: &[Triangle3D], transform: &Transform, material: &Material, cam: &Camera, light: Point3D, fog: Option<&Fog>) -> DrawStats {
+        let batch = Model::new(
+            triangles.iter().map(|tri| Triangle3D { material: 0, ..*tri }).collect(),
+            vec![material.clone()],
+            vec![Texture::solid(255, 255, 255, 255).into()],
+        );
+        self.draw_instanced(&batch, std::slice::from_ref(transform), cam, light, fog)
+    }
+
+    /// Project every (instance, triangle) pair to screen space, instance-major.
+    /// The order is part of the contract, depth ties (coplanar faces, DepthFunc::LessEqual) go to whichever triangle comes
+    /// later, so this must stay an order-preserving collect rather than anything that gathers results as threads finish
+    pub fn transform_instances(&self, model: &Model, instances: &[Transform], cam: &Camera) -> Vec<Triangle3D> {
+        let frustum = self.view_frustum(cam);
+        let visible = visible_instances(&frustum, model, instances, cam);
+        self.transform_pairs(model, instances, cam, &frustum, &visible, 0..instances.len() * model.triangles.len())
+    }
+
+    // The camera's view volume for this rasterizer's resolution and clip planes
+    fn view_frustum(&self, cam: &Camera) -> Frustum {
+        let far = self.far_plane.map_or(f32::INFINITY, |far| far.distance);
+        Frustum::new(self.resolution, cam.scaled_inv_world_height(self.resolution.y), self.near_plane, far)
+    }
+
+    // transform_instances for a range of the flattened instance-major (instance, triangle) pairs
+    fn transform_pairs(&self, model: &Model, instances: &[Transform], cam: &Camera, frustum: &Frustum, visible: &[bool], pairs: Range<usize>) -> Vec<Triangle3D> {
+        let scaled_inv_world_height = cam.scaled_inv_world_height(self.resolution.y);
+        let triangle_count = model.triangles.len();
+
+        // Flatten the pairs into one range so a single instance still spreads over every thread
+        pairs
+            .into_par_iter()
+            .filter(|i| visible[i / triangle_count])
+            .filter(|i| self.triangle_filter.accepts(&model.triangles[i % triangle_count]))
+            .filter_map(|i| {
+                let instance = &instances[i / triangle_count];
+                let tri = &model.triangles[i % triangle_count];
+
+                let (wa, wb, wc) = (instance.to_world_point(tri.a), instance.to_world_point(tri.b), instance.to_world_point(tri.c));
+                let (va, vb, vc) = (cam.transform.to_local_point(wa), cam.transform.to_local_point(wb), cam.transform.to_local_point(wc));
+                // Triangles entirely outside the view would still pass the area test when behind the camera, the projection mirrors them
+                if frustum.triangle_distances(va, vb, vc).outside() {
+                    return None;
+                }
+                let sa = view_to_screen(va, self.resolution, scaled_inv_world_height);
+                let sb = view_to_screen(vb, self.resolution, scaled_inv_world_height);
+                let sc = view_to_screen(vc, self.resolution, scaled_inv_world_height);
+
+                let min_x = sa.x.min(sb.x).min(sc.x);
+                let min_y = sa.y.min(sb.y).min(sc.y);
+                let max_x = sa.x.max(sb.x).max(sc.x);
+                let max_y = sa.y.max(sb.y).max(sc.y);
+
+                let block_start_x = (min_x.floor() as u32).clamp(0, self.screen.width - 1);
+                let block_start_y = (min_y.floor() as u32).clamp(0, self.screen.height - 1);
+                let block_end_x = (max_x.ceil() as u32).clamp(0, self.screen.width - 1);
+                let block_end_y = (max_y.ceil() as u32).clamp(0, self.screen.height - 1);
+                let misses_scissor = self.scissor.is_some_and(|scissor| {
+                    block_end_x < scissor.min_x || block_start_x >= scissor.max_x || block_end_y < scissor.min_y || block_start_y >= scissor.max_y
+                });
+                if misses_scissor {
+                    return None;
+                }
+
+                let mut screen_tri = Triangle3D {
+                    a: sa,
+                    b: sb,
+                    c: sc,
+                    wa,
+                    wb,
+                    wc,
+                    bb_start_x: block_start_x,
+                    bb_start_y: block_start_y,
+                    bb_end_x: block_end_x,
+                    bb_end_y: block_end_y,
+                    id: i as u32,
+                    ..*tri
+                };
+
+                // Back faces are culled in rasterize, so flip two-sided ones to face the camera and light their back side
+                let (pa, pb, pc) = (Point2D { x: sa.x, y: sa.y }, Point2D { x: sb.x, y: sb.y }, Point2D { x: sc.x, y: sc.y });
+                screen_tri.setup = EdgeSetup::new(pa, pb, pc);
+                if screen_tri.setup.area < 0.0 && model.materials[tri.material].two_sided {
+                    screen_tri.flip_winding();
+                    screen_tri.na = screen_tri.na * -1.0;
+                    screen_tri.nb = screen_tri.nb * -1.0;
+                    screen_tri.nc = screen_tri.nc * -1.0;
+                    screen_tri.setup = EdgeSetup::new(pa, pc, pb);
+                }
+                Some(screen_tri)
+            })
+            .collect()
+    }
+
+    /// World-space point drawn at a screen position, reconstructed from the depth buffer along the pixel's ray.
+    /// None where nothing was drawn or outside the screen. Reads the last rendered frame, call between draws
+    pub fn world_position_at(&self, x: f32, y: f32, cam: &Camera) -> Option<Point3D> {
+        if !(x >= 0.0 && y >= 0.0) {
+            return None;
+        }
+        let (px, py) = (x as u32, y as u32);
+        let depth = self.screen.get_depth(px, py);
+        if !depth.is_finite() {
+            return None;
+        }
+        // Depth was stored for the pixel's sample position, not the exact cursor position
+        let offset = self.pixel_center.offset();
+        let view = reconstruct_view_pos(px as f32 + offset, py as f32 + offset, depth, self.resolution, cam.scaled_inv_world_height(self.resolution.y));
+        Some(cam.transform.to_world_point(view))
+    }
+
+    /// draw_instanced without ever holding every transformed triangle, the (instance, triangle) pairs are transformed and
+    /// rasterized `chunk_size` at a time. Chunks go in order, so the framebuffer is byte-identical to draw_instanced's.
+    /// Blended triangles are queued for flush_blended like draw_instanced's
+    pub fn draw_instanced_chunked(&self, model: &Model, instances: &[Transform], cam: &Camera, light: Point3D, fog: Option<&Fog>, chunk_size: usize) -> DrawStats {
+        let frustum = self.view_frustum(cam);
+        let visible = visible_instances(&frustum, model, instances, cam);
+        let pair_count = instances.len() * model.triangles.len();
+        let mut stats = DrawStats { tile_times: vec![Duration::ZERO; self.tiles.len()], ..DrawStats::default() };
+        for start in (0..pair_count).step_by(chunk_size.max(1)) {
+            let transform_start = Instant::now();
+            let screen_triangles = self.transform_pairs(model, instances, cam, &frustum, &visible, start..(start + chunk_size.max(1)).min(pair_count));
+            stats.transform_time += transform_start.elapsed();
+            let (front, back) = facing_areas(&screen_triangles, self.resolution.x * self.resolution.y);
+            stats.front_facing_area += front;
+            stats.back_facing_area += back;
+
+            let triangle_start = Instant::now();
+            let (opaque, blended) = split_blended(screen_triangles, model);
+            let tile_times = self.rasterize(&opaque, model, light, fog);
+            self.defer_blended(blended, model, light, fog);
+            stats.triangle_time += triangle_start.elapsed();
+            for (total, time) in stats.tile_times.iter_mut().zip(tile_times) {
+                *total += time;
+            }
+        }
+        stats
+    }
+
+    // Queue a draw's blended triangles with copies of just the materials they use
+    fn defer_blended(&self, mut triangles: Vec<Triangle3D>, model: &Model, light: Point3D, fog: Option<&Fog>) {
+        if triangles.is_empty() {
+            return;
+        }
+        let mut used: Vec<usize> = triangles.iter().map(|tri| tri.material).collect();
+        used.sort_unstable();
+        used.dedup();
+        for tri in &mut triangles {
+            tri.material = used.binary_search(&tri.material).unwrap();
+        }
+        let batch = BlendedBatch {
+            triangles,
+            materials: used.iter().map(|&i| model.materials[i].clone()).collect(),
+            textures: used.iter().map(|&i| model.textures[i].clone()).collect(),
+            light,
+            fog: fog.copied(),
+        };
+        self.blended_queue.lock().unwrap().push(batch);
+    }
+
+    /// Draw every queued blended triangle over the frame, sorted back to front across all the draw calls that queued them.
+    /// Call once the frame's opaque geometry is drawn, triangles are depth tested against it without writing depth
+    pub fn flush_blended(&self) -> DrawStats {
+        let batches = std::mem::take(&mut *self.blended_queue.lock().unwrap());
+        let mut stats = DrawStats { tile_times: vec![Duration::ZERO; self.tiles.len()], ..DrawStats::default() };
+        if batches.is_empty() {
+            return stats;
+        }
+        let triangle_start = Instant::now();
+        // One material table for every batch, each batch's materials offset past the ones before it
+        let mut combined = Model::new(Vec::new(), Vec::new(), Vec::new());
+        let mut queued: Vec<(usize, Triangle3D)> = Vec::new();
+        for (index, batch) in batches.iter().enumerate() {
+            let offset = combined.materials.len();
+            queued.extend(batch.triangles.iter().map(|tri| (index, Triangle3D { material: tri.material + offset, ..*tri })));
+            combined.materials.extend(batch.materials.iter().cloned());
+            combined.textures.extend(batch.textures.iter().cloned());
+        }
+        sort_back_to_front(&mut queued);
+        // Consecutive triangles from draws with the same light and fog share a pass, usually the whole queue
+        let same_lighting = |p: usize, q: usize| batches[p].light == batches[q].light && batches[p].fog == batches[q].fog;
+        for run in queued.chunk_by(|(p, _), (q, _)| same_lighting(*p, *q)) {
+            let batch = &batches[run[0].0];
+            let triangles: Vec<Triangle3D> = run.iter().map(|&(_, tri)| tri).collect();
+            for (total, time) in stats.tile_times.iter_mut().zip(self.rasterize(&triangles, &combined, batch.light, batch.fog.as_ref())) {
+                *total += time;
+            }
+        }
+        stats.triangle_time = triangle_start.elapsed();
+        stats
+    }
+
+    /// Depth tested world-space lines drawn over the current frame, for grids and debug overlays. `bias` is the fraction of
+    /// depth lines are pulled toward the camera by, nonzero for lines drawn on the rendered surfaces such as a wireframe
+    pub fn draw_lines(&self, lines: &[(Point3D, Point3D)], cam: &Camera, color: (u8, u8, u8), bias: f32) {
+        let scaled_inv_world_height = cam.scaled_inv_world_height(self.resolution.y);
+        let near = self.near_plane;
+        for &(start, end) in lines {
+            let mut a = cam.transform.to_local_point(start);
+            let mut b = cam.transform.to_local_point(end);
+            if a.z < near && b.z < near {
+                continue;
+            }
+            // Pull whichever end is behind the camera onto the near plane
+            if a.z < near {
+                a = point3d::lerp(a, b, (near - a.z) / (b.z - a.z));
+            } else if b.z < near {
+                b = point3d::lerp(b, a, (near - b.z) / (a.z - b.z));
+            }
+            draw_line_depth(
+                &self.screen,
+                view_to_screen(a, self.resolution, scaled_inv_world_height),
+                view_to_screen(b, self.resolution, scaled_inv_world_height),
+                color,
+                bias,
+            );
+        }
+    }
+
+    /// Whether any enabled feature reads the interpolated world position
+    fn needs_world_pos(&self) -> bool {
+        self.fragment_shader.is_some()
+    }
+
+    /// Rasterize screen-space triangles into the screen, each tile on its own thread, returns how long each tile took.
+    /// Every pixel belongs to exactly one tile and each tile walks the triangles in slice order, so the result doesn't depend on
+    /// how tiles are scheduled. Splitting a tile's triangles across threads would break this for overlapping fragments
+    pub fn rasterize(&self, triangles: &[Triangle3D], model: &Model, light: Point3D, fog: Option<&Fog>) -> Vec<Duration> {
+        let screen = &self.screen;
+        let needs_world_pos = self.needs_world_pos();
+        let far_distance = self.far_plane.map_or(f32::INFINITY, |far| far.distance);
+        let far_fog = self.far_plane.map(|far| far.fade_fog(fog));
+        let fog = far_fog.as_ref().or(fog);
+        // The stored depth is only comparable to the true depth without an offset, and the fast reciprocal's error is larger than the margin
+        let early_depth_test = self.polygon_offset == PolygonOffset::default() && self.precision == Precision::Exact;
+        // Tiles never overlap, so threads write to disjoint pixels of the shared screen
+        self.tiles.par_iter().map(|tile| {
+            let tile_start = Instant::now();
+            let Some(rect) = self.scissor.map_or(Some(*tile), |scissor| tile.intersect(&scissor)) else {
+                return tile_start.elapsed();
+            };
+            let rect = &rect;
+            // Triangles arrive grouped by material, so the material and texture lookups happen once per run
+            for run in triangles.chunk_by(|p, q| p.material == q.material) {
+                let material = &model.materials[run[0].material];
+                let textures = &model.textures[run[0].material];
+                // Blended fragments composite over the pixel and leave depth alone so farther blended surfaces still draw
+                let blended = material.blend == BlendMode::Blend;
+                let write_depth = self.depth_write && !blended;
+                let opacity = if blended { material.dissolve } else { 1.0 };
+                for tri in run {
+                    let a = Point2D { x: tri.a.x, y: tri.a.y };
+                    let b = Point2D { x: tri.b.x, y: tri.b.y };
+                    let c = Point2D { x: tri.c.x, y: tri.c.y };
+                    let EdgeSetup { ab: edge_ab, bc: edge_bc, ca: edge_ca, area, inv_area } = tri.setup;
+                    // Back faces have negative area and are culled, skip them and any triangle whose edges exclude this whole tile
+                    // The tile reject tests exact edges, conservative coverage can reach half a pixel past them
+                    if area <= 0.0 || (!self.conservative && triangle_misses_rect(a, b, c, rect, self.pixel_center)) {
+                        continue;
+                    }
+                    // Edge functions are evaluated once per row then stepped by a constant per pixel
+                    let (bias_ab, bias_bc, bias_ca) = if self.conservative {
+                        (edge_ab.conservative_bias(), edge_bc.conservative_bias(), edge_ca.conservative_bias())
+                    } else {
+                        (0.0, 0.0, 0.0)
+                    };
+                    // Pixels at least this far inside every edge have all their samples covered and skip the coverage count
+                    let aa_reach = (edge_ab.conservative_bias(), edge_bc.conservative_bias(), edge_ca.conservative_bias());
+                    let depths: Point3D = Point3D { x: tri.a.z, y: tri.b.z, z: tri.c.z };
+                    // Inverted depth is planar in screen space, these are its per-pixel gradients
+                    let inv_depth_dx = dot3(depths, Point3D { x: edge_bc.step_x, y: edge_ca.step_x, z: edge_ab.step_x }) * inv_area;
+                    let inv_depth_dy = dot3(depths, Point3D { x: edge_bc.step_y, y: edge_ca.step_y, z: edge_ab.step_y }) * inv_area;
+                    let inv_depth_slope = inv_depth_dx.abs().max(inv_depth_dy.abs());
+                    // Use pre-computed bounding boxes + bounds of current thread rectangle
+                    let x_start = tri.bb_start_x.max(rect.min_x);
+                    let sample_offset = self.pixel_center.offset();
+                    for y in tri.bb_start_y.max(rect.min_y)..tri.bb_end_y.min(rect.max_y) {
+                        let row_start = Point2D { x: x_start as f32 + sample_offset, y: y as f32 + sample_offset };
+                        let mut row_ab = edge_ab.eval(row_start);
+                        let mut row_bc = edge_bc.eval(row_start);
+                        let mut row_ca = edge_ca.eval(row_start);
+                        for x in x_start..tri.bb_end_x.min(rect.max_x) {
+                            let (area_ab, area_bc, area_ca) = (row_ab, row_bc, row_ca);
+                            row_ab += edge_ab.step_x;
+                            row_bc += edge_bc.step_x;
+                            row_ca += edge_ca.step_x;
+
+                            if area_ab >= -bias_ab && area_bc >= -bias_bc && area_ca >= -bias_ca {
+                                let mut weights: Point3D = Point3D { x: area_bc * inv_area, y: area_ca * inv_area, z: area_ab * inv_area };
+                                if self.conservative {
+                                    // Centers outside the triangle would extrapolate attributes (and depth) past the vertices, snap them to its edge
+                                    weights = Point3D { x: weights.x.max(0.0), y: weights.y.max(0.0), z: weights.z.max(0.0) };
+                                    weights = weights / (weights.x + weights.y + weights.z);
+                                }
+                                if !self.mask_mode.passes(screen.get_mask(x, y)) {
+                                    continue;
+                                }
+                                let coverage = match &self.edge_aa {
+                                    Some(pattern) if area_ab < aa_reach.0 || area_bc < aa_reach.1 || area_ca < aa_reach.2 => {
+                                        tri.setup.coverage(Point2D { x: x as f32 + sample_offset, y: y as f32 + sample_offset }, pattern)
+                                    }
+                                    _ => 1.0,
+                                };
+                                if coverage <= 0.0 {
+                                    continue;
+                                }
+                                let inv_depth = dot3(depths, weights);
+                                // Occluded fragments are thrown out before paying for the reciprocal
+                                if early_depth_test && self.depth_func.rejects_inv_depth(inv_depth, screen.get_depth(x, y)) {
+                                    continue;
+                                }
+                                let depth: f32 = self.precision.recip(inv_depth);
+                                if depth > far_distance {
+                                    continue;
+                                }
+                                // d(1/z) = -dz / z^2, so the view depth slope is the inverted slope scaled by depth^2.
+                                // The offset only affects what's tested and stored, interpolation keeps the true depth
+                                let stored_depth = self.polygon_offset.apply(depth, inv_depth_slope * depth * depth);
+
+                                if !self.depth_func.passes(stored_depth, screen.get_depth(x, y)) {
+                                    continue;
+                                }
+
+                                let texture_coord: Point2D = Point2D {
+                                    x: perspective_interp(tri.ta.x, tri.tb.x, tri.tc.x, depths, weights, depth),
+                                    y: perspective_interp(tri.ta.y, tri.tb.y, tri.tc.y, depths, weights, depth),
+                                } + material.uv_offset;
+
+                                let normal: Point3D = perspective_interp3(tri.na, tri.nb, tri.nc, depths, weights, depth);
+
+                                let vertex_alpha: f32 = perspective_interp(tri.alpha_a, tri.alpha_b, tri.alpha_c, depths, weights, depth);
+
+                                // Only paid for when something downstream reads it
+                                let world_pos: Point3D = if needs_world_pos {
+                                    perspective_interp3(tri.wa, tri.wb, tri.wc, depths, weights, depth)
+                                } else {
+                                    Point3D { x: 0.0, y: 0.0, z: 0.0 }
+                                };
+
+                                if let Some(shader) = &self.fragment_shader {
+                                    let (r, g, b, a) = shader(FragmentInput {
+                                        x,
+                                        y,
+                                        uv: texture_coord,
+                                        normal,
+                                        depth,
+                                        world_pos,
+                                        vertex_alpha,
+                                        material: tri.material,
+                                    });
+                                    if a < ALPHA_CUTOFF {
+                                        continue;
+                                    }
+                                    if write_depth {
+                                        screen.unsafe_set_depth(x, y, stored_depth);
+                                    }
+                                    if blended {
+                                        screen.unsafe_blend_pixel(x, y, r, g, b, a as f32 / 255.0 * coverage);
+                                    } else if coverage < 1.0 {
+                                        screen.unsafe_blend_pixel(x, y, r, g, b, coverage);
+                                    } else {
+                                        screen.unsafe_set_pixel(x, y, r, g, b, a);
+                                    }
+                                    continue;
+                                }
+
+                                // Vertex alpha scales the texel alpha, fully transparent fragments are discarded before touching depth
+                                let texels = textures.sample(texture_coord.x, texture_coord.y, material.wrap, self.texture_filter);
+                                let (tr, tg, tb, ta) = texels.diffuse;
+                                let alpha = (ta as f32 * vertex_alpha.clamp(0.0, 1.0) * opacity).round() as u8;
+                                if alpha < ALPHA_CUTOFF {
+                                    continue;
+                                }
+
+                                if write_depth {
+                                    screen.unsafe_set_depth(x, y, stored_depth);
+                                }
+
+                                if self.triangle_colors {
+                                    let (r, g, b) = id_color(tri.id);
+                                    screen.unsafe_set_pixel(x, y, r, g, b, 255);
+                                } else if self.show_depth {
+                                    let depth_gray: u8 = depth_to_u8(depth, self.show_depth_range.0, self.show_depth_range.1);
+                                    screen.unsafe_set_pixel(x, y, depth_gray, depth_gray, depth_gray, 255);
+                                } else {
+                                    let mut color = shade_pixel((tr, tg, tb, alpha), normal, light, self.ambient, depth, fog);
+                                    if let Some(emissive) = texels.emissive {
+                                        color = add_emissive(color, emissive);
+                                    }
+                                    let (r, g, b, a) = color;
+                                    if blended {
+                                        screen.unsafe_blend_pixel(x, y, r, g, b, a as f32 / 255.0 * coverage);
+                                    } else if coverage < 1.0 {
+                                        screen.unsafe_blend_pixel(x, y, r, g, b, coverage);
+                                    } else {
+                                        screen.unsafe_set_pixel(x, y, r, g, b, a);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            tile_start.elapsed()
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::PitchMode;
+    use crate::geometry::{subdivide_count, vertex_to_screen};
+    use crate::obj::Vertex;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+    const RED: (u8, u8, u8, u8) = (255, 0, 0, 255);
+    const GREEN: (u8, u8, u8, u8) = (0, 255, 0, 255);
+    const LIGHT: Point3D = Point3D { x: 0.0, y: 0.0, z: -1.0 };
+    const IDENTITY: Transform = Transform { yaw: 0.0, pitch: 0.0, posistion: Point3D { x: 0.0, y: 0.0, z: 0.0 } };
+
+    fn rasterizer() -> Rasterizer {
+        let mut rasterizer = Rasterizer::new(WIDTH, HEIGHT, subdivide_count(WIDTH, HEIGHT, 4));
+        // Full ambient light shades every fragment at exactly its texel color
+        rasterizer.ambient = Point3D { x: 1.0, y: 1.0, z: 1.0 };
+        rasterizer
+    }
+
+    // At the origin looking down +z, a 90 degree FOV spans view y in [-z, z] over the screen height
+    fn camera() -> Camera {
+        Camera {
+            fov: 90.0f32.to_radians(),
+            camera_speed: 1.0,
+            mouse_sensitivity: 0.0,
+            transform: IDENTITY,
+            pitch_mode: PitchMode::default(),
+        }
+    }
+
+    // Triangle parallel to the screen at view depth `z`, corners in view units with y pointing down.
+    // Corners going counter-clockwise as seen on screen face the camera
+    fn triangle(corners: [(f32, f32); 3], z: f32, material: usize) -> Triangle3D {
+        let vertex = |(x, y): (f32, f32)| Vertex {
+            position: Point3D { x, y, z },
+            texcoord: Point2D { x: 0.0, y: 0.0 },
+            normal: Point3D { x: 0.0, y: 0.0, z: -1.0 },
+            alpha: 1.0,
+        };
+        Triangle3D::from_vertices(vertex(corners[0]), vertex(corners[1]), vertex(corners[2]), material)
+    }
+
+    // One flat colored material per color, triangles keep their given order
+    fn model(triangles: Vec<Triangle3D>, colors: &[(u8, u8, u8, u8)]) -> Model {
+        Model::new(
+            triangles,
+            colors.iter().map(|_| Material::default()).collect(),
+            colors.iter().map(|&(r, g, b, a)| Texture::solid(r, g, b, a).into()).collect(),
+        )
+    }
+
+    // Screen pixel at a view-space point on the plane z
+    fn pixel_at(rasterizer: &Rasterizer, x: f32, y: f32, z: f32) -> (u8, u8, u8, u8) {
+        let screen = view_to_screen(Point3D { x, y, z }, rasterizer.resolution, camera().scaled_inv_world_height(rasterizer.resolution.y));
+        rasterizer.screen.get_pixel(screen.x as u32, screen.y as u32)
+    }
+
+    #[test]
+    fn filter_range_selects_faces_in_file_order() {
+        // Face 0 on the left uses material 1, so sorting by material moves it after face 1 on the right
+        let mut model = model(vec![
+            triangle([(-8.0, -4.0), (-8.0, 4.0), (-2.0, -4.0)], 10.0, 1),
+            triangle([(2.0, -4.0), (2.0, 4.0), (8.0, -4.0)], 10.0, 0),
+        ], &[GREEN, RED]);
+        for (face, tri) in model.triangles.iter_mut().enumerate() {
+            tri.face = face as u32;
+        }
+        model.sort_by_material();
+
+        let mut rasterizer = rasterizer();
+        rasterizer.triangle_filter = TriangleFilter { range: Some(1..2), material: None };
+        rasterizer.screen.clear(0, 0, 0, 255);
+        rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+        assert_eq!(pixel_at(&rasterizer, -6.0, -2.0, 10.0), (0, 0, 0, 255));
+        assert_eq!(pixel_at(&rasterizer, 4.0, -2.0, 10.0), GREEN);
+    }
+
+    // Stored view depth under a view-space point on the plane z
+    fn depth_at(rasterizer: &Rasterizer, x: f32, y: f32, z: f32) -> f32 {
+        let screen = view_to_screen(Point3D { x, y, z }, rasterizer.resolution, camera().scaled_inv_world_height(rasterizer.resolution.y));
+        rasterizer.screen.get_depth(screen.x as u32, screen.y as u32)
+    }
+
+    #[test]
+    fn separate_draw_triangles_calls_composite_by_depth() {
+        // Two overlapping batches, the near one only covers the left half of the far one's screen area
+        let far = [triangle([(-8.0, -8.0), (-8.0, 8.0), (8.0, -8.0)], 20.0, 0)];
+        let near = [triangle([(-4.0, -4.0), (-4.0, 4.0), (0.0, -4.0)], 10.0, 0)];
+        for near_first in [false, true] {
+            let rasterizer = rasterizer();
+            rasterizer.screen.clear(0, 0, 0, 255);
+            rasterizer.screen.clear_depth(f32::INFINITY);
+            let batches = if near_first { [&near, &far] } else { [&far, &near] };
+            for batch in batches {
+                rasterizer.draw_triangles(batch, &IDENTITY, &Material::default(), &camera(), LIGHT, None);
+            }
+            // Where they overlap the near batch wins whichever call came last, elsewhere the far batch is kept
+            let (overlap, uncovered) = (depth_at(&rasterizer, -3.0, -3.0, 10.0), depth_at(&rasterizer, 3.0, -5.0, 20.0));
+            assert!((overlap - 10.0).abs() < 1e-3, "near first: {}, depth {}", near_first, overlap);
+            assert!((uncovered - 20.0).abs() < 1e-3, "near first: {}, depth {}", near_first, uncovered);
+            assert_eq!(pixel_at(&rasterizer, 3.0, -5.0, 20.0), (255, 255, 255, 255));
+            assert_eq!(depth_at(&rasterizer, 6.0, 6.0, 20.0), f32::INFINITY);
+        }
+    }
+
+    #[test]
+    fn unchanged_frames_are_skipped_until_something_changes() {
+        let mut cache = RenderCache::new();
+        assert!(cache.needs_render((IDENTITY, false)));
+        assert!(!cache.needs_render((IDENTITY, false)));
+        assert_eq!((cache.renders, cache.skips), (1, 1));
+
+        // A render setting in the key counts as a change just like a moved transform
+        assert!(cache.needs_render((IDENTITY, true)));
+        let moved = Transform { yaw: 0.5, ..IDENTITY };
+        assert!(cache.needs_render((moved, true)));
+        assert!(!cache.needs_render((moved, true)));
+        // Changes outside the key, like a new mesh, have to invalidate explicitly
+        cache.invalidate();
+        assert!(cache.needs_render((moved, true)));
+        assert_eq!((cache.renders, cache.skips), (4, 2));
+    }
+
+    #[test]
+    fn framebuffer_is_identical_across_thread_counts() {
+        // Coplanar triangles of different colors z-fight, so any change in their order between runs changes pixels
+        let mut triangles = Vec::new();
+        for i in 0..24 {
+            let offset = (i % 6) as f32 - 3.0;
+            triangles.push(triangle([(offset - 6.0, -5.0), (offset - 6.0, 5.0), (offset + 4.0, -5.0)], 10.0, i % 2));
+            triangles.push(triangle([(-7.0, offset - 2.0), (-7.0, offset + 4.0), (7.0, offset - 2.0)], 10.0 + (i % 3) as f32, (i + 1) % 2));
+        }
+        let model = model(triangles, &[RED, GREEN]);
+        let render = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| {
+                let rasterizer = rasterizer();
+                rasterizer.screen.clear(0, 0, 0, 255);
+                rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+                let rgba = unsafe { &*rasterizer.screen.rgba.get() }.clone();
+                let depth: Vec<u32> = unsafe { &*rasterizer.screen.depth.get() }.iter().map(|z| z.to_bits()).collect();
+                (rgba, depth)
+            })
+        };
+        let single = render(1);
+        assert!(single.0.chunks_exact(4).any(|pixel| pixel == [255, 0, 0, 255]));
+        assert!(single.0.chunks_exact(4).any(|pixel| pixel == [0, 255, 0, 255]));
+        for threads in [2, 3, 8] {
+            assert!(render(threads) == single, "{} threads rendered a different frame", threads);
+        }
+    }
+
+    #[test]
+    fn rotated_grid_gives_near_horizontal_edges_more_coverage_levels() {
+        // Only the bottom edge crosses the screen, it drops 0.8 pixels over the full width
+        let model = model(vec![triangle([(-40.0, -40.0), (-40.0, 1.0), (40.0, 2.0)], 10.0, 0)], &[RED]);
+        let edge_levels = |pattern: Option<SamplePattern>| {
+            let mut rasterizer = rasterizer();
+            rasterizer.edge_aa = pattern;
+            rasterizer.screen.clear(0, 0, 0, 255);
+            rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+            let mut levels: Vec<u8> = unsafe { &*rasterizer.screen.rgba.get() }
+                .chunks_exact(4)
+                .map(|pixel| pixel[0])
+                .filter(|&red| red != 0 && red != 255)
+                .collect();
+            levels.sort_unstable();
+            levels.dedup();
+            levels
+        };
+        assert!(edge_levels(None).is_empty());
+        let grid = edge_levels(Some(SamplePattern::Grid));
+        let rotated = edge_levels(Some(SamplePattern::RotatedGrid));
+        assert!(rotated.len() > grid.len(), "grid {:?}, rotated {:?}", grid, rotated);
+        // A quarter covered pixel has its center outside and isn't drawn, so half and three quarters are the levels seen
+        assert_eq!(rotated, [128, 191]);
+    }
+
+    #[test]
+    fn mask_limits_a_full_screen_draw_to_its_region() {
+        let model = model(vec![triangle([(-40.0, -40.0), (-40.0, 120.0), (120.0, -40.0)], 10.0, 0)], &[RED]);
+        let marked = Rect { min_x: 12, min_y: 8, max_x: 40, max_y: 30 };
+        let draw = |mode: MaskMode| {
+            let mut rasterizer = rasterizer();
+            rasterizer.mask_mode = mode;
+            rasterizer.screen.clear(0, 0, 0, 255);
+            rasterizer.screen.clear_mask(0);
+            rasterizer.screen.fill_mask_rect(&marked, 1);
+            rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+            rasterizer
+        };
+        let unmasked = draw(MaskMode::Off);
+        for mode in [MaskMode::Inside, MaskMode::Outside] {
+            let masked = draw(mode);
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    let inside = (marked.min_x..marked.max_x).contains(&x) && (marked.min_y..marked.max_y).contains(&y);
+                    let expected = if inside == (mode == MaskMode::Inside) { unmasked.screen.get_pixel(x, y) } else { (0, 0, 0, 255) };
+                    assert_eq!(masked.screen.get_pixel(x, y), expected, "{:?} at ({}, {})", mode, x, y);
+                }
+            }
+        }
+        // The whole marked region is covered by the triangle, so Inside really drew all of it
+        assert!((marked.min_y..marked.max_y).all(|y| (marked.min_x..marked.max_x).all(|x| draw(MaskMode::Inside).screen.get_pixel(x, y) == RED)));
+    }
+
+    #[test]
+    fn scissor_leaves_the_previous_frame_outside_its_rect() {
+        let model = model(vec![triangle([(-40.0, -40.0), (-40.0, 120.0), (120.0, -40.0)], 10.0, 0)], &[RED]);
+        let dirty = Rect { min_x: 5, min_y: 20, max_x: 50, max_y: 33 };
+        let unscissored = rasterizer();
+        unscissored.screen.clear(0, 0, 0, 255);
+        unscissored.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+        // The previous frame: green everywhere at depth 4, in front of the triangle
+        let mut scissored = rasterizer();
+        scissored.scissor = Some(dirty);
+        scissored.screen.clear_color(0, 255, 0, 255);
+        scissored.screen.clear_depth(4.0);
+        scissored.screen.clear_rect(&dirty, 0, 0, 0, 255);
+        scissored.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let inside = (dirty.min_x..dirty.max_x).contains(&x) && (dirty.min_y..dirty.max_y).contains(&y);
+                if inside {
+                    assert_eq!(scissored.screen.get_pixel(x, y), unscissored.screen.get_pixel(x, y), "({}, {})", x, y);
+                    assert_eq!(scissored.screen.get_depth(x, y), unscissored.screen.get_depth(x, y), "({}, {})", x, y);
+                } else {
+                    assert_eq!(scissored.screen.get_pixel(x, y), (0, 255, 0, 255), "({}, {})", x, y);
+                    assert_eq!(scissored.screen.get_depth(x, y), 4.0, "({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn early_depth_test_rejects_only_what_the_full_test_would() {
+        let mut rng = StdRng::seed_from_u64(472);
+        for func in [DepthFunc::Less, DepthFunc::LessEqual, DepthFunc::Greater, DepthFunc::Always, DepthFunc::Never] {
+            for i in 0..20_000 {
+                let stored: f32 = rng.gen_range(0.1..500.0);
+                // Half the fragments within a few ulps of the stored depth, where rounding decides
+                let depth = if i % 2 == 0 { stored * (1.0 + rng.gen_range(-1e-6..1e-6)) } else { rng.gen_range(0.1..500.0) };
+                let inv_depth = 1.0 / depth;
+                let full_rejects = !func.passes(Precision::Exact.recip(inv_depth), stored);
+                let early_rejects = func.rejects_inv_depth(inv_depth, stored);
+                assert!(!early_rejects || full_rejects, "{:?} wrongly rejected {} behind {}", func, depth, stored);
+                // Away from ties the shortcut catches every occluded fragment
+                if (depth / stored - 1.0).abs() > 1e-5 {
+                    assert_eq!(early_rejects, full_rejects, "{:?} at {} behind {}", func, depth, stored);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunked_draws_match_drawing_everything_at_once() {
+        // Overlapping and coplanar triangles, so any change in draw order shows up in the frame
+        let mut triangles = Vec::new();
+        for i in 0..24 {
+            let offset = (i % 6) as f32 - 3.0;
+            triangles.push(triangle([(offset - 6.0, -5.0), (offset - 6.0, 5.0), (offset + 4.0, -5.0)], 10.0, i % 2));
+            triangles.push(triangle([(-7.0, offset - 2.0), (-7.0, offset + 4.0), (7.0, offset - 2.0)], 10.0 + (i % 3) as f32, (i + 1) % 2));
+        }
+        let model = model(triangles, &[RED, GREEN]);
+        // The middle instance is behind the camera and culled whole
+        let shifted = |x: f32, z: f32| Transform { posistion: Point3D { x, y: 1.0, z }, ..IDENTITY };
+        let instances = [IDENTITY, shifted(0.0, -100.0), shifted(3.0, 0.5)];
+        let render = |chunk_size: Option<usize>| {
+            let mut rasterizer = rasterizer();
+            rasterizer.chunk_size = chunk_size;
+            rasterizer.screen.clear(0, 0, 0, 255);
+            rasterizer.draw_instanced(&model, &instances, &camera(), LIGHT, None);
+            let rgba = unsafe { &*rasterizer.screen.rgba.get() }.clone();
+            let depth: Vec<u32> = unsafe { &*rasterizer.screen.depth.get() }.iter().map(|z| z.to_bits()).collect();
+            (rgba, depth)
+        };
+        let all_at_once = render(None);
+        assert!(all_at_once.0.chunks_exact(4).any(|pixel| pixel == [255, 0, 0, 255]));
+        // Chunks that split instances and material runs, down to one pair at a time, and one bigger than the whole draw
+        for chunk_size in [1, 5, 48, 61, 1000] {
+            assert!(render(Some(chunk_size)) == all_at_once, "chunks of {} rendered a different frame", chunk_size);
+        }
+    }
+
+    // Flat colored models whose materials are all blended at half opacity
+    fn translucent(triangles: Vec<Triangle3D>, colors: &[(u8, u8, u8, u8)]) -> Model {
+        let mut model = model(triangles, colors);
+        for material in &mut model.materials {
+            material.blend = BlendMode::Blend;
+            material.dissolve = 0.5;
+        }
+        model
+    }
+
+    #[test]
+    fn translucent_surfaces_blend_over_opaque_ones_only_in_front() {
+        // Opaque red nearer than the green sheet on the left, farther on the right. The sheet is submitted first
+        let sheet = triangle([(-40.0, -40.0), (-40.0, 120.0), (120.0, -40.0)], 10.0, 1);
+        let near = triangle([(-12.0, -5.0), (-12.0, 5.0), (-2.0, -5.0)], 8.0, 0);
+        let far = triangle([(2.0, -5.0), (2.0, 5.0), (12.0, -5.0)], 12.0, 0);
+        let mut model = model(vec![sheet, near, far], &[RED, GREEN]);
+        model.materials[1].blend = BlendMode::Blend;
+        model.materials[1].dissolve = 0.5;
+        let rasterizer = rasterizer();
+        rasterizer.screen.clear(0, 0, 0, 255);
+        rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+        // Nothing blended is drawn until the flush
+        assert_eq!(pixel_at(&rasterizer, 0.0, 8.0, 10.0), (0, 0, 0, 255));
+        rasterizer.flush_blended();
+
+        assert_eq!(pixel_at(&rasterizer, -9.0, -2.0, 8.0), RED);
+        let (r, g, _, _) = pixel_at(&rasterizer, 5.0, -2.0, 12.0);
+        assert!((120..=136).contains(&r) && (120..=136).contains(&g), "({}, {})", r, g);
+        let (r, g, _, _) = pixel_at(&rasterizer, 0.0, 8.0, 10.0);
+        assert!(r == 0 && (120..=136).contains(&g), "({}, {})", r, g);
+        // The sheet doesn't write depth, so the far triangle's stays
+        assert!((depth_at(&rasterizer, 5.0, -2.0, 12.0) - 12.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn blended_draws_sort_back_to_front_across_calls() {
+        let full = |z: f32| triangle([(-40.0, -40.0), (-40.0, 120.0), (120.0, -40.0)], z, 0);
+        let near = translucent(vec![full(5.0)], &[GREEN]);
+        let middle = translucent(vec![full(10.0)], &[RED]);
+        let white = Material { blend: BlendMode::Blend, dissolve: 0.5, ..Material::default() };
+        // Draw 0 is the nearest and draw 2, the farthest, goes through draw_triangles
+        let render = |flush_each: bool, order: [usize; 3]| {
+            let rasterizer = rasterizer();
+            rasterizer.screen.clear(0, 0, 0, 255);
+            for draw in order {
+                match draw {
+                    0 => { rasterizer.draw_instanced(&near, &[IDENTITY], &camera(), LIGHT, None); }
+                    1 => { rasterizer.draw_instanced(&middle, &[IDENTITY], &camera(), LIGHT, None); }
+                    _ => { rasterizer.draw_triangles(&[full(20.0)], &IDENTITY, &white, &camera(), LIGHT, None); }
+                }
+                if flush_each {
+                    rasterizer.flush_blended();
+                }
+            }
+            rasterizer.flush_blended();
+            pixel_at(&rasterizer, 0.0, 0.0, 10.0)
+        };
+        let composited_in_order = render(true, [2, 1, 0]);
+        assert_ne!(render(true, [0, 1, 2]), composited_in_order);
+        assert_eq!(render(false, [0, 1, 2]), composited_in_order);
+        assert_eq!(render(false, [1, 0, 2]), composited_in_order);
+    }
+
+    #[test]
+    fn only_two_sided_materials_show_back_faces() {
+        // Both triangles wound clockwise on screen, so they face away. Material 0 is two-sided, material 1 isn't
+        let left = triangle([(-10.0, -5.0), (-2.0, -5.0), (-10.0, 5.0)], 10.0, 0);
+        let right = triangle([(2.0, -5.0), (10.0, -5.0), (2.0, 5.0)], 10.0, 1);
+        let mut model = model(vec![left, right], &[RED, GREEN]);
+        model.materials[0].two_sided = true;
+        let rasterizer = rasterizer();
+        rasterizer.screen.clear(0, 0, 0, 255);
+        rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+        assert_eq!(pixel_at(&rasterizer, -8.0, -3.0, 10.0), RED);
+        assert_eq!(pixel_at(&rasterizer, 4.0, -3.0, 10.0), (0, 0, 0, 255));
+    }
+
+    #[test]
+    fn vertex_alpha_interpolates_to_half_midway() {
+        // Alpha 1 at the top-left corner and 0 at the others, (-2, -4) sits at barycentric (0.5, 0.1, 0.4)
+        let mut tri = triangle([(-10.0, -5.0), (-10.0, 5.0), (10.0, -5.0)], 10.0, 0);
+        tri.alpha_b = 0.0;
+        tri.alpha_c = 0.0;
+        let model = model(vec![tri], &[RED]);
+        let rasterizer = rasterizer();
+        rasterizer.screen.clear(0, 0, 0, 255);
+        rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+
+        let (_, _, _, a) = pixel_at(&rasterizer, -2.0, -4.0, 10.0);
+        assert!((120..=136).contains(&a), "{}", a);
+        // Near the opaque corner the fragment is nearly opaque
+        assert!(pixel_at(&rasterizer, -9.0, -4.0, 10.0).3 > 200);
+    }
+
+    #[test]
+    fn one_triangle_draws_at_every_instance_transform() {
+        let model = model(vec![triangle([(-3.0, -3.0), (-3.0, 3.0), (3.0, -3.0)], 10.0, 0)], &[RED]);
+        let at = |x: f32| Transform { posistion: Point3D { x, y: 0.0, z: 0.0 }, ..IDENTITY };
+        let rasterizer = rasterizer();
+        rasterizer.screen.clear(0, 0, 0, 255);
+        let instances = [at(-7.0), at(7.0)];
+        rasterizer.draw_instanced(&model, &instances, &camera(), LIGHT, None);
+
+        assert_eq!(rasterizer.transform_instances(&model, &instances, &camera()).len(), 2);
+        assert_eq!(pixel_at(&rasterizer, -8.0, -1.0, 10.0), RED);
+        assert_eq!(pixel_at(&rasterizer, 6.0, -1.0, 10.0), RED);
+        // Nothing at the untransformed position between them
+        assert_eq!(pixel_at(&rasterizer, -1.0, -1.0, 10.0), (0, 0, 0, 255));
+    }
+
+    #[test]
+    fn fragment_shader_output_replaces_the_texture() {
+        let mut tri = triangle([(-10.0, -5.0), (-10.0, 5.0), (10.0, -5.0)], 10.0, 0);
+        // Up at the bottom-left corner, toward the camera at the others
+        tri.nb = Point3D { x: 0.0, y: 1.0, z: 0.0 };
+        let model = model(vec![tri], &[RED]);
+        let mut rasterizer = rasterizer();
+        let to_color = |v: f32| ((v + 1.0) * 127.5).round() as u8;
+        rasterizer.fragment_shader = Some(Box::new(move |input: FragmentInput| (to_color(input.normal.x), to_color(input.normal.y), to_color(input.normal.z), 255)));
+        rasterizer.screen.clear(0, 0, 0, 255);
+        rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+
+        // Near the top-right corner the normal is (0, 0, -1)
+        let (r, g, b, _) = pixel_at(&rasterizer, 8.0, -4.5, 10.0);
+        assert!(r.abs_diff(128) <= 2 && g.abs_diff(128) <= 8 && b <= 8, "({}, {}, {})", r, g, b);
+        // Near the bottom-left corner it's mostly +y
+        let (r, g, b, _) = pixel_at(&rasterizer, -9.5, 4.0, 10.0);
+        assert!(r.abs_diff(128) <= 2 && g > 220 && b < 128, "({}, {}, {})", r, g, b);
+    }
+
+    #[test]
+    fn lequal_decal_without_depth_write_draws_over_its_base() {
+        let corners = [(-10.0, -5.0), (-10.0, 5.0), (10.0, -5.0)];
+        let base = model(vec![triangle(corners, 10.0, 0)], &[RED]);
+        let decal = model(vec![triangle(corners, 10.0, 0)], &[GREEN]);
+        let mut rasterizer = rasterizer();
+        rasterizer.screen.clear(0, 0, 0, 255);
+        rasterizer.draw_instanced(&base, &[IDENTITY], &camera(), LIGHT, None);
+        let base_depth = depth_at(&rasterizer, -5.0, -2.0, 10.0);
+
+        // Less loses every tie against the base
+        rasterizer.depth_func = DepthFunc::Less;
+        rasterizer.draw_instanced(&decal, &[IDENTITY], &camera(), LIGHT, None);
+        assert_eq!(pixel_at(&rasterizer, -5.0, -2.0, 10.0), RED);
+
+        rasterizer.depth_func = DepthFunc::LessEqual;
+        rasterizer.depth_write = false;
+        rasterizer.draw_instanced(&decal, &[IDENTITY], &camera(), LIGHT, None);
+        assert_eq!(pixel_at(&rasterizer, -5.0, -2.0, 10.0), GREEN);
+        assert_eq!(depth_at(&rasterizer, -5.0, -2.0, 10.0), base_depth);
+    }
+
+    #[test]
+    fn scrolled_uvs_shift_by_velocity_times_elapsed() {
+        // Every corner has texcoord (0, 0), so a fragment's UV is just the scroll
+        let mut model = model(vec![triangle([(-10.0, -5.0), (-10.0, 5.0), (10.0, -5.0)], 10.0, 0)], &[RED]);
+        model.materials[0].uv_velocity = Point2D { x: 0.1, y: 0.04 };
+        let mut rasterizer = rasterizer();
+        rasterizer.fragment_shader = Some(Box::new(|input: FragmentInput| ((input.uv.x * 250.0).round() as u8, (input.uv.y * 250.0).round() as u8, 0, 255)));
+        let mut uv_at = |elapsed: f32| {
+            model.update_uv_scroll(elapsed);
+            rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+            let (r, g, _, _) = pixel_at(&rasterizer, -5.0, -2.0, 10.0);
+            (r as i32, g as i32)
+        };
+        let (u1, v1) = uv_at(1.0);
+        let (u3, v3) = uv_at(3.0);
+        // Two seconds apart, (0.2, 0.08) in UV is (50, 20) in the shader's color
+        assert_eq!((u1, v1), (25, 10));
+        assert_eq!((u3 - u1, v3 - v1), (50, 20));
+    }
+
+    #[test]
+    fn world_position_at_the_centroid_is_the_vertex_average() {
+        // Corners at different depths, so screen-space barycentrics at the centroid aren't a third each
+        let vertex = |x: f32, y: f32, z: f32| Vertex {
+            position: Point3D { x, y, z },
+            texcoord: Point2D { x: 0.0, y: 0.0 },
+            normal: Point3D { x: 0.0, y: 0.0, z: -1.0 },
+            alpha: 1.0,
+        };
+        let tri = Triangle3D::from_vertices(vertex(-6.0, -4.0, 8.0), vertex(-6.0, 4.0, 14.0), vertex(6.0, -4.0, 10.0), 0);
+        let model = model(vec![tri], &[RED]);
+        let transform = Transform { posistion: Point3D { x: 0.5, y: -1.0, z: 2.0 }, ..IDENTITY };
+        let centroid = Point3D { x: -2.0, y: -4.0 / 3.0, z: 32.0 / 3.0 };
+
+        // Finer pixels than the other tests so the sampled pixel center is close to the centroid
+        let mut rasterizer = Rasterizer::new(640, 480, subdivide_count(640, 480, 4));
+        let resolution = rasterizer.resolution;
+        let screen = vertex_to_screen(centroid, &transform, &camera(), resolution, camera().scaled_inv_world_height(resolution.y));
+        let (px, py) = (screen.x as u32, screen.y as u32);
+        let sampled = Arc::new(Mutex::new(None));
+        let output = Arc::clone(&sampled);
+        rasterizer.fragment_shader = Some(Box::new(move |input: FragmentInput| {
+            if (input.x, input.y) == (px, py) {
+                *output.lock().unwrap() = Some(input.world_pos);
+            }
+            (0, 0, 0, 255)
+        }));
+        rasterizer.draw_instanced(&model, &[transform], &camera(), LIGHT, None);
+
+        let world = sampled.lock().unwrap().expect("the centroid's pixel was shaded");
+        let expected = transform.to_world_point(centroid);
+        for (got, want) in [(world.x, expected.x), (world.y, expected.y), (world.z, expected.z)] {
+            assert!((got - want).abs() < 0.05, "{:?} vs {:?}", world, expected);
+        }
+    }
+
+    #[test]
+    fn sorting_groups_triangles_into_one_run_per_material() {
+        let materials = [2, 0, 1, 0, 2, 2, 1, 0];
+        let triangles = materials.iter().enumerate()
+            .map(|(i, &material)| {
+                let mut tri = triangle([(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0)], 10.0, material);
+                tri.face = i as u32;
+                tri
+            })
+            .collect();
+        let mut model = model(triangles, &[RED, GREEN, RED]);
+        model.sort_by_material();
+
+        let runs: Vec<usize> = model.triangles.chunk_by(|p, q| p.material == q.material).map(|run| run[0].material).collect();
+        assert_eq!(runs, [0, 1, 2]);
+        // Stable, so file order survives within a material
+        let faces: Vec<u32> = model.triangles.iter().map(|tri| tri.face).collect();
+        assert_eq!(faces, [1, 3, 7, 2, 6, 0, 4, 5]);
+    }
+
+    #[test]
+    fn polygon_offset_wins_every_coplanar_pixel_in_either_order() {
+        // Both triangles on the tilted plane z = 10 + 0.3x, with different corners so their depths round differently
+        let on_plane = |corners: [(f32, f32); 3]| {
+            let vertex = |(x, y): (f32, f32)| Vertex {
+                position: Point3D { x, y, z: 10.0 + 0.3 * x },
+                texcoord: Point2D { x: 0.0, y: 0.0 },
+                normal: Point3D { x: 0.0, y: 0.0, z: -1.0 },
+                alpha: 1.0,
+            };
+            Triangle3D::from_vertices(vertex(corners[0]), vertex(corners[1]), vertex(corners[2]), 0)
+        };
+        let base = model(vec![on_plane([(-12.0, -8.0), (-12.0, 8.0), (12.0, -8.0)])], &[RED]);
+        let decal = model(vec![on_plane([(-6.0, -4.0), (-6.0, 4.0), (4.0, -4.0)])], &[GREEN]);
+        let offset = PolygonOffset { bias: 0.0, factor: -1.0, units: -4.0 };
+
+        let alone = rasterizer();
+        alone.screen.clear(0, 0, 0, 255);
+        alone.draw_instanced(&decal, &[IDENTITY], &camera(), LIGHT, None);
+        let covered: Vec<(u32, u32)> = (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y))).filter(|&(x, y)| alone.screen.get_pixel(x, y) == GREEN).collect();
+        assert!(covered.len() > 50);
+
+        for decal_first in [false, true] {
+            let mut rasterizer = rasterizer();
+            rasterizer.depth_func = DepthFunc::Less;
+            rasterizer.screen.clear(0, 0, 0, 255);
+            let draws: [(&Model, PolygonOffset); 2] = if decal_first { [(&decal, offset), (&base, PolygonOffset::default())] } else { [(&base, PolygonOffset::default()), (&decal, offset)] };
+            for (model, offset) in draws {
+                rasterizer.polygon_offset = offset;
+                rasterizer.draw_instanced(model, &[IDENTITY], &camera(), LIGHT, None);
+            }
+            for &(x, y) in &covered {
+                assert_eq!(rasterizer.screen.get_pixel(x, y), GREEN, "({}, {}), decal first: {}", x, y, decal_first);
+            }
+        }
+    }
+
+    #[test]
+    fn debug_ray_endpoints_project_where_vertices_would() {
+        let rasterizer = rasterizer();
+        let resolution = rasterizer.resolution;
+        let scale = camera().scaled_inv_world_height(resolution.y);
+        // Both ends land inside a pixel rather than on its edge, so the stepped line's rounding can't move them to a neighbor
+        let (start, end) = (Point3D { x: -8.0, y: -3.0, z: 10.0 }, Point3D { x: 6.25, y: 4.25, z: 12.0 });
+        rasterizer.screen.clear(0, 0, 0, 255);
+        rasterizer.draw_lines(&[(start, end)], &camera(), (255, 255, 255), 0.0);
+
+        let lit = |p: Point3D| rasterizer.screen.get_pixel(p.x as u32, p.y as u32) == (255, 255, 255, 255);
+        let (start, end) = (view_to_screen(start, resolution, scale), view_to_screen(end, resolution, scale));
+        assert!(lit(start) && lit(end));
+        // One pixel past either end along x is left alone
+        assert!(!lit(start - Point3D { x: 1.0, y: 0.0, z: 0.0 }) && !lit(end + Point3D { x: 1.0, y: 0.0, z: 0.0 }));
+
+        // A cursor ray seen from the camera that cast it is a single point on the cursor's pixel
+        let (origin, direction) = camera().pixel_ray(40.5, 12.5, resolution);
+        rasterizer.screen.clear(0, 0, 0, 255);
+        rasterizer.draw_lines(&[(origin, origin + direction * 50.0)], &camera(), (255, 255, 255), 0.0);
+        let drawn: Vec<(u32, u32)> = (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y))).filter(|&(x, y)| rasterizer.screen.get_pixel(x, y).0 == 255).collect();
+        assert_eq!(drawn, [(40, 12)]);
+    }
+
+    #[test]
+    fn exact_uvs_match_the_ray_traced_reference_and_fast_ones_stay_close() {
+        // On the tilted plane z = 10 + 0.3x with texcoords (x / 20 + 0.5, y / 20 + 0.5), so any pixel's UV follows from where its ray hits the plane
+        let vertex = |x: f32, y: f32| Vertex {
+            position: Point3D { x, y, z: 10.0 + 0.3 * x },
+            texcoord: Point2D { x: x / 20.0 + 0.5, y: y / 20.0 + 0.5 },
+            normal: Point3D { x: 0.0, y: 0.0, z: -1.0 },
+            alpha: 1.0,
+        };
+        let model = model(vec![Triangle3D::from_vertices(vertex(-10.0, -8.0), vertex(-10.0, 8.0), vertex(10.0, -8.0), 0)], &[RED]);
+        let reference = |x: u32, y: u32, resolution: Point2D, scale: f32| {
+            let ray = reconstruct_view_pos(x as f32 + 0.5, y as f32 + 0.5, 1.0, resolution, scale);
+            let t = 10.0 / (1.0 - 0.3 * ray.x as f64);
+            (t * ray.x as f64 / 20.0 + 0.5, t * ray.y as f64 / 20.0 + 0.5)
+        };
+
+        let mut worst = Vec::new();
+        for precision in [Precision::Exact, Precision::Fast { newton_steps: 1 }] {
+            let mut rasterizer = rasterizer();
+            rasterizer.precision = precision;
+            let fragments = Arc::new(Mutex::new(Vec::new()));
+            let output = Arc::clone(&fragments);
+            rasterizer.fragment_shader = Some(Box::new(move |input: FragmentInput| {
+                output.lock().unwrap().push((input.x, input.y, input.uv));
+                (0, 0, 0, 255)
+            }));
+            rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+            let (resolution, scale) = (rasterizer.resolution, camera().scaled_inv_world_height(rasterizer.resolution.y));
+            let fragments = fragments.lock().unwrap();
+            assert!(fragments.len() > 100);
+            worst.push(fragments.iter().map(|&(x, y, uv)| {
+                let (u, v) = reference(x, y, resolution, scale);
+                (uv.x as f64 - u).abs().max((uv.y as f64 - v).abs())
+            }).fold(0.0, f64::max));
+        }
+        assert!(worst[0] < 1e-5, "exact {}", worst[0]);
+        assert!(worst[1] < 1e-2, "fast {}", worst[1]);
+    }
+
+    #[test]
+    fn each_render_target_is_drawn_at_its_own_size() {
+        let mut rasterizer = rasterizer();
+        let small = rasterizer.add_target(32, 24, 2);
+        let wide = rasterizer.add_target(40, 10, 3);
+        let model = model(vec![triangle([(-8.0, -8.0), (-8.0, 8.0), (8.0, -8.0)], 10.0, 0)], &[RED]);
+
+        for (index, width, height) in [(small, 32, 24), (wide, 40, 10)] {
+            let (size, inside, outside) = rasterizer.with_target(index, |target| {
+                target.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+                let size = (target.screen.width, target.screen.height, target.resolution);
+                (size, pixel_at(target, -3.0, -3.0, 10.0), pixel_at(target, 3.0, 3.0, 10.0))
+            });
+            assert_eq!(size, (width, height, Point2D { x: width as f32, y: height as f32 }));
+            assert_eq!(inside, RED);
+            assert_ne!(outside, RED);
+            // The target keeps what was drawn into it after the main screen is swapped back
+            assert_eq!((rasterizer.targets[index].screen.width, rasterizer.targets[index].screen.height), (width, height));
+        }
+        // Nothing reached the main screen, which is back at its own size
+        assert_eq!((rasterizer.screen.width, rasterizer.screen.height), (WIDTH, HEIGHT));
+        assert_ne!(pixel_at(&rasterizer, -3.0, -3.0, 10.0), RED);
+        let drawn = (0..24).flat_map(|y| (0..32).map(move |x| (x, y))).filter(|&(x, y)| rasterizer.targets[small].screen.get_pixel(x, y) == RED).count();
+        assert!(drawn > 0);
+    }
+
+    #[test]
+    fn conservative_mode_lights_a_sub_pixel_thin_triangle() {
+        // About a tenth of a pixel tall, squeezed between two rows of pixel centers
+        let model = model(vec![triangle([(-8.0, 0.02), (-8.0, 0.06), (8.0, 0.02)], 10.0, 0)], &[RED]);
+        let lit = |conservative: bool| {
+            let mut rasterizer = rasterizer();
+            rasterizer.conservative = conservative;
+            rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+            (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y))).filter(|&(x, y)| rasterizer.screen.get_pixel(x, y) == RED).map(|(_, y)| y).collect::<Vec<u32>>()
+        };
+        assert!(lit(false).is_empty());
+        let rows = lit(true);
+        assert!(rows.len() >= 30, "{} pixels", rows.len());
+        assert!(rows.iter().all(|&y| y == 23 || y == 24), "{rows:?}");
+    }
+
+    #[test]
+    fn depth_bias_breaks_the_tie_between_close_surfaces() {
+        // Red a thousandth of a unit behind green, drawn after it
+        let front = model(vec![triangle([(-8.0, -8.0), (-8.0, 8.0), (8.0, -8.0)], 10.0, 0)], &[GREEN]);
+        let back = model(vec![triangle([(-8.0, -8.0), (-8.0, 8.0), (8.0, -8.0)], 10.001, 0)], &[RED]);
+        let winner = |bias: f32| {
+            let mut rasterizer = rasterizer();
+            rasterizer.draw_instanced(&front, &[IDENTITY], &camera(), LIGHT, None);
+            rasterizer.polygon_offset = PolygonOffset { bias, ..PolygonOffset::default() };
+            rasterizer.draw_instanced(&back, &[IDENTITY], &camera(), LIGHT, None);
+            (pixel_at(&rasterizer, -4.0, -4.0, 10.0), depth_at(&rasterizer, -4.0, -4.0, 10.0))
+        };
+        let (color, depth) = winner(0.0);
+        assert_eq!(color, GREEN);
+        assert!((depth - 10.0).abs() < 1e-4, "{depth}");
+        // Pulled 0.01 toward the camera the farther surface now wins, and its biased depth is what's stored
+        let (color, depth) = winner(-0.01);
+        assert_eq!(color, RED);
+        assert!((depth - 9.991).abs() < 1e-4, "{depth}");
+    }
+
+    #[test]
+    fn reloading_swaps_in_the_new_mesh() {
+        let dir = std::env::temp_dir().join(format!("rusterizer-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, second) = (dir.join("first.obj"), dir.join("second.obj"));
+        std::fs::write(&first, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        std::fs::write(&second, "v 0 0 5\nv 2 0 5\nv 2 2 5\nv 0 2 5\nf 1 2 3 4\n").unwrap();
+        let texture = dir.join("missing.png");
+        let load = |path: &std::path::Path| Model::load(path.to_str().unwrap(), texture.to_str().unwrap(), &ObjLoadOptions::default(), false, None);
+        let (mut model, _) = load(&first).unwrap();
+        let before: Vec<Point3D> = model.triangles.iter().flat_map(|t| [t.a, t.b, t.c]).collect();
+
+        // What the render thread does with a dropped model: the loaded one replaces the old one whole
+        let (reloaded, mesh) = load(&second).unwrap();
+        model = reloaded;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(before.len(), 3);
+        assert_eq!(model.triangles.len(), 2);
+        assert_eq!(mesh.unwrap().positions.len(), 4);
+        assert!(model.triangles.iter().all(|t| [t.a, t.b, t.c].iter().all(|p| p.z == 5.0 && !before.contains(p))));
+        // The bounds follow the new mesh
+        assert!((model.bounds.0.z - 5.0).abs() < 1e-5);
+        // A missing texture falls back to the placeholder instead of failing the reload
+        assert_eq!(model.textures[0].diffuse.rgba, Texture::placeholder().rgba);
+    }
+
+    #[test]
+    fn world_position_under_the_cursor_follows_the_stored_depth() {
+        let rasterizer = rasterizer();
+        rasterizer.screen.clear_depth(f32::INFINITY);
+        rasterizer.screen.unsafe_set_depth(40, 12, 5.0);
+        let mut cam = camera();
+        cam.transform.posistion = Point3D { x: 3.0, y: 1.0, z: -2.0 };
+
+        // 90 degrees over 48 rows puts the pixel center (40.5, 12.5) at (8.5, -11.5) / 24 of the depth from the view axis
+        let point = rasterizer.world_position_at(40.7, 12.2, &cam).unwrap();
+        let expected = Point3D { x: 3.0 + 8.5 / 24.0 * 5.0, y: 1.0 - 11.5 / 24.0 * 5.0, z: 3.0 };
+        let miss = point - expected;
+        assert!(dot3(miss, miss) < 1e-8, "{:?} vs {:?}", point, expected);
+
+        // The reconstructed point is on the cursor pixel's ray
+        let (origin, dir) = cam.pixel_ray(40.5, 12.5, rasterizer.resolution);
+        let along = dot3(point - origin, dir);
+        let off_ray = origin + dir * along - point;
+        assert!(dot3(off_ray, off_ray) < 1e-8);
+
+        assert_eq!(rasterizer.world_position_at(8.0, 8.0, &cam), None);
+        assert_eq!(rasterizer.world_position_at(-1.0, 12.0, &cam), None);
+    }
+
+    #[test]
+    fn uncovered_pixels_show_the_backdrop() {
+        use crate::screen::{Backdrop, BackdropFit};
+        // 2x1 image, blue left half and white right half
+        let texture = Texture { width: 2, height: 1, rgba: vec![0, 0, 255, 255, 255, 255, 255, 255] };
+        let backdrop = Backdrop { texture, fit: BackdropFit::Contain };
+        let model = model(vec![triangle([(-2.0, -2.0), (-2.0, 2.0), (2.0, -2.0)], 10.0, 0)], &[RED]);
+
+        let rasterizer = rasterizer();
+        rasterizer.screen.clear_to_backdrop(&backdrop, 0, 0, 0, 255);
+        rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+        assert_eq!(pixel_at(&rasterizer, -1.0, -1.0, 10.0), RED);
+        assert_eq!(rasterizer.screen.get_pixel(4, 24), (0, 0, 255, 255));
+        assert_eq!(rasterizer.screen.get_pixel(60, 24), (255, 255, 255, 255));
+        // Contain fits the 2:1 image to the 64 pixel width, leaving 8 rows of clear color above and below
+        assert_eq!(rasterizer.screen.get_pixel(4, 7), (0, 0, 0, 255));
+        assert_eq!(rasterizer.screen.get_pixel(4, 8), (0, 0, 255, 255));
+        assert_eq!(rasterizer.screen.get_pixel(60, 40), (0, 0, 0, 255));
+        // The backdrop is only color, the depth buffer is clear behind it
+        assert_eq!(rasterizer.screen.get_depth(4, 24), f32::INFINITY);
+    }
+
+    #[test]
+    fn triangle_colors_give_each_half_of_a_quad_its_own_deterministic_color() {
+        let quad = model(vec![
+            triangle([(-8.0, -8.0), (-8.0, 8.0), (8.0, -8.0)], 10.0, 0),
+            triangle([(8.0, -8.0), (-8.0, 8.0), (8.0, 8.0)], 10.0, 0),
+        ], &[RED]);
+        let halves = || {
+            let mut rasterizer = rasterizer();
+            rasterizer.triangle_colors = true;
+            rasterizer.screen.clear(0, 0, 0, 255);
+            rasterizer.draw_instanced(&quad, &[IDENTITY], &camera(), LIGHT, None);
+            (pixel_at(&rasterizer, -4.0, -4.0, 10.0), pixel_at(&rasterizer, 4.0, 4.0, 10.0))
+        };
+        let (first, second) = halves();
+        let opaque = |(r, g, b): (u8, u8, u8)| (r, g, b, 255);
+        assert_eq!((first, second), (opaque(id_color(0)), opaque(id_color(1))));
+        assert_ne!(first, second);
+        assert_eq!(halves(), (first, second));
+    }
+
+    #[test]
+    fn wireframe_overlay_marks_visible_edges_and_keeps_the_shaded_interior() {
+        const WIRE: (u8, u8, u8) = (255, 170, 40);
+        // The red triangle's left edge runs down view x = -8, the green one in front hides its middle
+        let model = model(vec![
+            triangle([(-8.0, -8.0), (-8.0, 8.0), (8.0, -8.0)], 10.0, 0),
+            triangle([(-6.0, -3.0), (-6.0, 3.0), (0.0, -3.0)], 5.0, 1),
+        ], &[RED, GREEN]);
+        let rasterizer = rasterizer();
+        rasterizer.screen.clear(0, 0, 0, 255);
+        rasterizer.draw_instanced(&model, &[IDENTITY], &camera(), LIGHT, None);
+        rasterizer.draw_lines(&crate::gizmo::wireframe_lines(&model.triangles, &IDENTITY, Some(0)), &camera(), WIRE, 0.002);
+
+        // Screen x 12.8, the line lands on one of the two columns around it
+        let edge_at = |row: u32| (12..=13).map(|x| rasterizer.screen.get_pixel(x, row)).collect::<Vec<_>>();
+        assert!(edge_at(8).contains(&(WIRE.0, WIRE.1, WIRE.2, 255)), "{:?}", edge_at(8));
+        assert!(!edge_at(24).contains(&(WIRE.0, WIRE.1, WIRE.2, 255)), "{:?}", edge_at(24));
+        assert!(edge_at(24).contains(&GREEN));
+        assert_eq!(pixel_at(&rasterizer, 2.0, -4.0, 10.0), RED);
+    }
+
+    #[test]
+    fn back_faces_filling_the_screen_flag_the_camera_as_inside() {
+        let screen_area = (WIDTH * HEIGHT) as f32;
+        let draw = |triangles: Vec<Triangle3D>| rasterizer().draw_instanced(&model(triangles, &[RED]), &[IDENTITY], &camera(), LIGHT, None);
+        // A quad wound away from the camera covering the whole screen, what the inside of a closed mesh looks like
+        let inside = draw(vec![
+            triangle([(-12.0, -12.0), (12.0, -12.0), (-12.0, 12.0)], 10.0, 0),
+            triangle([(12.0, -12.0), (12.0, 12.0), (-12.0, 12.0)], 10.0, 0),
+        ]);
+        assert!(inside.back_facing_area > inside.front_facing_area);
+        assert!(inside.camera_inside_geometry(screen_area));
+
+        // The same quad facing the camera, and a small back face beside a large front one
+        let outside = draw(vec![
+            triangle([(-12.0, -12.0), (-12.0, 12.0), (12.0, -12.0)], 10.0, 0),
+            triangle([(12.0, -12.0), (-12.0, 12.0), (12.0, 12.0)], 10.0, 0),
+        ]);
+        assert!(!outside.camera_inside_geometry(screen_area));
+        let mixed = draw(vec![
+            triangle([(-12.0, -12.0), (-12.0, 12.0), (12.0, -12.0)], 10.0, 0),
+            triangle([(4.0, 4.0), (8.0, 4.0), (4.0, 8.0)], 10.0, 0),
+        ]);
+        assert!(mixed.back_facing_area > 0.0);
+        assert!(!mixed.camera_inside_geometry(screen_area));
+    }
+
+    #[test]
+    fn top_left_pixel_centers_shift_coverage_by_half_a_pixel() {
+        // At depth 24 a view unit is a pixel, so this one pixel wide strip spans screen x 10.25 to 11.25
+        let (left, right) = (-21.75, -20.75);
+        let strip = model(vec![
+            triangle([(left, -12.0), (left, 12.0), (right, -12.0)], 24.0, 0),
+            triangle([(right, -12.0), (left, 12.0), (right, 12.0)], 24.0, 0),
+        ], &[RED]);
+        let covered_columns = |pixel_center: PixelCenter| {
+            let mut rasterizer = rasterizer();
+            rasterizer.pixel_center = pixel_center;
+            rasterizer.screen.clear(0, 0, 0, 255);
+            rasterizer.draw_instanced(&strip, &[IDENTITY], &camera(), LIGHT, None);
+            (0..WIDTH).filter(|&x| rasterizer.screen.get_pixel(x, 24) == RED).collect::<Vec<_>>()
+        };
+        // Sampled at 10.5 the strip covers column 10, sampled at the corner it's 11.0 that lands inside
+        assert_eq!(covered_columns(PixelCenter::Center), [10]);
+        assert_eq!(covered_columns(PixelCenter::TopLeft), [11]);
+    }
+}
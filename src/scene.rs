@@ -0,0 +1,181 @@
+use serde::Deserialize;
+
+use crate::camera::{Camera, PitchMode};
+use crate::error::RusterizerError;
+use crate::light::DirectionalLight;
+use crate::point3d::Point3D;
+use crate::screen::{Backdrop, BackdropFit};
+use crate::texture::Texture;
+use crate::transform::Transform;
+
+/// Scene setup read from a JSON file at startup. Every field is optional and falls back to the built-in scene, angles are in degrees
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    pub model: String,
+    // Diffuse texture for materials without a map_Kd
+    pub texture: String,
+    // Image drawn behind the scene instead of the black clear
+    pub backdrop: Option<String>,
+    pub object: TransformDesc,
+    pub camera: CameraDesc,
+    pub lights: Vec<LightDesc>,
+    pub settings: RenderSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TransformDesc {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub position: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CameraDesc {
+    pub fov: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub position: [f32; 3],
+    pub speed: f32,
+    pub mouse_sensitivity: f32,
+}
+
+/// Directional light, `direction` points from the scene toward the light
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct LightDesc {
+    pub direction: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub fog: bool,
+    pub grid: bool,
+    // World y of the ground grid plane
+    pub grid_height: f32,
+    pub ambient: f32,
+    pub exposure: f32,
+    pub bilinear: bool,
+    pub backdrop_fit: BackdropFit,
+    // Model turntable rate in degrees per second, negative turns the other way
+    pub spin: f32,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            model: String::from("socrates.obj"),
+            texture: String::from("socrates.png"),
+            backdrop: None,
+            object: TransformDesc::default(),
+            camera: CameraDesc::default(),
+            lights: vec![LightDesc { direction: [0.0, 0.0, -1.0] }],
+            settings: RenderSettings::default(),
+        }
+    }
+}
+
+impl Default for TransformDesc {
+    fn default() -> Self {
+        Self { yaw: 90.0, pitch: 180.0, position: [0.0, 55.0, 300.0] }
+    }
+}
+
+impl Default for CameraDesc {
+    fn default() -> Self {
+        Self { fov: 30.0, yaw: 0.0, pitch: 0.0, position: [0.0, 0.0, 0.0], speed: 1.0, mouse_sensitivity: 0.002 }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self { fog: false, grid: false, grid_height: 0.0, ambient: 0.0, exposure: 0.0, bilinear: false, backdrop_fit: BackdropFit::Cover, spin: 0.0 }
+    }
+}
+
+impl Scene {
+    pub fn load(path: &str) -> Result<Self, RusterizerError> {
+        let text = std::fs::read_to_string(path).map_err(|source| RusterizerError::Io { path: path.to_string(), source })?;
+        serde_json::from_str(&text).map_err(|e| RusterizerError::Scene { path: path.to_string(), message: e.to_string() })
+    }
+
+    /// The backdrop image, None without one or if it fails to load
+    pub fn backdrop(&self) -> Option<Backdrop> {
+        let path = self.backdrop.as_ref()?;
+        match Texture::load(path) {
+            Ok(texture) => Some(Backdrop { texture, fit: self.settings.backdrop_fit }),
+            Err(e) => {
+                eprintln!("Warning: failed to load backdrop {}: {}, using the clear color", path, e);
+                None
+            }
+        }
+    }
+
+    /// The first light, the renderer only shades with one
+    pub fn light(&self) -> DirectionalLight {
+        if self.lights.len() > 1 {
+            eprintln!("Warning: scene has {} lights, only the first is used", self.lights.len());
+        }
+        let [x, y, z] = self.lights.first().map_or([0.0, 0.0, -1.0], |light| light.direction);
+        DirectionalLight::from_direction(Point3D { x, y, z })
+    }
+}
+
+impl TransformDesc {
+    pub fn to_transform(&self) -> Transform {
+        let [x, y, z] = self.position;
+        Transform { yaw: self.yaw.to_radians(), pitch: self.pitch.to_radians(), posistion: Point3D { x, y, z } }
+    }
+}
+
+impl CameraDesc {
+    pub fn to_camera(&self) -> Camera {
+        let transform = TransformDesc { yaw: self.yaw, pitch: self.pitch, position: self.position }.to_transform();
+        Camera {
+            fov: self.fov.to_radians(),
+            camera_speed: self.speed,
+            mouse_sensitivity: self.mouse_sensitivity,
+            transform,
+            pitch_mode: PitchMode::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scene_file_fields_reach_the_constructed_objects() {
+        let path = std::env::temp_dir().join(format!("rusterizer-scene-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{
+            "model": "teapot.obj",
+            "texture": "teapot.png",
+            "object": { "yaw": 90, "pitch": 0, "position": [1, 2, 3] },
+            "camera": { "fov": 60, "position": [0, 5, -10], "speed": 2 },
+            "lights": [{ "direction": [0, 1, 0] }],
+            "settings": { "fog": true, "ambient": 0.25, "spin": 30 }
+        }"#).unwrap();
+        let broken = path.with_extension("broken.json");
+        std::fs::write(&broken, "{ \"model\": ").unwrap();
+        let (scene, error) = (Scene::load(path.to_str().unwrap()), Scene::load(broken.to_str().unwrap()));
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&broken).unwrap();
+        let scene = scene.unwrap();
+
+        assert_eq!((scene.model.as_str(), scene.texture.as_str(), scene.backdrop.as_deref()), ("teapot.obj", "teapot.png", None));
+        let object = scene.object.to_transform();
+        assert_eq!((object.yaw, object.pitch), (90.0f32.to_radians(), 0.0));
+        assert_eq!(object.posistion, Point3D { x: 1.0, y: 2.0, z: 3.0 });
+        // Fields the file leaves out keep the built-in scene's values
+        let camera = scene.camera.to_camera();
+        assert_eq!((camera.fov, camera.camera_speed, camera.mouse_sensitivity), (60.0f32.to_radians(), 2.0, CameraDesc::default().mouse_sensitivity));
+        assert_eq!(camera.transform.posistion, Point3D { x: 0.0, y: 5.0, z: -10.0 });
+        assert_eq!(scene.light().direction(), DirectionalLight::from_direction(Point3D { x: 0.0, y: 1.0, z: 0.0 }).direction());
+        assert_eq!(scene.settings, RenderSettings { fog: true, ambient: 0.25, spin: 30.0, ..RenderSettings::default() });
+
+        assert!(matches!(error, Err(RusterizerError::Scene { .. })));
+    }
+}
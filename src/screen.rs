@@ -1,16 +1,68 @@
 use bytemuck::cast_slice_mut;
 use std::fs::File;
 use std::io::{Write, BufWriter};
-use anyhow::Result;
+use anyhow::{Result, bail};
+use serde::Deserialize;
 use std::cell::UnsafeCell;
 
 use crate::rectangle::Rect;
+use crate::texture::Texture;
+
 pub struct ScreenSpace {
     pub rect: Rect,
     pub width: u32,
     pub height: u32,
     pub rgba: UnsafeCell<Vec<u8>>,
     pub depth: UnsafeCell<Vec<f32>>,
+    // Per-pixel stencil-like mask, nonzero marks a pixel. clear() leaves it alone so a layout is marked once and reused
+    pub mask: UnsafeCell<Vec<u8>>,
+}
+
+/// How a backdrop image is scaled to the screen
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackdropFit {
+    // Fill the screen exactly, ignoring the aspect ratio
+    Stretch,
+    // Fill the screen keeping the aspect ratio, cropping the overflow
+    #[default]
+    Cover,
+    // Show the whole image keeping the aspect ratio, bars in the clear color
+    Contain,
+}
+
+/// Static image drawn behind the scene in place of a solid clear color
+pub struct Backdrop {
+    pub texture: Texture,
+    pub fit: BackdropFit,
+}
+
+/// How rasterization is restricted by ScreenSpace::mask
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MaskMode {
+    #[default]
+    Off,
+    // Only write marked pixels, e.g. the scene seen through a portal
+    Inside,
+    // Only write unmarked pixels, e.g. keeping a HUD area untouched
+    Outside,
+}
+
+impl MaskMode {
+    #[inline(always)]
+    pub fn passes(self, mask: u8) -> bool {
+        match self {
+            MaskMode::Off => true,
+            MaskMode::Inside => mask != 0,
+            MaskMode::Outside => mask == 0,
+        }
+    }
+}
+
+// Widened before multiplying, a 65536 x 65536 screen has 2^32 pixels and 2^34 RGBA bytes
+#[inline(always)]
+fn pixel_count(width: u32, height: u32) -> usize {
+    width as usize * height as usize
 }
 
 unsafe impl Send for ScreenSpace {}
@@ -18,20 +70,27 @@ unsafe impl Sync for ScreenSpace {}
 
 impl ScreenSpace {
     pub fn new(width: u32, height: u32) -> Self {
-        let size = (width * height) as usize;
+        let size = pixel_count(width, height);
         Self {
             rect: Rect { min_x: 0, min_y: 0, max_x: 0, max_y: 0 },
             width,
             height,
             rgba: UnsafeCell::new(vec![0; size * 4]),
             depth: UnsafeCell::new(vec![f32::INFINITY; size]),
+            mask: UnsafeCell::new(vec![0; size]),
         }
     }
 
+    // Widened before multiplying, width * height * 4 overflows u32 past ~32K x 32K
+    #[inline(always)]
+    fn pixel_index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
     #[inline]
     pub fn unsafe_set_pixel(&self, x: u32, y: u32, r: u8, g: u8, b: u8, a: u8) {
         if x >= self.width || y >= self.height { return; }
-        let i = ((y * self.width + x) * 4) as usize;
+        let i = self.pixel_index(x, y) * 4;
         unsafe {
             let rgba = &mut *self.rgba.get();
             rgba[i] = r;
@@ -41,34 +100,178 @@ impl ScreenSpace {
         }
     }
 
+    /// Alpha blend a color over the current pixel, coverage 0 leaves it untouched and 1 overwrites it
+    #[inline]
+    pub fn unsafe_blend_pixel(&self, x: u32, y: u32, r: u8, g: u8, b: u8, coverage: f32) {
+        if x >= self.width || y >= self.height { return; }
+        let coverage = coverage.clamp(0.0, 1.0);
+        let i = self.pixel_index(x, y) * 4;
+        unsafe {
+            let rgba = &mut *self.rgba.get();
+            for (channel, src) in [r, g, b].into_iter().enumerate() {
+                let dst = rgba[i + channel] as f32;
+                rgba[i + channel] = (dst + (src as f32 - dst) * coverage).round() as u8;
+            }
+        }
+    }
+
     #[inline]
     pub fn unsafe_set_depth(&self, x: u32, y: u32, value: f32) {
         if x >= self.width || y >= self.height { return; }
-        let i = (y * self.width + x) as usize;
+        let i = self.pixel_index(x, y);
         unsafe {
             (&mut *self.depth.get())[i] = value;
         }
     }
 
+    /// Color at a pixel, transparent black out of bounds
+    #[inline]
+    pub fn get_pixel(&self, x: u32, y: u32) -> (u8, u8, u8, u8) {
+        if x >= self.width || y >= self.height {
+            return (0, 0, 0, 0);
+        }
+        let i = self.pixel_index(x, y) * 4;
+        let rgba = unsafe { &*self.rgba.get() };
+        (rgba[i], rgba[i + 1], rgba[i + 2], rgba[i + 3])
+    }
+
+    /// Stored depth at a pixel, this is linear view z (vertices carry 1/z but the buffer holds its reciprocal), infinity where nothing was drawn or out of bounds
     #[inline]
     pub fn get_depth(&self, x: u32, y: u32) -> f32 {
         if x >= self.width || y >= self.height {
             return f32::INFINITY;
         }
-        let i = (y * self.width + x) as usize;
+        let i = self.pixel_index(x, y);
         unsafe {
             (&*self.depth.get())[i]
         }
     }
 
+    /// Mask value at a pixel, 0 out of bounds
+    #[inline]
+    pub fn get_mask(&self, x: u32, y: u32) -> u8 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        let i = self.pixel_index(x, y);
+        unsafe {
+            (&*self.mask.get())[i]
+        }
+    }
+
+    /// Set the mask over a rectangle, clipped to the screen
+    pub fn fill_mask_rect(&self, rect: &Rect, value: u8) {
+        let mask = unsafe { &mut *self.mask.get() };
+        for row in self.rect_rows(rect) {
+            mask[row].fill(value);
+        }
+    }
+
+    pub fn clear_mask(&self, value: u8) {
+        let mask = unsafe { &mut *self.mask.get() };
+        mask.fill(value);
+    }
+
     pub fn clear(&self, r: u8, g: u8, b: u8, a: u8) {
+        self.clear_color(r, g, b, a);
+        self.clear_depth(f32::INFINITY);
+    }
+
+    /// Fill the color buffer only, depth is kept (e.g. from a depth prepass)
+    pub fn clear_color(&self, r: u8, g: u8, b: u8, a: u8) {
         let color = u32::from_le_bytes([r, g, b, a]);
         let rgba = unsafe { &mut *self.rgba.get() };
         let buf_as_u32: &mut [u32] = cast_slice_mut(rgba);
         buf_as_u32.fill(color);
+    }
+
+    /// Fill the depth buffer only, a finite value acts like a far wall (e.g. a sky drawn at that depth first)
+    pub fn clear_depth(&self, value: f32) {
+        let depth = unsafe { &mut *self.depth.get() };
+        depth.fill(value);
+    }
+
+    /// clear() restricted to a rectangle, clipped to the screen, for redrawing a dirty region over the previous frame
+    pub fn clear_rect(&self, rect: &Rect, r: u8, g: u8, b: u8, a: u8) {
+        self.clear_color_rect(rect, r, g, b, a);
+        self.clear_depth_rect(rect, f32::INFINITY);
+    }
 
+    /// clear_color() restricted to a rectangle, clipped to the screen
+    pub fn clear_color_rect(&self, rect: &Rect, r: u8, g: u8, b: u8, a: u8) {
+        let color = u32::from_le_bytes([r, g, b, a]);
+        let rgba = unsafe { &mut *self.rgba.get() };
+        let buf_as_u32: &mut [u32] = cast_slice_mut(rgba);
+        for row in self.rect_rows(rect) {
+            buf_as_u32[row].fill(color);
+        }
+    }
+
+    /// clear_depth() restricted to a rectangle, clipped to the screen
+    pub fn clear_depth_rect(&self, rect: &Rect, value: f32) {
         let depth = unsafe { &mut *self.depth.get() };
-        depth.fill(f32::INFINITY);
+        for row in self.rect_rows(rect) {
+            depth[row].fill(value);
+        }
+    }
+
+    // Pixel index range of each row of a rectangle clipped to the screen, empty when it's off screen
+    fn rect_rows(&self, rect: &Rect) -> impl Iterator<Item = std::ops::Range<usize>> + '_ {
+        let (min_x, max_x) = (rect.min_x, rect.max_x.min(self.width));
+        let rows = if min_x < max_x { rect.min_y..rect.max_y.min(self.height) } else { 0..0 };
+        rows.map(move |y| self.pixel_index(min_x, y)..self.pixel_index(max_x, y))
+    }
+
+    /// Clear depth and fill the color buffer with a backdrop, nearest sampled. Contain's bars get the given color
+    pub fn clear_to_backdrop(&self, backdrop: &Backdrop, r: u8, g: u8, b: u8, a: u8) {
+        self.clear(r, g, b, a);
+        let texture = &backdrop.texture;
+        let (width, height) = (self.width as f32, self.height as f32);
+        let (scale_x, scale_y) = match backdrop.fit {
+            BackdropFit::Stretch => (texture.width as f32 / width, texture.height as f32 / height),
+            fit => {
+                let x = texture.width as f32 / width;
+                let y = texture.height as f32 / height;
+                // Texels per pixel, the fitted image is centered on the screen
+                let scale = if fit == BackdropFit::Cover { x.min(y) } else { x.max(y) };
+                (scale, scale)
+            }
+        };
+        let rgba = unsafe { &mut *self.rgba.get() };
+        for y in 0..self.height {
+            let ty = ((y as f32 + 0.5 - height * 0.5) * scale_y + texture.height as f32 * 0.5).floor();
+            if ty < 0.0 || ty >= texture.height as f32 {
+                continue;
+            }
+            // Texture rows are stored bottom up, screen rows top down
+            let texture_row = (texture.height - 1 - ty as u32) as usize * texture.width as usize;
+            for x in 0..self.width {
+                let tx = ((x as f32 + 0.5 - width * 0.5) * scale_x + texture.width as f32 * 0.5).floor();
+                if tx < 0.0 || tx >= texture.width as f32 {
+                    continue;
+                }
+                let src = (texture_row + tx as usize) * 4;
+                let dst = self.pixel_index(x, y) * 4;
+                rgba[dst..dst + 4].copy_from_slice(&texture.rgba[src..src + 4]);
+            }
+        }
+    }
+
+    /// Copy another screen's colors and depths into this one with its top left corner at (offset_x, offset_y), clipped to this screen
+    pub fn copy_from(&self, src: &ScreenSpace, offset_x: u32, offset_y: u32) {
+        if offset_x >= self.width || offset_y >= self.height { return; }
+        let row_pixels = src.width.min(self.width - offset_x) as usize;
+        let rows = src.height.min(self.height - offset_y);
+        let dst = unsafe { &mut *self.rgba.get() };
+        let src_rgba = unsafe { &*src.rgba.get() };
+        let dst_depth = unsafe { &mut *self.depth.get() };
+        let src_depth = unsafe { &*src.depth.get() };
+        for y in 0..rows {
+            let src_start = src.pixel_index(0, y);
+            let dst_start = self.pixel_index(offset_x, offset_y + y);
+            dst[dst_start * 4..(dst_start + row_pixels) * 4].copy_from_slice(&src_rgba[src_start * 4..(src_start + row_pixels) * 4]);
+            dst_depth[dst_start..dst_start + row_pixels].copy_from_slice(&src_depth[src_start..src_start + row_pixels]);
+        }
     }
 
     pub fn write_bmp(&self, path: &str) -> Result<()> {
@@ -99,7 +302,7 @@ impl ScreenSpace {
         let padding = vec![0u8; (row_stride - width * 3) as usize];
         for y in (0..height).rev() {
             for x in 0..width {
-                let i = ((y * width + x) * 4) as usize;
+                let i = (y as usize * width as usize + x as usize) * 4;
                 let r = rgba[i];
                 let g = rgba[i + 1];
                 let b = rgba[i + 2];
@@ -109,4 +312,218 @@ impl ScreenSpace {
         }
         Ok(())
     }
+
+    /// Depth buffer as raw little-endian f32 view z, rows top to bottom with no header
+    pub fn write_depth_raw(&self, path: &str) -> Result<()> {
+        let depth = unsafe { &*self.depth.get() };
+        let mut file = BufWriter::new(File::create(path)?);
+        for value in depth {
+            file.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// 16-bit grayscale PNG of view depth mapped linearly from `near` (black) to `far` (white), further or empty pixels are white
+    pub fn write_depth_png16(&self, path: &str, near: f32, far: f32) -> Result<()> {
+        let depth = unsafe { &*self.depth.get() };
+        let levels: Vec<u16> = depth.iter().map(|&z| depth_to_u16(z, near, far)).collect();
+        let image = image::ImageBuffer::<image::Luma<u16>, Vec<u16>>::from_raw(self.width, self.height, levels)
+            .expect("depth buffer size matches the screen");
+        image.save(path)?;
+        Ok(())
+    }
+
+    pub fn write_png(&self, path: &str) -> Result<()> {
+        let rgba = unsafe { &*self.rgba.get() };
+        image::save_buffer(path, rgba, self.width, self.height, image::ColorType::Rgba8)?;
+        Ok(())
+    }
+
+    /// write_png with pixels near the key color made transparent, the screen itself is left untouched
+    pub fn write_png_keyed(&self, path: &str, key: &ColorKey) -> Result<()> {
+        let mut rgba = unsafe { &*self.rgba.get() }.clone();
+        key.apply(&mut rgba);
+        image::save_buffer(path, &rgba, self.width, self.height, image::ColorType::Rgba8)?;
+        Ok(())
+    }
+
+    /// Copy of the color buffer with every pixel nothing was drawn to (depth still infinite) fully transparent.
+    /// Unlike a color key this can't eat dark parts of the model that happen to match the background
+    pub fn background_cutout(&self) -> Vec<u8> {
+        let mut rgba = unsafe { &*self.rgba.get() }.clone();
+        let depth = unsafe { &*self.depth.get() };
+        for (pixel, &z) in rgba.chunks_exact_mut(4).zip(depth) {
+            if z == f32::INFINITY {
+                pixel[3] = 0;
+            }
+        }
+        rgba
+    }
+
+    /// write_png of background_cutout
+    pub fn write_png_cutout(&self, path: &str) -> Result<()> {
+        image::save_buffer(path, &self.background_cutout(), self.width, self.height, image::ColorType::Rgba8)?;
+        Ok(())
+    }
+}
+
+/// Chroma key for exports: pixels within `tolerance` of `color` (RGB distance in 0-255 units) become transparent,
+/// alpha then ramps back up over the next `softness` so antialiased edges blend instead of keeping a fringe
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorKey {
+    pub color: (u8, u8, u8),
+    pub tolerance: f32,
+    pub softness: f32,
+}
+
+impl ColorKey {
+    /// Scale the alpha of every RGBA pixel in `rgba` by how far it is from the key color
+    pub fn apply(&self, rgba: &mut [u8]) {
+        let key = [self.color.0, self.color.1, self.color.2];
+        for pixel in rgba.chunks_exact_mut(4) {
+            let distance = pixel[..3].iter().zip(key).map(|(&c, k)| (c as f32 - k as f32).powi(2)).sum::<f32>().sqrt();
+            let coverage = if self.softness > 0.0 {
+                ((distance - self.tolerance) / self.softness).clamp(0.0, 1.0)
+            } else if distance > self.tolerance {
+                1.0
+            } else {
+                0.0
+            };
+            pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+        }
+    }
+}
+
+// One write_depth_png16 level, infinity (nothing drawn) saturates to white
+fn depth_to_u16(z: f32, near: f32, far: f32) -> u16 {
+    (((z - near) / (far - near)).clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+}
+
+/// Read a file written by ScreenSpace::write_depth_raw back into view depths
+pub fn read_depth_raw(path: &str, width: u32, height: u32) -> Result<Vec<f32>> {
+    let bytes = std::fs::read(path)?;
+    let expected = width as usize * height as usize * 4;
+    if bytes.len() != expected {
+        bail!("{path} is {} bytes, expected {expected} for a {width}x{height} depth buffer", bytes.len());
+    }
+    Ok(bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_depth_round_trips_exactly() {
+        let screen = ScreenSpace::new(3, 2);
+        let values = [0.1, 1.0, 2.5, 1e-7, 12345.678, f32::INFINITY];
+        for (i, &value) in values.iter().enumerate() {
+            screen.unsafe_set_depth(i as u32 % 3, i as u32 / 3, value);
+        }
+        assert_eq!(screen.get_depth(1, 1), 12345.678);
+        assert_eq!(screen.get_depth(3, 0), f32::INFINITY);
+
+        let path = std::env::temp_dir().join(format!("rusterizer-depth-{}.f32", std::process::id()));
+        let path = path.to_str().unwrap();
+        screen.write_depth_raw(path).unwrap();
+        let read = read_depth_raw(path, 3, 2);
+        let wrong_size = read_depth_raw(path, 2, 2);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(read.unwrap(), values);
+        assert!(wrong_size.is_err());
+    }
+
+    #[test]
+    fn cutout_clears_only_undrawn_pixels() {
+        let screen = ScreenSpace::new(2, 2);
+        screen.clear(0, 0, 0, 255);
+        // A black pixel of the model is kept, only depth decides what's background
+        screen.unsafe_set_pixel(0, 0, 0, 0, 0, 255);
+        screen.unsafe_set_depth(0, 0, 5.0);
+        screen.unsafe_set_pixel(1, 1, 200, 40, 40, 255);
+        screen.unsafe_set_depth(1, 1, 7.0);
+        let alphas: Vec<u8> = screen.background_cutout().chunks_exact(4).map(|pixel| pixel[3]).collect();
+        assert_eq!(alphas, [255, 0, 0, 255]);
+        assert_eq!(screen.get_pixel(1, 0), (0, 0, 0, 255));
+    }
+
+    #[test]
+    fn copied_screens_bring_their_depth_for_the_cutout() {
+        // A side-by-side eye drawn on its right half only
+        let eye = ScreenSpace::new(2, 1);
+        eye.clear(0, 0, 0, 255);
+        eye.unsafe_set_pixel(1, 0, 90, 90, 90, 255);
+        eye.unsafe_set_depth(1, 0, 3.0);
+        let screen = ScreenSpace::new(4, 1);
+        screen.copy_from(&eye, 2, 0);
+        assert_eq!(screen.get_depth(3, 0), 3.0);
+        let alphas: Vec<u8> = screen.background_cutout().chunks_exact(4).map(|pixel| pixel[3]).collect();
+        assert_eq!(alphas, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn color_key_knocks_out_the_background_and_feathers_edges() {
+        let key = ColorKey { color: (0, 255, 0), tolerance: 10.0, softness: 100.0 };
+        // Background, a pixel half covering the green and the foreground object
+        let mut rgba = vec![0, 255, 0, 255, 40, 230, 0, 255, 200, 40, 40, 255];
+        key.apply(&mut rgba);
+        assert_eq!(rgba[3], 0);
+        assert!(rgba[7] > 0 && rgba[7] < 255, "edge alpha {}", rgba[7]);
+        assert_eq!(rgba[11], 255);
+    }
+
+    #[test]
+    fn png_levels_map_near_to_black_and_far_to_white() {
+        assert_eq!(depth_to_u16(1.0, 1.0, 3.0), 0);
+        assert_eq!(depth_to_u16(2.0, 1.0, 3.0), 32768);
+        assert_eq!(depth_to_u16(3.0, 1.0, 3.0), u16::MAX);
+        assert_eq!(depth_to_u16(f32::INFINITY, 1.0, 3.0), u16::MAX);
+        assert_eq!(depth_to_u16(0.5, 1.0, 3.0), 0);
+    }
+
+    #[test]
+    fn rect_clears_touch_only_the_rect_clipped_to_the_screen() {
+        let screen = ScreenSpace::new(6, 4);
+        // Hangs off the right edge, so the clip is exercised too
+        let rect = Rect { min_x: 3, min_y: 1, max_x: 9, max_y: 3 };
+        let inside = |x, y| (3..6).contains(&x) && (1..3).contains(&y);
+        let check = |color_inside: (u8, u8, u8, u8), depth_inside: f32| {
+            for y in 0..4 {
+                for x in 0..6 {
+                    let (color, depth) = if inside(x, y) { (color_inside, depth_inside) } else { ((10, 20, 30, 255), 2.0) };
+                    assert_eq!(screen.get_pixel(x, y), color, "({}, {})", x, y);
+                    assert_eq!(screen.get_depth(x, y), depth, "({}, {})", x, y);
+                }
+            }
+        };
+        let reset = || {
+            screen.clear_color(10, 20, 30, 255);
+            screen.clear_depth(2.0);
+        };
+
+        reset();
+        screen.clear_color_rect(&rect, 200, 0, 0, 255);
+        check((200, 0, 0, 255), 2.0);
+        reset();
+        screen.clear_depth_rect(&rect, 7.5);
+        check((10, 20, 30, 255), 7.5);
+        reset();
+        screen.clear_rect(&rect, 0, 0, 200, 128);
+        check((0, 0, 200, 128), f32::INFINITY);
+        // Entirely off screen does nothing
+        reset();
+        screen.clear_rect(&Rect { min_x: 6, min_y: 0, max_x: 9, max_y: 4 }, 0, 0, 0, 0);
+        check((10, 20, 30, 255), 2.0);
+    }
+
+    #[test]
+    fn sizes_past_u32_are_computed_without_overflow() {
+        assert_eq!(pixel_count(65536, 65536) * 4, 1 << 34);
+        assert_eq!(pixel_count(u32::MAX, 2), 2 * u32::MAX as usize);
+        // A screen too wide for 16-bit indices still addresses its last pixel
+        let screen = ScreenSpace::new(70000, 2);
+        screen.unsafe_set_pixel(69999, 1, 1, 2, 3, 4);
+        assert_eq!(screen.get_pixel(69999, 1), (1, 2, 3, 4));
+        assert_eq!(screen.get_pixel(69999, 0), (0, 0, 0, 0));
+    }
 }
@@ -0,0 +1,60 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Build a file name like `prefix_20250101_120000_123.ext` from a time since the UNIX epoch (UTC, millisecond resolution)
+pub fn timestamped_filename(prefix: &str, extension: &str, since_epoch: Duration) -> String {
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let seconds_of_day = secs % 86_400;
+    format!(
+        "{prefix}_{year:04}{month:02}{day:02}_{:02}{:02}{:02}_{:03}.{extension}",
+        seconds_of_day / 3600,
+        (seconds_of_day / 60) % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis(),
+    )
+}
+
+/// Timestamped file name for the current time, with a counter appended if a file of that name already exists
+pub fn next_screenshot_filename(prefix: &str, extension: &str) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let name = timestamped_filename(prefix, extension, now);
+    if !Path::new(&name).exists() {
+        return name;
+    }
+    let stem = name.trim_end_matches(&format!(".{extension}")).to_string();
+    (1..)
+        .map(|i| format!("{stem}_{i}.{extension}"))
+        .find(|candidate| !Path::new(candidate).exists())
+        .unwrap()
+}
+
+// Days since 1970-01-01 to (year, month, day), Howard Hinnant's civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn close_timestamps_get_distinct_names() {
+        let first = Duration::from_millis(1_735_732_800_000);
+        let second = first + Duration::from_millis(1);
+        assert_eq!(timestamped_filename("shot", "png", first), "shot_20250101_120000_000.png");
+        assert_eq!(timestamped_filename("shot", "png", second), "shot_20250101_120000_001.png");
+        // Leap day and the last millisecond of a day
+        assert_eq!(timestamped_filename("s", "bmp", Duration::from_millis(951_782_399_999)), "s_20000228_235959_999.bmp");
+        assert_eq!(timestamped_filename("s", "bmp", Duration::from_secs(951_782_400)), "s_20000229_000000_000.bmp");
+    }
+}
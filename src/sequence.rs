@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::camera::Camera;
+use crate::point3d::Point3D;
+use crate::render::{Model, Rasterizer};
+use crate::screen::Backdrop;
+use crate::transform::Transform;
+
+/// Fixed-rate clock for offline rendering, a frame's time depends only on its index so the output doesn't depend on how fast frames render
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sequence {
+    pub fps: f32,
+    pub frame_count: u32,
+}
+
+impl Sequence {
+    pub fn new(duration_secs: f32, fps: f32) -> Self {
+        Self { fps, frame_count: (duration_secs * fps).round().max(0.0) as u32 }
+    }
+
+    /// Seconds since the start of the sequence at frame `index`
+    pub fn frame_time(&self, index: u32) -> f32 {
+        index as f32 / self.fps
+    }
+
+    /// Length of the whole sequence, frame_time of the frame after the last
+    pub fn duration(&self) -> f32 {
+        self.frame_time(self.frame_count)
+    }
+}
+
+/// frame_00001.png, frame_00002.png, ... numbered from 1 with fixed width so ffmpeg's `-i frame_%05d.png` picks them up in order
+pub fn frame_filename(dir: &Path, index: u32) -> PathBuf {
+    dir.join(format!("frame_{:05}.png", index + 1))
+}
+
+/// Render `sequence` to numbered PNGs in `dir`. `pose` sets the model up for each frame's time and returns the object transform to draw it at
+#[allow(clippy::too_many_arguments)]
+pub fn render_frames(
+    dir: &Path,
+    sequence: &Sequence,
+    rasterizer: &Rasterizer,
+    model: &mut Model,
+    cam: &Camera,
+    light: Point3D,
+    backdrop: Option<&Backdrop>,
+    mut pose: impl FnMut(f32, &mut Model) -> Transform,
+) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for index in 0..sequence.frame_count {
+        let transform = pose(sequence.frame_time(index), model);
+        match backdrop {
+            Some(backdrop) => rasterizer.screen.clear_to_backdrop(backdrop, 0, 0, 0, 255),
+            None => rasterizer.screen.clear(0, 0, 0, 255),
+        }
+        rasterizer.draw_instanced(model, &[transform], cam, light, None);
+        rasterizer.flush_blended();
+        rasterizer.screen.write_png(&frame_filename(dir, index).to_string_lossy())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::PitchMode;
+    use crate::geometry::subdivide_count;
+    use crate::material::Material;
+    use crate::obj::Vertex;
+    use crate::point2d::Point2D;
+    use crate::texture::Texture;
+    use crate::triangle::Triangle3D;
+
+    #[test]
+    fn three_frame_sequence_writes_three_numbered_frames_that_advance() {
+        let dir = std::env::temp_dir().join(format!("rusterizer-sequence-{}", std::process::id()));
+        let rasterizer = Rasterizer::new(32, 24, subdivide_count(32, 24, 2));
+        let vertex = |x: f32, y: f32| Vertex {
+            position: Point3D { x, y, z: 0.0 },
+            texcoord: Point2D { x: 0.0, y: 0.0 },
+            normal: Point3D { x: 0.0, y: 0.0, z: -1.0 },
+            alpha: 1.0,
+        };
+        let mut model = Model::new(
+            vec![Triangle3D::from_vertices(vertex(-2.0, -2.0), vertex(-2.0, 2.0), vertex(2.0, -2.0), 0)],
+            vec![Material::default()],
+            vec![Texture::solid(255, 255, 255, 255).into()],
+        );
+        let cam = Camera {
+            fov: 90.0f32.to_radians(),
+            camera_speed: 1.0,
+            mouse_sensitivity: 0.0,
+            transform: Transform { yaw: 0.0, pitch: 0.0, posistion: Point3D { x: 0.0, y: 0.0, z: 0.0 } },
+            pitch_mode: PitchMode::default(),
+        };
+        // The triangle slides right by 4 units a second, 1 unit per frame at 4 fps
+        let sequence = Sequence::new(0.75, 4.0);
+        let mut times = Vec::new();
+        let result = render_frames(&dir, &sequence, &rasterizer, &mut model, &cam, Point3D { x: 0.0, y: 0.0, z: -1.0 }, None, |time, _| {
+            times.push(time);
+            Transform { yaw: 0.0, pitch: 0.0, posistion: Point3D { x: 4.0 * time, y: 0.0, z: 10.0 } }
+        });
+        let frames: Vec<_> = (0..4).map(|index| image::open(frame_filename(&dir, index)).map(|image| image.to_rgba8())).collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+
+        assert_eq!(sequence.frame_count, 3);
+        assert_eq!(times, [0.0, 0.25, 0.5]);
+        assert_eq!(frame_filename(&dir, 2).file_name().unwrap(), "frame_00003.png");
+        assert!(frames[3].is_err());
+        // Left edge of the lit pixels in each frame's middle row, moving right as the animation advances
+        let left_edges: Vec<u32> = frames[..3].iter().map(|frame| {
+            let frame = frame.as_ref().unwrap();
+            (0..32).find(|&x| frame.get_pixel(x, 12).0[0] > 0).unwrap()
+        }).collect();
+        assert!(left_edges[0] < left_edges[1] && left_edges[1] < left_edges[2], "{left_edges:?}");
+    }
+}
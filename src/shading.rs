@@ -0,0 +1,183 @@
+use crate::point3d::{self, Point3D};
+
+// Tonemap passes linear values below the knee through untouched and rolls off everything above it smoothly
+const TONEMAP_KNEE: f32 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fog {
+    pub color: (u8, u8, u8),
+    // View depths where the fog starts and becomes fully opaque
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Fog {
+    /// 0 at or before the fog start, 1 at or past the fog end. A fog with no length between them is a step at the end
+    #[inline(always)]
+    pub fn factor(&self, depth: f32) -> f32 {
+        if self.end <= self.start {
+            return if depth >= self.end { 1.0 } else { 0.0 };
+        }
+        ((depth - self.start) / (self.end - self.start)).clamp(0.0, 1.0)
+    }
+
+    /// Blend a linear color toward the linearized fog color
+    #[inline(always)]
+    pub fn apply(&self, color: Point3D, depth: f32) -> Point3D {
+        let fog_color = Point3D {
+            x: srgb_to_linear(self.color.0),
+            y: srgb_to_linear(self.color.1),
+            z: srgb_to_linear(self.color.2),
+        };
+        point3d::lerp(color, fog_color, self.factor(depth))
+    }
+}
+
+/// Depth past which nothing is drawn, with a fade over [fade_start, distance] so geometry crossing it dissolves instead of popping
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FarPlane {
+    pub distance: f32,
+    pub fade_start: f32,
+    // What the fade blends into when there's no scene fog, should match the screen clear color
+    pub background: (u8, u8, u8),
+}
+
+impl FarPlane {
+    /// The fog to shade with: the scene fog pulled in to end by the far plane, or a fade to the background without one
+    pub fn fade_fog(&self, fog: Option<&Fog>) -> Fog {
+        match fog {
+            Some(fog) => Fog { end: fog.end.min(self.distance), start: fog.start.min(self.distance), ..*fog },
+            None => Fog { color: self.background, start: self.fade_start, end: self.distance },
+        }
+    }
+}
+
+#[inline(always)]
+pub fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+#[inline(always)]
+pub fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let s = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (s * 255.0).round() as u8
+}
+
+#[inline(always)]
+pub fn tonemap(c: f32) -> f32 {
+    if c <= TONEMAP_KNEE {
+        return c;
+    }
+    let range = 1.0 - TONEMAP_KNEE;
+    TONEMAP_KNEE + range * (1.0 - (-(c - TONEMAP_KNEE) / range).exp())
+}
+
+/// Grayscale for the depth view, white at `near` fading linearly to black at `far`. Only 256 levels cover the range,
+/// so a tight range around the geometry of interest bands far less than one spanning the whole scene
+pub fn depth_to_u8(depth: f32, near: f32, far: f32) -> u8 {
+    if depth <= near {
+        return 255
+    }
+    let t = (depth - near) / (far - near);
+    (255.0 * (1.0 - t)).round().clamp(0.0, 255.0) as u8
+}
+
+/// Add a linear emissive texel to a shaded color, emission is neither lit nor fogged
+#[inline(always)]
+pub fn add_emissive((r, g, b, a): (u8, u8, u8, u8), (er, eg, eb, _): (f32, f32, f32, f32)) -> (u8, u8, u8, u8) {
+    let add = |c: u8, e: f32| linear_to_srgb(srgb_to_linear(c) + e);
+    (add(r, er), add(g, eg), add(b, eb), a)
+}
+
+/// Half-Lambert diffuse plus a per-channel ambient term, the sum is clamped to 1 so ambient can't overexpose lit sides
+pub fn shade_pixel(texel: (u8, u8, u8, u8), normal: Point3D, light: Point3D, ambient: Point3D, depth: f32, fog: Option<&Fog>) -> (u8, u8, u8, u8) {
+    let (r, g, b, a) = texel;
+    let normalized_normal = point3d::normalize(normal); //unit vector
+    let normalized_light = point3d::normalize(light);
+    let diffuse = (point3d::dot3(normalized_normal, normalized_light) + 1.0) * 0.5;
+    let intensity = Point3D {
+        x: (diffuse + ambient.x).min(1.0),
+        y: (diffuse + ambient.y).min(1.0),
+        z: (diffuse + ambient.z).min(1.0),
+    };
+    // Unfogged pixels take the byte path with or without fog enabled, so turning fog on (or a far plane fade) doesn't shift near colors
+    match fog.filter(|fog| fog.factor(depth) > 0.0) {
+        None => (((r as f32) * intensity.x) as u8, ((g as f32) * intensity.y) as u8, ((b as f32) * intensity.z) as u8, a),
+        Some(fog) => {
+            // Fog has to blend in linear light before tonemapping, blending sRGB bytes gives a muddy fade
+            let lit = Point3D {
+                x: srgb_to_linear(r) * intensity.x,
+                y: srgb_to_linear(g) * intensity.y,
+                z: srgb_to_linear(b) * intensity.z,
+            };
+            let fogged = fog.apply(lit, depth);
+            (linear_to_srgb(tonemap(fogged.x)), linear_to_srgb(tonemap(fogged.y)), linear_to_srgb(tonemap(fogged.z)), a)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fog_leaves_pixels_before_its_start_unchanged() {
+        let fog = Fog { color: (128, 128, 128), start: 100.0, end: 200.0 };
+        let normal = Point3D { x: 0.0, y: 0.0, z: -1.0 };
+        let light = Point3D { x: 0.3, y: 0.5, z: -1.0 };
+        let ambient = Point3D { x: 0.1, y: 0.1, z: 0.1 };
+        for texel in [(200, 120, 40, 255), (255, 255, 255, 255), (10, 90, 250, 128)] {
+            assert_eq!(shade_pixel(texel, normal, light, ambient, 50.0, Some(&fog)), shade_pixel(texel, normal, light, ambient, 50.0, None));
+        }
+    }
+
+    #[test]
+    fn fog_reaches_its_color_at_the_end() {
+        let fog = Fog { color: (128, 64, 32), start: 100.0, end: 200.0 };
+        let (r, g, b, _) = shade_pixel((255, 255, 255, 255), Point3D { x: 0.0, y: 0.0, z: -1.0 }, Point3D { x: 0.0, y: 0.0, z: -1.0 }, Point3D { x: 0.0, y: 0.0, z: 0.0 }, 250.0, Some(&fog));
+        assert_eq!((r, g, b), (128, 64, 32));
+    }
+
+    #[test]
+    fn fog_blends_before_the_tonemap() {
+        // Fully lit white halfway into a dark fog
+        let fog = Fog { color: (100, 100, 100), start: 100.0, end: 200.0 };
+        let up = Point3D { x: 0.0, y: 0.0, z: -1.0 };
+        let (r, _, _, _) = shade_pixel((255, 255, 255, 255), up, up, Point3D { x: 0.0, y: 0.0, z: 0.0 }, 150.0, Some(&fog));
+        let fog_linear = srgb_to_linear(100);
+        let pre_tonemap = linear_to_srgb(tonemap(0.5 * (1.0 + fog_linear)));
+        let post_tonemap = linear_to_srgb(0.5 * (tonemap(1.0) + fog_linear));
+        assert_ne!(pre_tonemap, post_tonemap);
+        assert_eq!(r, pre_tonemap);
+    }
+
+    #[test]
+    fn ambient_is_all_that_lights_a_fully_back_facing_fragment() {
+        let light = Point3D { x: 0.0, y: 0.0, z: -1.0 };
+        let away = Point3D { x: 0.0, y: 0.0, z: 1.0 };
+        let texel = (200, 100, 50, 255);
+        assert_eq!(shade_pixel(texel, away, light, Point3D { x: 0.0, y: 0.0, z: 0.0 }, 10.0, None), (0, 0, 0, 255));
+        assert_eq!(shade_pixel(texel, away, light, Point3D { x: 0.2, y: 0.2, z: 0.2 }, 10.0, None), (40, 20, 10, 255));
+    }
+
+    #[test]
+    fn far_fade_reaches_the_background_at_the_far_plane() {
+        let far = FarPlane { distance: 100.0, fade_start: 80.0, background: (10, 20, 30) };
+        let up = Point3D { x: 0.0, y: 0.0, z: -1.0 };
+        let ambient = Point3D { x: 0.0, y: 0.0, z: 0.0 };
+        let texel = (200, 150, 100, 255);
+        let fade = far.fade_fog(None);
+        assert_eq!(shade_pixel(texel, up, up, ambient, 80.0, Some(&fade)), shade_pixel(texel, up, up, ambient, 80.0, None));
+        assert_eq!(shade_pixel(texel, up, up, ambient, 100.0, Some(&fade)), (10, 20, 30, 255));
+
+        // Scene fog starting beyond the far plane is pulled in to a zero-length step there instead of dividing by zero
+        let fog = Fog { color: (90, 90, 90), start: 150.0, end: 200.0 };
+        let pulled_in = far.fade_fog(Some(&fog));
+        assert_eq!((pulled_in.start, pulled_in.end), (100.0, 100.0));
+        assert_eq!(pulled_in.factor(99.0), 0.0);
+        assert_eq!(pulled_in.factor(100.0), 1.0);
+        assert_eq!(shade_pixel(texel, up, up, ambient, 100.0, Some(&pulled_in)), (90, 90, 90, 255));
+    }
+}
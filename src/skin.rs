@@ -0,0 +1,194 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use anyhow::{Result, bail};
+use rayon::prelude::*;
+
+use crate::obj::{ObjMesh, UvGenMode, fan_triangulate_faces};
+use crate::point3d::{self, Point3D};
+use crate::transform::Transform;
+use crate::triangle::Triangle3D;
+
+// Bones that can move one vertex, the usual limit for linear blend skinning
+pub const MAX_INFLUENCES: usize = 4;
+
+/// The bones moving one vertex and how much each contributes, unused slots have zero weight and weights sum to 1
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BoneInfluence {
+    pub bones: [u16; MAX_INFLUENCES],
+    pub weights: [f32; MAX_INFLUENCES],
+}
+
+/// A bone's current pose relative to its bind pose, as an affine map of object-space points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoneMatrix {
+    // Images of the object-space axes
+    pub x: Point3D,
+    pub y: Point3D,
+    pub z: Point3D,
+    pub translation: Point3D,
+}
+
+impl BoneMatrix {
+    pub const IDENTITY: Self = Self {
+        x: Point3D { x: 1.0, y: 0.0, z: 0.0 },
+        y: Point3D { x: 0.0, y: 1.0, z: 0.0 },
+        z: Point3D { x: 0.0, y: 0.0, z: 1.0 },
+        translation: Point3D { x: 0.0, y: 0.0, z: 0.0 },
+    };
+
+    pub fn from_transform(transform: &Transform) -> Self {
+        let (x, y, z) = transform.get_basis_vectors();
+        Self { x, y, z, translation: transform.posistion }
+    }
+
+    #[inline(always)]
+    pub fn apply_point(&self, p: Point3D) -> Point3D {
+        self.apply_direction(p) + self.translation
+    }
+
+    #[inline(always)]
+    pub fn apply_direction(&self, d: Point3D) -> Point3D {
+        self.x * d.x + self.y * d.y + self.z * d.z
+    }
+}
+
+impl BoneInfluence {
+    /// Weighted sum of the point as moved by each bone. Bone indices past the end of `bones` stay in the bind pose
+    #[inline(always)]
+    pub fn skin_point(&self, bones: &[BoneMatrix], p: Point3D) -> Point3D {
+        self.blend(bones, |bone| bone.apply_point(p))
+    }
+
+    /// skin_point for normals, renormalized since the blend of unit vectors is shorter than unit
+    #[inline(always)]
+    pub fn skin_normal(&self, bones: &[BoneMatrix], n: Point3D) -> Point3D {
+        point3d::normalize(self.blend(bones, |bone| bone.apply_direction(n)))
+    }
+
+    fn blend(&self, bones: &[BoneMatrix], apply: impl Fn(&BoneMatrix) -> Point3D) -> Point3D {
+        let mut sum = Point3D { x: 0.0, y: 0.0, z: 0.0 };
+        for (&bone, &weight) in self.bones.iter().zip(&self.weights) {
+            if weight != 0.0 {
+                sum += apply(bones.get(bone as usize).unwrap_or(&BoneMatrix::IDENTITY)) * weight;
+            }
+        }
+        sum
+    }
+}
+
+/// Read bone weights from a sidecar file, OBJ has no place for them. One line per OBJ position in file order holding
+/// `bone weight` pairs, e.g. `0 0.75 3 0.25`; blank lines and `#` comments are skipped. Vertices with more than
+/// MAX_INFLUENCES pairs keep the heaviest, weights are normalized and a line with no weight leaves the vertex on bone 0
+pub fn load_weights(path: &str, vertex_count: usize) -> Result<Vec<BoneInfluence>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut influences = Vec::with_capacity(vertex_count);
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if !tokens.len().is_multiple_of(2) {
+            bail!("{path}: bone weight line {} has a bone without a weight", influences.len() + 1);
+        }
+        let mut pairs: Vec<(u16, f32)> = Vec::with_capacity(tokens.len() / 2);
+        for pair in tokens.chunks_exact(2) {
+            let weight: f32 = pair[1].parse()?;
+            if !weight.is_finite() || weight < 0.0 {
+                bail!("{path}: bone weight {weight} on line {} isn't a finite non-negative number", influences.len() + 1);
+            }
+            pairs.push((pair[0].parse()?, weight));
+        }
+        pairs.sort_by(|a, b| b.1.total_cmp(&a.1));
+        pairs.truncate(MAX_INFLUENCES);
+        let total: f32 = pairs.iter().map(|&(_, weight)| weight).sum();
+        let mut influence = BoneInfluence::default();
+        if total > 0.0 {
+            for (slot, (bone, weight)) in pairs.into_iter().enumerate() {
+                influence.bones[slot] = bone;
+                influence.weights[slot] = weight / total;
+            }
+        } else {
+            influence.weights[0] = 1.0;
+        }
+        influences.push(influence);
+    }
+    if influences.len() != vertex_count {
+        bail!("{path} has bone weights for {} vertices, the mesh has {vertex_count}", influences.len());
+    }
+    Ok(influences)
+}
+
+/// A mesh's bind-pose triangles with the bone influences of each corner, posed with linear blend skinning
+pub struct Skin {
+    pub base: Vec<Triangle3D>,
+    // One influence per triangle corner, indexed like base
+    pub influences: Vec<[BoneInfluence; 3]>,
+}
+
+impl Skin {
+    /// Triangulate the mesh in its bind pose, `weights` has one entry per OBJ position.
+    /// Triangles are grouped by material like Morph::new, so the base can be used as the model's triangles directly
+    pub fn new(mesh: &ObjMesh, weights: &[BoneInfluence], flip_v: bool, uv_gen: UvGenMode) -> Result<Self> {
+        if weights.len() != mesh.positions.len() {
+            bail!("{} bone weights for a mesh with {} vertices", weights.len(), mesh.positions.len());
+        }
+        let triangles = fan_triangulate_faces(&mesh.faces, &mesh.positions, &mesh.alphas, &mesh.texcoords, &mesh.normals, flip_v, uv_gen);
+        // Same fan order as fan_triangulate_faces, so corners pair up with the triangles
+        let corners: Vec<[BoneInfluence; 3]> = mesh.faces.iter()
+            .filter(|face| face.v_indices.len() >= 3)
+            .flat_map(|face| (1..face.v_indices.len() - 1).map(move |i| {
+                [weights[face.v_indices[0]], weights[face.v_indices[i]], weights[face.v_indices[i + 1]]]
+            }))
+            .collect();
+        let mut order: Vec<usize> = (0..triangles.len()).collect();
+        order.sort_by_key(|&i| triangles[i].material);
+        Ok(Self {
+            base: order.iter().map(|&i| triangles[i]).collect(),
+            influences: order.iter().map(|&i| corners[i]).collect(),
+        })
+    }
+
+    /// Pose positions and normals with `bones`, written over `out` which must match the base's triangle order.
+    /// Runs before the transform stage, which then projects the posed triangles like any other
+    pub fn skin_into(&self, bones: &[BoneMatrix], out: &mut [Triangle3D]) {
+        out.par_iter_mut().zip(self.base.par_iter()).zip(self.influences.par_iter()).for_each(|((dst, bind), [ia, ib, ic])| {
+            let (a, b, c) = (ia.skin_point(bones, bind.a), ib.skin_point(bones, bind.b), ic.skin_point(bones, bind.c));
+            *dst = Triangle3D {
+                a,
+                b,
+                c,
+                na: ia.skin_normal(bones, bind.na),
+                nb: ib.skin_normal(bones, bind.nb),
+                nc: ic.skin_normal(bones, bind.nc),
+                wa: a,
+                wb: b,
+                wc: c,
+                ..*bind
+            };
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn even_two_bone_weights_land_halfway_between_the_poses() {
+        let shifted = BoneMatrix { translation: Point3D { x: 2.0, y: 0.0, z: 0.0 }, ..BoneMatrix::IDENTITY };
+        // A quarter turn about y, taking x to -z and z to x
+        let turned = BoneMatrix::from_transform(&Transform {
+            yaw: -std::f32::consts::FRAC_PI_2,
+            pitch: 0.0,
+            posistion: Point3D { x: 0.0, y: 4.0, z: 0.0 },
+        });
+        let influence = BoneInfluence { bones: [0, 1, 0, 0], weights: [0.5, 0.5, 0.0, 0.0] };
+        let p = Point3D { x: 1.0, y: 1.0, z: 0.0 };
+        let skinned = influence.skin_point(&[shifted, turned], p);
+        // (3, 1, 0) and (0, 5, -1) averaged
+        let d = skinned - Point3D { x: 1.5, y: 3.0, z: -0.5 };
+        assert!(point3d::dot3(d, d) < 1e-10, "{:?}", skinned);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::camera::Camera;
+use crate::geometry::subdivide_count;
+use crate::point3d::Point3D;
+use crate::render::{DrawStats, Model, Rasterizer};
+use crate::screen::ScreenSpace;
+use crate::shading::Fog;
+use crate::transform::Transform;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    #[default]
+    Off,
+    SideBySide,
+    // Red/cyan, for 3D glasses
+    Anaglyph,
+}
+
+impl StereoMode {
+    pub fn next(self) -> Self {
+        match self {
+            StereoMode::Off => StereoMode::SideBySide,
+            StereoMode::SideBySide => StereoMode::Anaglyph,
+            StereoMode::Anaglyph => StereoMode::Off,
+        }
+    }
+}
+
+/// Two rasterizers rendering the scene from eye positions offset along the camera's right axis
+pub struct StereoRig {
+    pub left: Rasterizer,
+    pub right: Rasterizer,
+    // Distance between the eyes in world units
+    pub eye_separation: f32,
+}
+
+impl StereoRig {
+    /// Each eye gets half of `width` for side-by-side output, the vertical fov is shared so each eye sees a narrower horizontal fov
+    pub fn new(width: u32, height: u32, tile_count: usize, eye_separation: f32) -> Self {
+        Self::with_eye_size(width / 2, height, tile_count, eye_separation)
+    }
+
+    /// Eyes at an explicit resolution, anaglyph output needs each eye at the full screen size
+    pub fn with_eye_size(eye_width: u32, height: u32, tile_count: usize, eye_separation: f32) -> Self {
+        Self {
+            left: Rasterizer::new(eye_width, height, subdivide_count(eye_width, height, tile_count)),
+            right: Rasterizer::new(eye_width, height, subdivide_count(eye_width, height, tile_count)),
+            eye_separation,
+        }
+    }
+
+    /// Copies of the camera shifted half the eye separation to the left and right
+    pub fn eye_cameras(&self, cam: &Camera) -> (Camera, Camera) {
+        let (right_axis, _, _) = cam.transform.get_basis_vectors();
+        let offset = right_axis * (self.eye_separation * 0.5);
+        let mut left = *cam;
+        let mut right = *cam;
+        left.transform.posistion -= offset;
+        right.transform.posistion += offset;
+        (left, right)
+    }
+
+    pub fn clear(&self, r: u8, g: u8, b: u8, a: u8) {
+        self.left.screen.clear(r, g, b, a);
+        self.right.screen.clear(r, g, b, a);
+    }
+
+    /// Draw into both eyes, the returned times are the sum of the two passes and the tile times are left eye then right
+    pub fn draw_instanced(&self, model: &Model, instances: &[Transform], cam: &Camera, light: Point3D, fog: Option<&Fog>) -> DrawStats {
+        let (left_cam, right_cam) = self.eye_cameras(cam);
+        let left = self.left.draw_instanced(model, instances, &left_cam, light, fog);
+        let right = self.right.draw_instanced(model, instances, &right_cam, light, fog);
+        DrawStats {
+            transform_time: left.transform_time + right.transform_time,
+            triangle_time: left.triangle_time + right.triangle_time,
+            tile_times: left.tile_times.into_iter().chain(right.tile_times).collect(),
+            // Both eyes see nearly the same thing, so one eye's coverage stands for the pair
+            front_facing_area: left.front_facing_area,
+            back_facing_area: left.back_facing_area,
+        }
+    }
+
+    /// Rasterizer::flush_blended for both eyes, tile times are left eye then right like draw_instanced's
+    pub fn flush_blended(&self) -> DrawStats {
+        let left = self.left.flush_blended();
+        let right = self.right.flush_blended();
+        DrawStats {
+            triangle_time: left.triangle_time + right.triangle_time,
+            tile_times: left.tile_times.into_iter().chain(right.tile_times).collect(),
+            ..DrawStats::default()
+        }
+    }
+
+    pub fn draw_lines(&self, lines: &[(Point3D, Point3D)], cam: &Camera, color: (u8, u8, u8), bias: f32) {
+        let (left_cam, right_cam) = self.eye_cameras(cam);
+        self.left.draw_lines(lines, &left_cam, color, bias);
+        self.right.draw_lines(lines, &right_cam, color, bias);
+    }
+
+    /// Left eye into the left half of `target`, right eye into the right half
+    pub fn composite_side_by_side(&self, target: &ScreenSpace) {
+        target.copy_from(&self.left.screen, 0, 0);
+        target.copy_from(&self.right.screen, target.width - self.right.screen.width, 0);
+    }
+
+    pub fn composite_anaglyph(&self, target: &ScreenSpace) {
+        anaglyph(&self.left.screen, &self.right.screen, target);
+    }
+}
+
+/// Red from the left eye, green and blue from the right, over the area all three screens share.
+/// Depth is the nearer of the two eyes, so a pixel counts as drawn if either eye drew it
+pub fn anaglyph(left: &ScreenSpace, right: &ScreenSpace, target: &ScreenSpace) {
+    let width = target.width.min(left.width).min(right.width) as usize;
+    let height = target.height.min(left.height).min(right.height) as usize;
+    let left_rgba = unsafe { &*left.rgba.get() };
+    let right_rgba = unsafe { &*right.rgba.get() };
+    let target_rgba = unsafe { &mut *target.rgba.get() };
+    let (left_depth, right_depth) = unsafe { (&*left.depth.get(), &*right.depth.get()) };
+    let target_depth = unsafe { &mut *target.depth.get() };
+    for y in 0..height {
+        for x in 0..width {
+            let l = y * left.width as usize + x;
+            let r = y * right.width as usize + x;
+            let t = y * target.width as usize + x;
+            target_rgba[t * 4] = left_rgba[l * 4];
+            target_rgba[t * 4 + 1] = right_rgba[r * 4 + 1];
+            target_rgba[t * 4 + 2] = right_rgba[r * 4 + 2];
+            target_rgba[t * 4 + 3] = 255;
+            target_depth[t] = left_depth[l].min(right_depth[r]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::PitchMode;
+    use crate::material::Material;
+    use crate::obj::Vertex;
+    use crate::point2d::Point2D;
+    use crate::texture::Texture;
+    use crate::triangle::Triangle3D;
+
+    const IDENTITY: Transform = Transform { yaw: 0.0, pitch: 0.0, posistion: Point3D { x: 0.0, y: 0.0, z: 0.0 } };
+
+    // A 4x4 white square facing the camera at the origin, centered 10 units ahead
+    fn square() -> Model {
+        let vertex = |x: f32, y: f32| Vertex {
+            position: Point3D { x, y, z: 10.0 },
+            texcoord: Point2D { x: 0.0, y: 0.0 },
+            normal: Point3D { x: 0.0, y: 0.0, z: -1.0 },
+            alpha: 1.0,
+        };
+        let triangles = vec![
+            Triangle3D::from_vertices(vertex(-2.0, -2.0), vertex(-2.0, 2.0), vertex(2.0, -2.0), 0),
+            Triangle3D::from_vertices(vertex(2.0, -2.0), vertex(-2.0, 2.0), vertex(2.0, 2.0), 0),
+        ];
+        Model::new(triangles, vec![Material::default()], vec![Texture::solid(255, 255, 255, 255).into()])
+    }
+
+    fn camera() -> Camera {
+        Camera { fov: 90.0f32.to_radians(), camera_speed: 1.0, mouse_sensitivity: 0.0, transform: IDENTITY, pitch_mode: PitchMode::default() }
+    }
+
+    // Mean x of the lit pixels in columns [start, end)
+    fn centroid_x(screen: &ScreenSpace, start: u32, end: u32) -> f32 {
+        let lit: Vec<u32> = (0..screen.height)
+            .flat_map(|y| (start..end).map(move |x| (x, y)))
+            .filter(|&(x, y)| screen.get_pixel(x, y).0 > 0)
+            .map(|(x, _)| x)
+            .collect();
+        assert!(!lit.is_empty());
+        lit.iter().sum::<u32>() as f32 / lit.len() as f32
+    }
+
+    #[test]
+    fn side_by_side_halves_show_the_object_at_mirrored_offsets() {
+        let mut rig = StereoRig::new(128, 48, 4, 4.0);
+        for eye in [&mut rig.left, &mut rig.right] {
+            eye.ambient = Point3D { x: 1.0, y: 1.0, z: 1.0 };
+        }
+        rig.clear(0, 0, 0, 255);
+        rig.draw_instanced(&square(), &[IDENTITY], &camera(), Point3D { x: 0.0, y: 0.0, z: -1.0 }, None);
+        let target = ScreenSpace::new(128, 48);
+        rig.composite_side_by_side(&target);
+
+        // The halves are centered on columns 31.5 and 95.5, the left eye sits left of the object so sees it right of center
+        let left = centroid_x(&target, 0, 64) - 31.5;
+        let right = centroid_x(&target, 64, 128) - 95.5;
+        assert!(left > 2.0, "left eye offset {}", left);
+        assert!((left + right).abs() < 0.5, "offsets {} and {}", left, right);
+    }
+
+    #[test]
+    fn anaglyph_takes_red_from_the_left_eye_and_cyan_from_the_right() {
+        let left = ScreenSpace::new(4, 3);
+        let right = ScreenSpace::new(4, 3);
+        left.clear(200, 10, 20, 100);
+        right.clear(30, 150, 250, 255);
+        left.unsafe_set_depth(1, 1, 5.0);
+        right.unsafe_set_depth(1, 1, 3.0);
+        let target = ScreenSpace::new(4, 3);
+        anaglyph(&left, &right, &target);
+
+        assert_eq!(target.get_pixel(0, 0), (200, 150, 250, 255));
+        assert_eq!(target.get_pixel(3, 2), (200, 150, 250, 255));
+        assert_eq!(target.get_depth(1, 1), 3.0);
+        assert_eq!(target.get_depth(0, 0), f32::INFINITY);
+    }
+}
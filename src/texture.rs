@@ -1,8 +1,50 @@
 use std::{path::Path, simd::num::SimdFloat};
+use std::sync::Arc;
 use image::{DynamicImage, GenericImageView};
-use std::simd::{Simd, StdFloat, u8x4, usizex4, f32x4};
-use std::simd::num::SimdUint;
+use std::simd::{StdFloat, f32x4};
+use rayon::prelude::*;
 
+use crate::error::RusterizerError;
+use crate::shading::srgb_to_linear;
+
+/// How texcoords outside [0, 1] are mapped back onto the texture
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    // Tile the texture
+    #[default]
+    Repeat,
+    // Stretch the edge texels, for UVs that only stray outside [0, 1] by float error
+    Clamp,
+}
+
+impl WrapMode {
+    #[inline(always)]
+    pub fn apply(self, coord: f32) -> f32 {
+        match self {
+            WrapMode::Repeat => coord.rem_euclid(1.0),
+            WrapMode::Clamp => coord.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Wrap an integer texel coordinate into [0, size)
+    #[inline(always)]
+    pub fn apply_texel(self, texel: i32, size: u32) -> usize {
+        match self {
+            WrapMode::Repeat => texel.rem_euclid(size as i32) as usize,
+            WrapMode::Clamp => texel.clamp(0, size as i32 - 1) as usize,
+        }
+    }
+}
+
+/// How a texture is sampled between texel centers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFilter {
+    #[default]
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Clone)]
 pub struct Texture {
     pub width: u32,
     pub height: u32,
@@ -11,23 +53,140 @@ pub struct Texture {
 
 impl Texture {
     pub fn load<P: AsRef<Path>>(path: P) -> image::ImageResult<Self> {
-        let img: DynamicImage = image::open(path)?;
+        reject_empty(path.as_ref())?;
+        Ok(Self::from_image(image::open(path)?))
+    }
+
+    /// load with the failure classified, for callers that match on the kind of error
+    pub fn open(path: &str) -> Result<Self, RusterizerError> {
+        Self::load(path).map_err(|e| RusterizerError::texture(path, e))
+    }
+
+    /// Decode an encoded image (PNG, JPEG, ...) from memory, for textures embedded with include_bytes! or read from an archive
+    pub fn from_bytes(bytes: &[u8]) -> image::ImageResult<Self> {
+        if bytes.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "texture data is empty").into());
+        }
+        Ok(Self::from_image(image::load_from_memory(bytes)?))
+    }
+
+    // RGBA8 conversion with rows flipped so v = 0 is the bottom of the image
+    fn from_image(img: DynamicImage) -> Self {
         let (width, height) = img.dimensions();
-        let rgba_img = img.to_rgba8();
-        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+        Self::from_rgba_top_down(width, height, img.to_rgba8().as_raw())
+    }
+
+    /// RGBA8 pixels stored top row first (as image decoders produce them), flipped so v = 0 is the bottom of the image
+    pub fn from_rgba_top_down(width: u32, height: u32, pixels: &[u8]) -> Self {
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
         for y in (0..height).rev() {
-            let row_start = (y * width * 4) as usize;
-            let row_end = row_start + (width * 4) as usize;
-            rgba.extend_from_slice(&rgba_img.as_raw()[row_start..row_end]);
+            let row_start = y as usize * width as usize * 4;
+            let row_end = row_start + width as usize * 4;
+            rgba.extend_from_slice(&pixels[row_start..row_end]);
+        }
+        Self { width, height, rgba }
+    }
+
+    /// Decode several textures at once on the thread pool, results stay in the order of `paths`
+    pub fn load_parallel<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<image::ImageResult<Self>> {
+        paths.par_iter().map(Self::load).collect()
+    }
+
+    /// Half-size copy with every texel the box average of the 2x2 block under it, rows are filtered in parallel.
+    /// An odd edge's last texel is reused as its own neighbor, and a 1 texel dimension stays 1
+    pub fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let src_row = self.width as usize * 4;
+        let mut rgba = vec![0; width as usize * height as usize * 4];
+        rgba.par_chunks_mut(width as usize * 4).enumerate().for_each(|(y, row)| {
+            let y0 = (y * 2).min(self.height as usize - 1);
+            let y1 = (y * 2 + 1).min(self.height as usize - 1);
+            for x in 0..width as usize {
+                let x0 = (x * 2).min(self.width as usize - 1);
+                let x1 = (x * 2 + 1).min(self.width as usize - 1);
+                for channel in 0..4 {
+                    let sum = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)].iter()
+                        .map(|&(sx, sy)| self.rgba[sy * src_row + sx * 4 + channel] as u32)
+                        .sum::<u32>();
+                    // Round to nearest rather than truncating so repeated levels don't drift darker
+                    row[x * 4 + channel] = ((sum + 2) / 4) as u8;
+                }
+            }
+        });
+        Self { width, height, rgba }
+    }
+
+    /// Every mip level below this one down to 1x1, level 0 (the texture itself) isn't included.
+    /// Each level depends on the previous, so the levels are sequential and only the rows within one run in parallel
+    pub fn mip_chain(&self) -> Vec<Self> {
+        let mut levels: Vec<Self> = Vec::new();
+        loop {
+            let level = levels.last().unwrap_or(self);
+            if level.width == 1 && level.height == 1 {
+                return levels;
+            }
+            let next = level.downsample();
+            levels.push(next);
         }
-        Ok(Self { width, height, rgba })
     }
+
+    /// Mip chains for several textures at once, results stay in the order of `textures`
+    pub fn mip_chains_parallel(textures: &[Self]) -> Vec<Vec<Self>> {
+        textures.par_iter().map(Self::mip_chain).collect()
+    }
+
+    /// 1x1 magenta texture, stands in for textures that are missing or failed to load
+    pub fn placeholder() -> Self {
+        Self::solid(255, 0, 255, 255)
+    }
+
+    /// 1x1 texture of a single color
+    pub fn solid(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { width: 1, height: 1, rgba: vec![r, g, b, a] }
+    }
+    /// Nearest texel with texcoords wrapped per `wrap`
+    #[inline(always)]
+    pub fn sample_wrapped(&self, u: f32, v: f32, wrap: WrapMode) -> (u8, u8, u8, u8) {
+        self.texel_at(wrap.apply(u), wrap.apply(v))
+    }
+
     pub fn sample(&self, u: f32, v: f32) -> (u8, u8, u8, u8) {
-        let u = u.fract();
-        let v = v.fract();
+        self.texel_at(u.fract(), v.fract())
+    }
+
+    /// Blend of the four texels around (u, v). Each tap is wrapped on its own, so with Repeat a lookup at the u = 1 edge
+    /// blends the last column with the first instead of seaming
+    pub fn sample_bilinear(&self, u: f32, v: f32, wrap: WrapMode) -> (u8, u8, u8, u8) {
+        // Texel centers sit at half-integer coordinates. Wrapping the texcoord first keeps wild UVs from saturating the casts below
+        let x = wrap.apply(u) * self.width as f32 - 0.5;
+        let y = wrap.apply(v) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+
+        let tap = |tx: i32, ty: i32| self.gather_texel(tx, ty, wrap);
+        let top = tap(x0, y0) * f32x4::splat(1.0 - fx) + tap(x0 + 1, y0) * f32x4::splat(fx);
+        let bottom = tap(x0, y0 + 1) * f32x4::splat(1.0 - fx) + tap(x0 + 1, y0 + 1) * f32x4::splat(fx);
+        let color = (top * f32x4::splat(1.0 - fy) + bottom * f32x4::splat(fy)).round().cast::<u8>();
+        (color[0], color[1], color[2], color[3])
+    }
+
+    /// Sample with the given filter and wrap mode
+    #[inline(always)]
+    pub fn sample_filtered(&self, u: f32, v: f32, wrap: WrapMode, filter: TextureFilter) -> (u8, u8, u8, u8) {
+        match filter {
+            TextureFilter::Nearest => self.sample_wrapped(u, v, wrap),
+            TextureFilter::Bilinear => self.sample_bilinear(u, v, wrap),
+        }
+    }
+
+    // Nearest texel for texcoords already in [0, 1]
+    #[inline(always)]
+    fn texel_at(&self, u: f32, v: f32) -> (u8, u8, u8, u8) {
         let x = (u * (self.width as f32 - 1.0)).round() as u32;
         let y = (v * (self.height as f32 - 1.0)).round() as u32;
-        let idx = ((y * self.width + x) * 4) as usize;
+        let idx = (y as usize * self.width as usize + x as usize) * 4;
         (
             self.rgba[idx],
             self.rgba[idx + 1],
@@ -36,36 +195,251 @@ impl Texture {
         )
     }
 
-pub fn sample_quad_test(&self, u: f32x4, v: f32x4) -> (f32x4, f32x4, f32x4, f32x4) {
-    let (sr0, sg0, sb0, sa0) = self.sample(u[0], v[0]);
-    let (sr1, sg1, sb1, sa1) = self.sample(u[1], v[1]);
-    let (sr2, sg2, sb2, sa2) = self.sample(u[2], v[2]);
-    let (sr3, sg3, sb3, sa3) = self.sample(u[3], v[3]);
+    /// One texel's RGBA as floats in [0, 255], the coordinates may be anywhere and are wrapped per axis first.
+    /// Every filter fetches through this so the wrap and index math lives in one place
+    #[inline(always)]
+    pub fn gather_texel(&self, x: i32, y: i32, wrap: WrapMode) -> f32x4 {
+        let idx = (wrap.apply_texel(y, self.height) * self.width as usize + wrap.apply_texel(x, self.width)) * 4;
+        f32x4::from_array([self.rgba[idx] as f32, self.rgba[idx + 1] as f32, self.rgba[idx + 2] as f32, self.rgba[idx + 3] as f32])
+    }
+} 
 
-    (
-        f32x4::from_array([sr0 as f32, sr1 as f32, sr2 as f32, sr3 as f32]),
-        f32x4::from_array([sg0 as f32, sg1 as f32, sg2 as f32, sg3 as f32]),
-        f32x4::from_array([sb0 as f32, sb1 as f32, sb2 as f32, sb3 as f32]),
-        f32x4::from_array([sa0 as f32, sa1 as f32, sa2 as f32, sa3 as f32]),
-    )
+/// Every map bound to one material, fetched together per fragment so shading code doesn't juggle textures individually.
+/// Maps are shared, so cloning is cheap and one texture can back several materials
+#[derive(Clone)]
+pub struct MaterialTextures {
+    pub diffuse: Arc<Texture>,
+    // Light the surface gives off itself in linear light, added after lighting. HDR maps can go above 1
+    pub emissive: Option<Arc<HdrTexture>>,
 }
 
-    pub fn sample_quad(&self, u: f32x4, v: f32x4) -> (f32x4, f32x4, f32x4, f32x4) {
-        let width  = self.width as f32;
-        let height = self.height as f32;
+/// One fragment's texels from each bound map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialSample {
+    pub diffuse: (u8, u8, u8, u8),
+    pub emissive: Option<(f32, f32, f32, f32)>,
+}
 
-        // Convert UV to pixel coords
-        let x = (u * f32x4::splat(width  - 1.0)).cast::<usize>();
-        let y = (v * f32x4::splat(height - 1.0)).cast::<usize>();
+impl From<Texture> for MaterialTextures {
+    fn from(diffuse: Texture) -> Self {
+        Self { diffuse: Arc::new(diffuse), emissive: None }
+    }
+}
 
-        // Index into texel (RGBA = 4 bytes)
-        let idx: usizex4 = (y * usizex4::splat(self.width as usize) + x) * Simd::splat(4);
+impl MaterialTextures {
+    /// Fetch every bound map at (u, v), all with the same wrap. The filter applies to the diffuse map, emission is nearest sampled
+    #[inline(always)]
+    pub fn sample(&self, u: f32, v: f32, wrap: WrapMode, filter: TextureFilter) -> MaterialSample {
+        MaterialSample {
+            diffuse: self.diffuse.sample_filtered(u, v, wrap, filter),
+            emissive: self.emissive.as_ref().map(|emissive| emissive.sample(u, v, wrap)),
+        }
+    }
+}
+
+/// Float RGBA texture for HDR sources (.hdr, .exr) such as environment maps and emissive textures, values aren't limited to [0, 1]
+pub struct HdrTexture {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<f32>,
+}
+
+impl HdrTexture {
+    pub fn load<P: AsRef<Path>>(path: P) -> image::ImageResult<Self> {
+        reject_empty(path.as_ref())?;
+        let img: DynamicImage = image::open(path)?;
+        let (width, height) = img.dimensions();
+        let rgba_img = img.to_rgba32f();
+        // Same row flip as Texture::load so both share texcoord conventions
+        let row_len = width as usize * 4;
+        let mut rgba = Vec::with_capacity(row_len * height as usize);
+        for y in (0..height as usize).rev() {
+            rgba.extend_from_slice(&rgba_img.as_raw()[y * row_len..(y + 1) * row_len]);
+        }
+        Ok(Self { width, height, rgba })
+    }
+
+    /// Linearize an 8-bit sRGB texture so LDR and HDR maps share one float path, alpha is only rescaled
+    pub fn from_srgb(texture: &Texture) -> Self {
+        let rgba = texture.rgba.chunks_exact(4)
+            .flat_map(|t| [srgb_to_linear(t[0]), srgb_to_linear(t[1]), srgb_to_linear(t[2]), t[3] as f32 / 255.0])
+            .collect();
+        Self { width: texture.width, height: texture.height, rgba }
+    }
+
+    /// Load .hdr and .exr files as float data, anything else through Texture::load and linearized
+    pub fn load_any<P: AsRef<Path>>(path: P) -> image::ImageResult<Self> {
+        let path = path.as_ref();
+        let is_hdr = path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("hdr") || ext.eq_ignore_ascii_case("exr"));
+        if is_hdr {
+            Self::load(path)
+        } else {
+            Texture::load(path).map(|texture| Self::from_srgb(&texture))
+        }
+    }
 
-        let gathered_simd_r: f32x4 = Simd::gather_or_default(&self.rgba, idx).cast::<f32>();
-        let gathered_simd_g: f32x4 = Simd::gather_or_default(&self.rgba, idx+Simd::splat(1)).cast::<f32>();
-        let gathered_simd_b: f32x4 = Simd::gather_or_default(&self.rgba, idx+Simd::splat(2)).cast::<f32>();
-        let gathered_simd_a: f32x4 = Simd::gather_or_default(&self.rgba, idx+Simd::splat(3)).cast::<f32>();
-        //println!("{gathered_simd_r:?},{gathered_simd_g:?},{gathered_simd_b:?},{gathered_simd_a:?}");
-        (gathered_simd_r, gathered_simd_g, gathered_simd_b, gathered_simd_a)
+    /// load_any for several files on the thread pool, results stay in the order of `paths`
+    pub fn load_parallel<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<image::ImageResult<Self>> {
+        paths.par_iter().map(Self::load_any).collect()
     }
-} 
\ No newline at end of file
+
+    pub fn sample(&self, u: f32, v: f32, wrap: WrapMode) -> (f32, f32, f32, f32) {
+        let x = (wrap.apply(u) * (self.width as f32 - 1.0)).round() as usize;
+        let y = (wrap.apply(v) * (self.height as f32 - 1.0)).round() as usize;
+        let idx = (y * self.width as usize + x) * 4;
+        (self.rgba[idx], self.rgba[idx + 1], self.rgba[idx + 2], self.rgba[idx + 3])
+    }
+}
+
+// image::open reports an empty file as an unrecognized format, which hides the real problem
+fn reject_empty(path: &Path) -> image::ImageResult<()> {
+    if std::fs::metadata(path)?.len() == 0 {
+        let message = format!("texture file {} is empty", path.display());
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, message).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Top row red, green. Bottom row blue, half transparent white
+    const RGBA_2X2_PNG: &[u8] = include_bytes!("../testdata/rgba_2x2.png");
+
+    #[test]
+    fn embedded_png_decodes_with_v_up() {
+        let texture = Texture::from_bytes(RGBA_2X2_PNG).unwrap();
+        assert_eq!((texture.width, texture.height), (2, 2));
+        // Rows are stored bottom first, so the image's top left is the last row's first texel
+        assert_eq!(texture.rgba[..4], [0, 0, 255, 255]);
+        assert_eq!(texture.rgba[4..8], [255, 255, 255, 128]);
+        assert_eq!(texture.rgba[8..12], [255, 0, 0, 255]);
+        assert!(Texture::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn hdr_texels_keep_values_above_one() {
+        // 2x1 map, the right texel is four times brighter than 8-bit white
+        let hdr = HdrTexture { width: 2, height: 1, rgba: vec![0.5, 0.5, 0.5, 1.0, 4.0, 2.0, 1.5, 1.0] };
+        let (r, g, b, _) = hdr.sample(1.0 - f32::EPSILON, 0.5, WrapMode::Clamp);
+        assert!(r > 1.0 && g > 1.0 && b > 1.0, "({}, {}, {})", r, g, b);
+        assert_eq!(hdr.sample(0.0, 0.5, WrapMode::Clamp).0, 0.5);
+        // Repeat wraps negative texcoords instead of indexing out of bounds
+        assert_eq!(hdr.sample(-0.1, 0.5, WrapMode::Repeat).0, 4.0);
+    }
+
+    #[test]
+    fn ldr_maps_are_linearized() {
+        let hdr = HdrTexture::from_srgb(&Texture::solid(255, 128, 0, 51));
+        let (r, g, b, a) = hdr.sample(0.5, 0.5, WrapMode::Repeat);
+        assert_eq!((r, b), (1.0, 0.0));
+        assert!((g - srgb_to_linear(128)).abs() < 1e-6 && g < 0.5);
+        assert!((a - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parallel_loads_decode_every_path_in_order() {
+        let png = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/rgba_2x2.png");
+        let missing = std::env::temp_dir().join(format!("rusterizer-missing-{}.png", std::process::id()));
+        let paths = vec![png.into(), png.into(), missing, png.into(), png.into()];
+        let results = Texture::load_parallel(&paths);
+        assert_eq!(results.len(), 5);
+        assert!(results[2].is_err());
+        let expected = Texture::from_bytes(RGBA_2X2_PNG).unwrap();
+        for result in results.iter().enumerate().filter(|(i, _)| *i != 2).map(|(_, result)| result) {
+            let texture = result.as_ref().unwrap();
+            assert_eq!((texture.width, texture.height, &texture.rgba), (expected.width, expected.height, &expected.rgba));
+        }
+    }
+
+    #[test]
+    fn empty_files_are_reported_as_empty_by_both_loaders() {
+        let path = std::env::temp_dir().join(format!("rusterizer-empty-{}.hdr", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let errors = [Texture::load(&path).err(), HdrTexture::load(&path).err(), HdrTexture::load_any(&path).err()];
+        std::fs::remove_file(&path).unwrap();
+        for error in errors {
+            let message = error.expect("an empty file can't load").to_string();
+            assert!(message.contains("is empty"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn bilinear_blends_across_the_wrap_seam() {
+        // Black then white, the u = 0 edge sits halfway between the white last column and the black first one
+        let texture = Texture { width: 2, height: 1, rgba: vec![0, 0, 0, 255, 255, 255, 255, 255] };
+        assert_eq!(texture.sample_bilinear(0.0, 0.5, WrapMode::Repeat), (128, 128, 128, 255));
+        assert_eq!(texture.sample_bilinear(1.0, 0.5, WrapMode::Repeat), (128, 128, 128, 255));
+        assert_eq!(texture.sample_bilinear(-2.0, 0.5, WrapMode::Repeat), (128, 128, 128, 255));
+        // Clamped, the edge texel only blends with itself
+        assert_eq!(texture.sample_bilinear(0.0, 0.5, WrapMode::Clamp), (0, 0, 0, 255));
+        assert_eq!(texture.sample_bilinear(1.0, 0.5, WrapMode::Clamp), (255, 255, 255, 255));
+        // UVs far past the i32 range don't overflow the neighbor taps
+        for wrap in [WrapMode::Repeat, WrapMode::Clamp] {
+            for wild in [3.0e9, -3.0e9, f32::MAX, f32::INFINITY, f32::NAN] {
+                texture.sample_bilinear(wild, wild, wrap);
+            }
+        }
+    }
+
+    #[test]
+    fn material_sample_fetches_diffuse_and_emissive_at_the_same_texcoord() {
+        let diffuse = Texture { width: 2, height: 1, rgba: vec![255, 0, 0, 255, 0, 255, 0, 255] };
+        let emissive = HdrTexture { width: 2, height: 1, rgba: vec![4.0, 2.0, 0.0, 1.0, 0.0, 0.5, 3.0, 1.0] };
+        let textures = MaterialTextures { diffuse: Arc::new(diffuse.clone()), emissive: Some(Arc::new(emissive)) };
+        for (u, diffuse_texel, emissive_texel) in [(0.1, (255, 0, 0, 255), (4.0, 2.0, 0.0, 1.0)), (0.9, (0, 255, 0, 255), (0.0, 0.5, 3.0, 1.0))] {
+            let sample = textures.sample(u, 0.5, WrapMode::Clamp, TextureFilter::Nearest);
+            assert_eq!(sample, MaterialSample { diffuse: diffuse_texel, emissive: Some(emissive_texel) });
+        }
+        // Only the diffuse map bound
+        let plain = MaterialTextures::from(diffuse).sample(0.1, 0.5, WrapMode::Clamp, TextureFilter::Nearest);
+        assert_eq!(plain, MaterialSample { diffuse: (255, 0, 0, 255), emissive: None });
+    }
+
+    #[test]
+    fn right_edge_fetches_stay_in_their_row() {
+        // 2x2, the texel after the bottom-right one in memory is the top row's first, bright red
+        let texture = Texture { width: 2, height: 2, rgba: vec![0, 0, 0, 255, 0, 0, 200, 255, 255, 0, 0, 255, 255, 0, 0, 255] };
+        // Bilinear at the right edge of the bottom row blends the edge texel only with itself when clamped, and with its own row's first texel when repeating
+        assert_eq!(texture.sample_bilinear(1.0, 0.25, WrapMode::Clamp), (0, 0, 200, 255));
+        assert_eq!(texture.sample_bilinear(1.0, 0.25, WrapMode::Repeat), (0, 0, 100, 255));
+        assert_eq!(texture.gather_texel(2, 0, WrapMode::Clamp), texture.gather_texel(1, 0, WrapMode::Clamp));
+        assert_eq!(texture.gather_texel(2, 0, WrapMode::Repeat), texture.gather_texel(0, 0, WrapMode::Repeat));
+        // Nearest sampling past u = 1 stays on the last column
+        assert_eq!(texture.sample_wrapped(1.5, 0.0, WrapMode::Clamp), (0, 0, 200, 255));
+    }
+
+    #[test]
+    fn mip_levels_halve_down_to_one_texel_and_average_the_block_under_them() {
+        // 5x2, gray ramp along x so each level's texels are known averages
+        let rgba = (0..2).flat_map(|_| (0..5u8).flat_map(|x| [x * 40, x * 40, x * 40, 255])).collect();
+        let texture = Texture { width: 5, height: 2, rgba };
+        let chain = texture.mip_chain();
+        let sizes: Vec<(u32, u32)> = chain.iter().map(|level| (level.width, level.height)).collect();
+        assert_eq!(sizes, [(2, 1), (1, 1)]);
+        // (0 + 40) / 2 and (80 + 120) / 2, the odd fifth column is dropped at this level
+        assert_eq!(chain[0].rgba, [20, 20, 20, 255, 100, 100, 100, 255]);
+        assert_eq!(chain[1].rgba, [60, 60, 60, 255]);
+        // Rounds to nearest: (0 + 1 + 1 + 1) / 4 is 0.75
+        let rounded = Texture { width: 2, height: 2, rgba: [0, 1, 1, 1].iter().flat_map(|&v| [v; 4]).collect() };
+        assert_eq!(rounded.downsample().rgba, [1; 4]);
+
+        let chains = Texture::mip_chains_parallel(&[texture, Texture::placeholder()]);
+        assert_eq!(chains[0].len(), 2);
+        assert!(chains[1].is_empty());
+    }
+
+    #[test]
+    fn gather_texel_fetches_a_known_texel_as_floats() {
+        // 3x2, every texel's channels distinct from the others'
+        let rgba = (0..6u8).flat_map(|i| [i * 10, i * 10 + 1, i * 10 + 2, 255 - i]).collect();
+        let texture = Texture { width: 3, height: 2, rgba };
+        assert_eq!(texture.gather_texel(1, 1, WrapMode::Clamp).to_array(), [40.0, 41.0, 42.0, 251.0]);
+        // Out of range coordinates wrap per axis before indexing
+        assert_eq!(texture.gather_texel(-2, 3, WrapMode::Repeat).to_array(), [40.0, 41.0, 42.0, 251.0]);
+        assert_eq!(texture.gather_texel(-5, 9, WrapMode::Clamp).to_array(), [30.0, 31.0, 32.0, 252.0]);
+    }
+}
@@ -0,0 +1,67 @@
+/// Fixed-rate simulation clock, accumulates real frame time and hands out whole simulation steps
+pub struct FixedTimestep {
+    pub step: f32,
+    pub max_steps: u32,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(rate_hz: f32) -> Self {
+        Self { step: 1.0 / rate_hz, max_steps: 8, accumulator: 0.0 }
+    }
+
+    /// Add a frame's elapsed time and return how many simulation steps to run this frame
+    pub fn advance(&mut self, frame_dt: f32) -> u32 {
+        self.accumulator += frame_dt.max(0.0);
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        // Drop time we can't catch up on rather than spiralling after a long stall
+        if steps > self.max_steps {
+            steps = self.max_steps;
+            self.accumulator = 0.0;
+        }
+        steps
+    }
+
+    /// How far (0..1) the render time is between the last two simulation steps
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_count_depends_on_total_time_not_frame_rate() {
+        // One simulated second at 60 Hz, delivered in even, uneven and stuttering frames
+        let frame_patterns: [&[f32]; 3] = [&[1.0 / 60.0], &[0.004, 0.021, 0.013, 0.031, 0.0012], &[0.1, 0.001, 0.001, 0.05]];
+        for pattern in frame_patterns {
+            let mut timestep = FixedTimestep::new(60.0);
+            let (mut elapsed, mut steps) = (0.0f64, 0);
+            for &dt in pattern.iter().cycle() {
+                if elapsed + dt as f64 > 1.0 + 1e-4 {
+                    break;
+                }
+                elapsed += dt as f64;
+                steps += timestep.advance(dt);
+            }
+            // Whatever is left in the accumulator is less than a step, so the count is within one of the exact 60
+            let expected = (elapsed * 60.0).floor() as u32;
+            assert!(steps.abs_diff(expected) <= 1, "{} steps over {}s from {:?}", steps, elapsed, pattern);
+            assert!((0.0..1.0).contains(&timestep.alpha()));
+        }
+    }
+
+    #[test]
+    fn long_stalls_are_capped_instead_of_caught_up() {
+        let mut timestep = FixedTimestep::new(60.0);
+        assert_eq!(timestep.advance(2.0), timestep.max_steps);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(-1.0), 0);
+    }
+}
@@ -1,11 +1,33 @@
-use crate::point3d::Point3D;
+use crate::point3d::{Point3D, cross3, dot3};
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Transform {
     pub yaw: f32,
     pub pitch: f32,
     pub posistion: Point3D,
 }
 
+/// General inverse of the matrix with columns ihat, jhat, khat, for bases that aren't orthonormal.
+/// None when the basis is singular (flattened onto a plane or line), or too close to it to invert in f32
+pub fn invert_basis(ihat: Point3D, jhat: Point3D, khat: Point3D) -> Option<(Point3D, Point3D, Point3D)> {
+    // Rows of the inverse are the cross products of column pairs over the determinant
+    let row_x = cross3(jhat, khat);
+    let row_y = cross3(khat, ihat);
+    let row_z = cross3(ihat, jhat);
+    let det = dot3(ihat, row_x);
+    let scale = dot3(ihat, ihat).max(dot3(jhat, jhat)).max(dot3(khat, khat));
+    if det.is_nan() || det.abs() <= f32::EPSILON * scale * scale.sqrt() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let (row_x, row_y, row_z) = (row_x * inv_det, row_y * inv_det, row_z * inv_det);
+    Some((
+        Point3D { x: row_x.x, y: row_y.x, z: row_z.x },
+        Point3D { x: row_x.y, y: row_y.y, z: row_z.y },
+        Point3D { x: row_x.z, y: row_y.z, z: row_z.z },
+    ))
+}
+
 fn transform_vector(ihat: Point3D, jhat: Point3D, khat: Point3D, v: Point3D) -> Point3D {
     ihat * v.x + jhat * v.y + khat * v.z
 }
@@ -29,6 +51,8 @@ impl Transform {
         (ihat, jhat, khat)
     }
 
+    /// Inverse as the transpose, exact because the basis is a product of two rotations and so orthonormal at every yaw and pitch.
+    /// to_local_point(to_world_point(p)) gets back p up to f32 rounding. A basis with scale or shear needs invert_basis instead
     pub fn get_inverse_basis_vectors(&self) -> (Point3D, Point3D, Point3D) {
         let (ihat, jhat, khat) = self.get_basis_vectors();
         let inv_ihat = Point3D{x: ihat.x, y: jhat.x, z: khat.x};
@@ -51,4 +75,61 @@ impl Transform {
         let (ihat, jhat, khat) = self.get_basis_vectors();
         transform_vector(ihat, jhat, khat, dir)
     }
+
+    pub fn to_local_direction(&self, dir: Point3D) -> Point3D {
+        let (ihat, jhat, khat) = self.get_inverse_basis_vectors();
+        transform_vector(ihat, jhat, khat, dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn close(a: Point3D, b: Point3D, tolerance: f32) -> bool {
+        let d = a - b;
+        dot3(d, d).sqrt() <= tolerance
+    }
+
+    #[test]
+    fn local_and_world_points_round_trip() {
+        let mut rng = StdRng::seed_from_u64(464);
+        let half_pi = std::f32::consts::FRAC_PI_2;
+        // Random poses plus pitches right at and beyond straight up and down
+        let pitches = (0..200).map(|_| rng.gen_range(-half_pi..half_pi)).chain([half_pi, -half_pi, std::f32::consts::PI, 3.0 * half_pi]).collect::<Vec<f32>>();
+        for pitch in pitches {
+            let transform = Transform {
+                yaw: rng.gen_range(-10.0..10.0),
+                pitch,
+                posistion: Point3D { x: rng.gen_range(-50.0..50.0), y: rng.gen_range(-50.0..50.0), z: rng.gen_range(-50.0..50.0) },
+            };
+            let p = Point3D { x: rng.gen_range(-20.0..20.0), y: rng.gen_range(-20.0..20.0), z: rng.gen_range(-20.0..20.0) };
+            assert!(close(transform.to_local_point(transform.to_world_point(p)), p, 1e-4), "{:?} {:?}", transform, p);
+            assert!(close(transform.to_world_point(transform.to_local_point(p)), p, 1e-4), "{:?} {:?}", transform, p);
+            assert!(close(transform.to_local_direction(transform.transform_direction(p)), p, 1e-4), "{:?} {:?}", transform, p);
+        }
+    }
+
+    #[test]
+    fn invert_basis_undoes_scale_and_shear() {
+        let ihat = Point3D { x: 2.0, y: 0.0, z: 0.0 };
+        let jhat = Point3D { x: 0.5, y: 3.0, z: 0.0 };
+        let khat = Point3D { x: -1.0, y: 0.25, z: 0.5 };
+        let (inv_i, inv_j, inv_k) = invert_basis(ihat, jhat, khat).unwrap();
+        for v in [Point3D { x: 1.0, y: 0.0, z: 0.0 }, Point3D { x: -3.0, y: 7.0, z: 2.5 }, Point3D { x: 0.1, y: -0.2, z: 9.0 }] {
+            let transformed = transform_vector(ihat, jhat, khat, v);
+            assert!(close(transform_vector(inv_i, inv_j, inv_k, transformed), v, 1e-5), "{:?}", v);
+        }
+    }
+
+    #[test]
+    fn invert_basis_rejects_singular_bases() {
+        let ihat = Point3D { x: 1.0, y: 2.0, z: 0.0 };
+        let jhat = Point3D { x: 0.0, y: 1.0, z: 1.0 };
+        // khat in the plane of the other two
+        assert_eq!(invert_basis(ihat, jhat, ihat * 2.0 - jhat), None);
+        assert_eq!(invert_basis(ihat, jhat, Point3D { x: 0.0, y: 0.0, z: 0.0 }), None);
+    }
 }
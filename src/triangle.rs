@@ -1,5 +1,7 @@
+use crate::geometry::EdgeSetup;
+use crate::obj::Vertex;
 use crate::point2d::Point2D;
-use crate::point3d::Point3D;
+use crate::point3d::{self, Point3D};
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Triangle3D {
@@ -15,11 +17,189 @@ pub struct Triangle3D {
     pub na: Point3D,
     pub nb: Point3D,
     pub nc: Point3D,
+    // per-vertex alpha
+    pub alpha_a: f32,
+    pub alpha_b: f32,
+    pub alpha_c: f32,
+    // world-space positions, equal to a/b/c until the transform stage replaces those with screen positions
+    pub wa: Point3D,
+    pub wb: Point3D,
+    pub wc: Point3D,
+    // index into the mesh materials
+    pub material: usize,
+    // OBJ face this triangle came from in file order (a glTF triangle's own index), unchanged by sorting and subdivision
+    pub face: u32,
     // screenspace bounding boxes
     pub bb_start_x: u32,
     pub bb_start_y: u32,
     pub bb_end_x: u32,
     pub bb_end_y: u32,
+    // Edge functions and area of the screen-space triangle, filled in by the transform stage
+    pub setup: EdgeSetup,
+    // Flattened (instance, triangle) index of the source triangle, filled in by the transform stage and stable under culling
+    pub id: u32,
+}
+
+impl Triangle3D {
+    /// Object-space triangle from three vertices, world positions start equal to the positions
+    pub fn from_vertices(a: Vertex, b: Vertex, c: Vertex, material: usize) -> Self {
+        Triangle3D {
+            a: a.position, b: b.position, c: c.position,
+            ta: a.texcoord, tb: b.texcoord, tc: c.texcoord,
+            na: a.normal, nb: b.normal, nc: c.normal,
+            alpha_a: a.alpha, alpha_b: b.alpha, alpha_c: c.alpha,
+            wa: a.position, wb: b.position, wc: c.position,
+            material,
+            face: 0,
+            bb_start_x: 0, bb_start_y: 0, bb_end_x: 0, bb_end_y: 0,
+            setup: EdgeSetup::default(),
+            id: 0,
+        }
+    }
+
+    /// Reverse the winding by swapping vertices b and c along with their attributes
+    pub fn flip_winding(&mut self) {
+        std::mem::swap(&mut self.b, &mut self.c);
+        std::mem::swap(&mut self.wb, &mut self.wc);
+        std::mem::swap(&mut self.tb, &mut self.tc);
+        std::mem::swap(&mut self.nb, &mut self.nc);
+        std::mem::swap(&mut self.alpha_b, &mut self.alpha_c);
+    }
+
+    /// Area of the a/b/c triangle, in object space before the transform stage
+    pub fn area(&self) -> f32 {
+        let n = point3d::cross3(self.b - self.a, self.c - self.a);
+        0.5 * point3d::dot3(n, n).sqrt()
+    }
+
+    /// Split at the edge midpoints into four triangles with the same winding, every attribute is interpolated linearly
+    pub fn subdivide(&self) -> [Triangle3D; 4] {
+        let (a, b, c) = (self.corner(0), self.corner(1), self.corner(2));
+        let (ab, bc, ca) = (a.midpoint(&b), b.midpoint(&c), c.midpoint(&a));
+        [
+            self.with_corners(a, ab, ca),
+            self.with_corners(ab, b, bc),
+            self.with_corners(ca, bc, c),
+            self.with_corners(ab, bc, ca),
+        ]
+    }
+
+    fn corner(&self, i: usize) -> Corner {
+        match i {
+            0 => Corner { position: self.a, texcoord: self.ta, normal: self.na, alpha: self.alpha_a, world: self.wa },
+            1 => Corner { position: self.b, texcoord: self.tb, normal: self.nb, alpha: self.alpha_b, world: self.wb },
+            _ => Corner { position: self.c, texcoord: self.tc, normal: self.nc, alpha: self.alpha_c, world: self.wc },
+        }
+    }
+
+    fn with_corners(&self, a: Corner, b: Corner, c: Corner) -> Triangle3D {
+        Triangle3D {
+            a: a.position, b: b.position, c: c.position,
+            ta: a.texcoord, tb: b.texcoord, tc: c.texcoord,
+            na: a.normal, nb: b.normal, nc: c.normal,
+            alpha_a: a.alpha, alpha_b: b.alpha, alpha_c: c.alpha,
+            wa: a.world, wb: b.world, wc: c.world,
+            ..*self
+        }
+    }
+}
+
+// One vertex's attributes, for building subdivided triangles
+#[derive(Clone, Copy)]
+struct Corner {
+    position: Point3D,
+    texcoord: Point2D,
+    normal: Point3D,
+    alpha: f32,
+    world: Point3D,
+}
+
+impl Corner {
+    fn midpoint(&self, other: &Corner) -> Corner {
+        Corner {
+            position: point3d::lerp(self.position, other.position, 0.5),
+            texcoord: (self.texcoord + other.texcoord) * 0.5,
+            normal: point3d::lerp(self.normal, other.normal, 0.5),
+            alpha: (self.alpha + other.alpha) * 0.5,
+            world: point3d::lerp(self.world, other.world, 0.5),
+        }
+    }
+}
+
+/// Deterministic, well spread color for a triangle id, neighboring ids get unrelated colors
+pub fn id_color(id: u32) -> (u8, u8, u8) {
+    // Murmur3's finalizer, every input bit affects every output bit
+    let mut h = id;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    let [r, g, b, _] = h.to_le_bytes();
+    (r, g, b)
+}
+
+/// How an ordered vertex list is assembled into triangles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    // Every three vertices are a separate triangle
+    List,
+    // Each vertex after the first two makes a triangle with the two before it
+    Strip,
+    // Each vertex after the first two makes a triangle with the one before it and the first
+    Fan,
+}
+
+/// Expand a primitive into standalone triangles that all share the winding of the first one.
+/// In a strip every other triangle would otherwise come out reversed, so those have their first two vertices swapped.
+/// Leftover vertices that don't complete a triangle are ignored, degenerate triangles (repeated vertices stitching strips) are kept
+pub fn assemble(vertices: &[Vertex], topology: Topology, material: usize) -> Vec<Triangle3D> {
+    if vertices.len() < 3 {
+        return Vec::new();
+    }
+    match topology {
+        Topology::List => vertices
+            .chunks_exact(3)
+            .map(|tri| Triangle3D::from_vertices(tri[0], tri[1], tri[2], material))
+            .collect(),
+        Topology::Strip => vertices
+            .windows(3)
+            .enumerate()
+            .map(|(i, tri)| {
+                if i % 2 == 0 {
+                    Triangle3D::from_vertices(tri[0], tri[1], tri[2], material)
+                } else {
+                    Triangle3D::from_vertices(tri[1], tri[0], tri[2], material)
+                }
+            })
+            .collect(),
+        Topology::Fan => vertices[1..]
+            .windows(2)
+            .map(|pair| Triangle3D::from_vertices(vertices[0], pair[0], pair[1], material))
+            .collect(),
+    }
+}
+
+/// Preprocess away enormous triangles (a ground plane as two triangles) that defeat per-tile rejection,
+/// anything larger than `max_area` is split into quarters until every piece fits
+pub fn split_large_triangles(triangles: &[Triangle3D], max_area: f32) -> Vec<Triangle3D> {
+    if max_area <= 0.0 {
+        return triangles.to_vec();
+    }
+    let mut result = Vec::with_capacity(triangles.len());
+    let mut pending: Vec<Triangle3D> = Vec::new();
+    for tri in triangles {
+        pending.push(*tri);
+        while let Some(piece) = pending.pop() {
+            // Degenerate or non-finite areas would never shrink below the limit
+            if piece.area() > max_area && piece.area().is_finite() {
+                pending.extend(piece.subdivide());
+            } else {
+                result.push(piece);
+            }
+        }
+    }
+    result
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -27,3 +207,64 @@ pub struct Triangle2D {
     pub a: Point2D,
     pub b: Point2D,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ground plane corner with texcoords running 0 to 1 over the 8 unit span
+    fn ground_vertex(x: f32, z: f32) -> Vertex {
+        Vertex {
+            position: Point3D { x, y: 0.0, z },
+            texcoord: Point2D { x: x / 8.0, y: z / 8.0 },
+            normal: Point3D { x: 0.0, y: 1.0, z: 0.0 },
+            alpha: 1.0,
+        }
+    }
+
+    #[test]
+    fn split_pieces_tile_the_original_with_interpolated_uvs() {
+        let original = Triangle3D::from_vertices(ground_vertex(0.0, 0.0), ground_vertex(0.0, 8.0), ground_vertex(8.0, 0.0), 3);
+        let winding = point3d::cross3(original.b - original.a, original.c - original.a);
+        // Area 32 quarters twice to reach 2
+        let pieces = split_large_triangles(&[original], 2.0);
+        assert_eq!(pieces.len(), 16);
+        assert!((pieces.iter().map(Triangle3D::area).sum::<f32>() - original.area()).abs() < 1e-4);
+
+        for piece in &pieces {
+            assert!(piece.area() <= 2.0);
+            assert_eq!(piece.material, 3);
+            // Same facing as the original, so none end up culled
+            assert!(point3d::dot3(point3d::cross3(piece.b - piece.a, piece.c - piece.a), winding) > 0.0);
+            for (position, texcoord, world) in [(piece.a, piece.ta, piece.wa), (piece.b, piece.tb, piece.wb), (piece.c, piece.tc, piece.wc)] {
+                assert_eq!(texcoord, Point2D { x: position.x / 8.0, y: position.z / 8.0 });
+                assert_eq!(world, position);
+                assert!(position.x >= 0.0 && position.z >= 0.0 && position.x + position.z <= 8.0);
+            }
+        }
+    }
+
+    #[test]
+    fn small_triangles_and_disabled_splitting_pass_through() {
+        let tri = Triangle3D::from_vertices(ground_vertex(0.0, 0.0), ground_vertex(0.0, 1.0), ground_vertex(1.0, 0.0), 0);
+        assert_eq!(split_large_triangles(&[tri], 2.0), [tri]);
+        assert_eq!(split_large_triangles(&[tri], 0.0), [tri]);
+    }
+
+    #[test]
+    fn five_vertex_strip_expands_to_three_triangles_with_one_winding() {
+        // Zigzag along x: even vertices on z = 0, odd ones on z = 1
+        let strip: Vec<Vertex> = (0..5).map(|i| ground_vertex(i as f32 * 0.5, (i % 2) as f32)).collect();
+        let triangles = assemble(&strip, Topology::Strip, 1);
+        assert_eq!(triangles.len(), 3);
+        // The middle triangle has its first two vertices swapped
+        let expected = [[0, 1, 2], [2, 1, 3], [2, 3, 4]];
+        let winding = point3d::cross3(triangles[0].b - triangles[0].a, triangles[0].c - triangles[0].a);
+        assert!(point3d::dot3(winding, winding) > 0.0);
+        for (tri, indices) in triangles.iter().zip(expected) {
+            assert_eq!([tri.a, tri.b, tri.c], indices.map(|i| strip[i].position));
+            assert_eq!(tri.material, 1);
+            assert!(point3d::dot3(point3d::cross3(tri.b - tri.a, tri.c - tri.a), winding) > 0.0);
+        }
+    }
+}
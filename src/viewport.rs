@@ -0,0 +1,64 @@
+use crate::point2d::Point2D;
+
+/// How the rendered frame is scaled into a window of a different size
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ViewportFit {
+    // Fill the window, distorting the image when the aspect ratios differ
+    Stretch,
+    // Largest undistorted fit, centered with black bars on the sides or top and bottom
+    #[default]
+    Letterbox,
+}
+
+/// Where the frame lands in the window, in window pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub origin: Point2D,
+    pub size: Point2D,
+}
+
+impl Viewport {
+    pub fn fit(resolution: Point2D, window: Point2D, fit: ViewportFit) -> Self {
+        match fit {
+            ViewportFit::Stretch => Self { origin: Point2D { x: 0.0, y: 0.0 }, size: window },
+            ViewportFit::Letterbox => {
+                let scale = (window.x / resolution.x).min(window.y / resolution.y);
+                let size = Point2D { x: resolution.x * scale, y: resolution.y * scale };
+                Self { origin: Point2D { x: (window.x - size.x) * 0.5, y: (window.y - size.y) * 0.5 }, size }
+            }
+        }
+    }
+
+    /// Map a window position (e.g. the cursor) to render pixels, None over the bars.
+    /// The far edges are excluded so the result always truncates to a pixel inside the frame
+    pub fn to_render(&self, window_pos: Point2D, resolution: Point2D) -> Option<Point2D> {
+        let x = (window_pos.x - self.origin.x) / self.size.x;
+        let y = (window_pos.y - self.origin.y) / self.size.y;
+        ((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y)).then_some(Point2D { x: x * resolution.x, y: y * resolution.y })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESOLUTION: Point2D = Point2D { x: 1920.0, y: 1080.0 };
+
+    #[test]
+    fn letterbox_centers_the_largest_undistorted_fit() {
+        // A square window gets bars above and below, the width limits the scale to a half
+        let square = Viewport::fit(RESOLUTION, Point2D { x: 960.0, y: 960.0 }, ViewportFit::Letterbox);
+        assert_eq!(square, Viewport { origin: Point2D { x: 0.0, y: 210.0 }, size: Point2D { x: 960.0, y: 540.0 } });
+        // A wide window gets bars on the sides
+        let wide = Viewport::fit(RESOLUTION, Point2D { x: 3000.0, y: 540.0 }, ViewportFit::Letterbox);
+        assert_eq!(wide, Viewport { origin: Point2D { x: 1020.0, y: 0.0 }, size: Point2D { x: 960.0, y: 540.0 } });
+        let stretched = Viewport::fit(RESOLUTION, Point2D { x: 960.0, y: 960.0 }, ViewportFit::Stretch);
+        assert_eq!(stretched.size, Point2D { x: 960.0, y: 960.0 });
+
+        // The window center is the frame center, the bars and the far edge aren't in the frame
+        assert_eq!(square.to_render(Point2D { x: 480.0, y: 480.0 }, RESOLUTION), Some(Point2D { x: 960.0, y: 540.0 }));
+        assert_eq!(square.to_render(Point2D { x: 0.0, y: 210.0 }, RESOLUTION), Some(Point2D { x: 0.0, y: 0.0 }));
+        assert_eq!(square.to_render(Point2D { x: 480.0, y: 100.0 }, RESOLUTION), None);
+        assert_eq!(square.to_render(Point2D { x: 960.0, y: 480.0 }, RESOLUTION), None);
+    }
+}